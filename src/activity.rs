@@ -0,0 +1,126 @@
+//! When the games are played, by hour of the day and day of the week
+
+use std::time::Duration;
+
+use chrono::{Datelike, Timelike};
+use plotters::{coord::Shift, prelude::*};
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    plot::{DrawResult, Figure, Theme},
+};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Real time of the games by weekday, from Monday, and hour of their start, in the timezone
+/// of the analysis. Games without a start are ignored
+#[derive(Default, Debug)]
+pub struct Activity([[Duration; 24]; 7]);
+
+impl Activity {
+    pub fn get(&self, weekday: chrono::Weekday, hour: u32) -> Duration {
+        self.0[weekday.num_days_from_monday() as usize][hour as usize]
+    }
+}
+
+impl Aggregator for Activity {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if let Some(start) = game.start {
+            let weekday = start.weekday().num_days_from_monday() as usize;
+            self.0[weekday][start.hour() as usize] += game.exact_duration;
+        }
+    }
+}
+
+impl Figure for Activity {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let max = self
+            .0
+            .iter()
+            .flatten()
+            .max()
+            .copied()
+            .unwrap_or_default()
+            .as_secs_f64()
+            .max(1.);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Real time played by hour and weekday", theme.caption(30))
+            .margin(20)
+            .x_label_area_size(50)
+            .y_label_area_size(60)
+            // rows centered on the weekday labels
+            .build_cartesian_2d(0_f64..24., -0.5..6.5)?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .disable_mesh()
+            .x_desc("hour of the start")
+            .x_labels(13)
+            .y_labels(7)
+            // Monday at the top
+            .y_label_formatter(&|y: &f64| {
+                let weekday = 6 - y.round() as i64;
+                usize::try_from(weekday)
+                    .ok()
+                    .and_then(|weekday| WEEKDAYS.get(weekday))
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .draw()?;
+        chart.draw_series(self.0.iter().enumerate().flat_map(|(weekday, hours)| {
+            hours.iter().enumerate().map(move |(hour, time)| {
+                let (x, y) = (hour as f64, (6 - weekday) as f64);
+                let color = ViridisRGB.get_color((time.as_secs_f64() / max) as f32);
+                Rectangle::new([(x, y - 0.5), (x + 1., y + 0.5)], color.filled())
+            })
+        }))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Weekday;
+
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    fn game(date: &str, time: &str) -> String {
+        format!(
+            r#"[Event "Rated Blitz game"]
+[UTCDate "{date}"]
+[UTCTime "{time}"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+
+1. e4 {{ [%clk 0:03:00] }} 1... e5 {{ [%clk 0:03:00] }} 2. Nf3 {{ [%clk 0:02:00] }} 2... Nc6 {{ [%clk 0:02:00] }} 1-0
+
+"#
+        )
+    }
+
+    #[test]
+    fn test_activity() {
+        let pgn = [
+            game("2023.01.31", "12:00:00"),
+            game("2023.01.31", "12:59:00"),
+            game("2023.02.05", "23:30:00"),
+        ]
+        .concat();
+        let mut activity = Activity::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut activity])
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        let game = activity.get(Weekday::Sun, 23);
+        assert!(!game.is_zero());
+        assert_eq!(activity.get(Weekday::Tue, 12), game * 2);
+        assert_eq!(activity.get(Weekday::Tue, 13), Duration::ZERO);
+    }
+}
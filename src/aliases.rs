@@ -0,0 +1,179 @@
+//! Merging alternative accounts into their main one
+
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    results::{escape, CsvOptions},
+    visitor::TimeSpents,
+};
+
+/// alt username -> main username
+pub type Aliases = FxHashMap<String, String>;
+
+/// Load a `main,alt` csv file, one alias per line. A `main,alt` header is allowed.
+pub fn load(path: &str) -> io::Result<Aliases> {
+    let content = fs::read_to_string(path)?;
+    let mut aliases = Aliases::default();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line == "main,alt") {
+            continue;
+        }
+        let (main, alt) = line.split_once(',').ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{path}:{}: expected `main,alt`, got {line:?}", i + 1),
+            )
+        })?;
+        aliases.insert(alt.trim().to_string(), main.trim().to_string());
+    }
+    Ok(aliases)
+}
+
+/// Contribution of an alt account to its main one
+#[derive(Debug)]
+pub struct Contribution {
+    pub main: String,
+    pub alt: String,
+    pub time_spents: TimeSpents,
+}
+
+/// Fold the alt accounts of `users` into their main account, returning what each alt contributed
+pub fn merge(users: &mut FxHashMap<String, TimeSpents>, aliases: &Aliases) -> Vec<Contribution> {
+    let mut contributions = Vec::new();
+    for (alt, main) in aliases {
+        if alt == main {
+            continue;
+        }
+        if let Some(time_spents) = users.remove(alt) {
+            *users.entry(main.clone()).or_default() += time_spents.clone();
+            contributions.push(Contribution {
+                main: main.clone(),
+                alt: alt.clone(),
+                time_spents,
+            });
+        }
+    }
+    contributions.sort_by(|a, b| (&a.main, &a.alt).cmp(&(&b.main, &b.alt)));
+    contributions
+}
+
+pub fn write_csv(
+    w: &mut impl Write,
+    contributions: &[Contribution],
+    options: &CsvOptions,
+) -> io::Result<()> {
+    let d = options.delimiter.as_char();
+    write!(w, "username{d}alias")?;
+    TimeSpents::write_csv_header(w, options)?;
+    writeln!(w)?;
+    for c in contributions {
+        write!(w, "{}{d}{}", escape(&c.main, d), escape(&c.alt, d))?;
+        c.time_spents.to_csv(w, options, None)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        columns::Column,
+        results,
+        visitor::{Perf, Rating},
+    };
+
+    fn csv_row(time_spents: &TimeSpents) -> String {
+        let mut w = Vec::new();
+        time_spents
+            .to_csv(&mut w, &CsvOptions::default(), None)
+            .unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn prop_csv_round_trip(
+            contributions in proptest::collection::vec(
+                ("[a-z]{2,10}", "[a-z]{2,10}", results::tests::time_spents()),
+                0..10,
+            )
+        ) {
+            let contributions: Vec<Contribution> = contributions
+                .into_iter()
+                .map(|(main, alt, time_spents)| Contribution { main, alt, time_spents })
+                .collect();
+            let mut w = Vec::new();
+            write_csv(&mut w, &contributions, &CsvOptions::default()).unwrap();
+            let csv = String::from_utf8(w).unwrap();
+            let mut lines = csv.lines();
+            let header: Vec<_> = lines
+                .next()
+                .unwrap()
+                .split(',')
+                .skip(2)
+                .map(|name| Column::parse_header(name).unwrap())
+                .collect();
+            let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+            prop_assert_eq!(rows.len(), contributions.len());
+            for (row, c) in rows.iter().zip(&contributions) {
+                prop_assert_eq!(row[0], &c.main);
+                prop_assert_eq!(row[1], &c.alt);
+                prop_assert_eq!(
+                    TimeSpents::from_csv(&header, &row[2..]),
+                    Some(c.time_spents.clone())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_alt_into_main() {
+        let mut users = FxHashMap::default();
+        let mut main = TimeSpents::default();
+        main.add_game(
+            Perf::Bullet,
+            Duration::from_secs(100),
+            Duration::ZERO,
+            60,
+            Rating(1500),
+            true,
+        );
+        users.insert("main".to_string(), main);
+        let mut alt = TimeSpents::default();
+        alt.add_game(
+            Perf::Bullet,
+            Duration::from_secs(50),
+            Duration::ZERO,
+            60,
+            Rating(1700),
+            true,
+        );
+        users.insert("alt".to_string(), alt);
+        let aliases = Aliases::from_iter([("alt".to_string(), "main".to_string())]);
+
+        let contributions = merge(&mut users, &aliases);
+        assert_eq!(users.len(), 1);
+        assert_eq!(
+            csv_row(&users["main"]),
+            ",,,,,,,,,,,,,,2,1600,120,150,0,0,0,0,0,2,150,0,0,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,"
+        );
+        assert_eq!(users["main"].as_white().nb_games, 2);
+        assert_eq!(contributions.len(), 1);
+        assert_eq!(contributions[0].alt, "alt");
+        assert_eq!(
+            csv_row(&contributions[0].time_spents),
+            ",,,,,,,,,,,,,,1,1700,60,50,0,0,0,0,0,1,50,0,0,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,"
+        );
+    }
+}
@@ -0,0 +1,836 @@
+//! Configuring and running an analysis over a pgn
+
+use std::{
+    io::{self, Read},
+    ops::RangeInclusive,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+use indicatif::ProgressBar;
+use pgn_reader::BufferedReader;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    endings::Ending,
+    openings::EcoNames,
+    skips::SkipReason,
+    terminations::TerminationHeader,
+    timestamp::Zone,
+    usernames::Usernames,
+    variants::Variant,
+    visitor::{Perf, PgnVisitor, Player, Players, Score, Tc, Users},
+};
+
+/// Upper bounds (inclusive) of the estimated game duration, in seconds,
+/// of each perf but classical which has none
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "[usize; 4]")]
+pub struct Buckets([usize; 4]);
+
+impl Buckets {
+    pub fn new(ultrabullet: usize, bullet: usize, blitz: usize, rapid: usize) -> Self {
+        Self::try_from([ultrabullet, bullet, blitz, rapid]).unwrap()
+    }
+
+    /// Upper bounds of the ultrabullet, bullet, blitz and rapid average game durations
+    pub fn bounds(&self) -> [usize; 4] {
+        self.0
+    }
+
+    pub fn perf(&self, avg_time: usize) -> Perf {
+        self.0
+            .iter()
+            .position(|bound| avg_time <= *bound)
+            .map_or(Perf::Classical, |i| Perf::ALL[i])
+    }
+}
+
+impl TryFrom<[usize; 4]> for Buckets {
+    type Error = String;
+
+    fn try_from(bounds: [usize; 4]) -> Result<Self, Self::Error> {
+        if bounds.windows(2).all(|w| w[0] < w[1]) {
+            Ok(Self(bounds))
+        } else {
+            Err(format!("bucket bounds must be increasing, got {bounds:?}"))
+        }
+    }
+}
+
+/// `ultrabullet,bullet,blitz,rapid` upper bounds
+impl FromStr for Buckets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bounds: Vec<usize> = s
+            .split(',')
+            .map(|bound| bound.trim().parse().map_err(|e| format!("{bound:?}: {e}")))
+            .collect::<Result<_, _>>()?;
+        let bounds: [usize; 4] = bounds
+            .try_into()
+            .map_err(|v: Vec<usize>| format!("expected 4 bounds, got {}", v.len()))?;
+        Self::try_from(bounds)
+    }
+}
+
+impl Default for Buckets {
+    /// https://lichess.org/faq#time-controls
+    fn default() -> Self {
+        Self::new(29, 179, 479, 1499)
+    }
+}
+
+/// Thinking time of a correspondence move, unless configured
+pub const DEFAULT_CORRESPONDENCE_MOVE: Duration = Duration::from_secs(60);
+
+/// How the time of a game is credited to its players
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Attribution {
+    /// Both players are credited the whole game duration
+    #[default]
+    #[value(name = "game_duration")]
+    GameDuration,
+    /// Each player is credited the time spent on their own moves, from their own clocks
+    /// and increments
+    #[value(name = "thinking_time")]
+    ThinkingTime,
+}
+
+/// A game taken into account by the analysis
+#[derive(Debug)]
+pub struct CountedGame<'g> {
+    pub white: &'g Player,
+    pub black: &'g Player,
+    pub link: &'g str,
+    pub tc: Tc,
+    pub perf: Perf,
+    pub variant: Variant,
+    pub plies: u64,
+    pub exact_duration: Duration,
+    /// in seconds, from `base + 40 * increment` adjusted for berserk, or the estimated move
+    /// time of correspondence games
+    pub approximate_duration: usize,
+    /// whether each player berserked, white first
+    pub berserks: [bool; 2],
+    /// start of the game in the configured timezone, if the `UTCDate` and `UTCTime` headers are present
+    pub start: Option<NaiveDateTime>,
+    /// estimated time taken by the first move of each player, before the clocks start
+    pub setup_duration: Duration,
+    /// both clocks rose at some point by more than the increment and a +15s press,
+    /// typical of server lag
+    pub clock_anomaly: bool,
+    /// without clocks, as in older dumps, the exact duration only counts the increments
+    pub has_clocks: bool,
+    /// there are clocks, but not one per ply: the plies are likely miscounted, which breaks
+    /// the increment part of the duration
+    pub clock_mismatch: bool,
+    /// set for decisive games ended by resignation, checkmate or flag
+    pub ending: Option<Ending>,
+    /// result of each player, white first, none for unfinished games
+    pub scores: Option<[Score; 2]>,
+    /// from the `Termination` header, whatever the result
+    pub termination: Option<TerminationHeader>,
+    /// family of the `Opening` header, else the `ECO` code, empty without either
+    pub opening: &'g str,
+    /// clock after each ply, only kept with `keep_clocks`
+    pub clocks: &'g [Duration],
+}
+
+/// Additional statistics computed alongside the per-user time spent
+pub trait Aggregator {
+    fn add_game(&mut self, game: &CountedGame<'_>);
+
+    /// Called for every game not counted, `link` being empty without a `Site` header
+    fn skip_game(&mut self, _link: &str, _reason: SkipReason) {}
+
+    /// A game of a variant, only with `AnalysisBuilder::variants`
+    fn add_variant_game(&mut self, _game: &CountedGame<'_>) {}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// games where both players are outside the range are skipped
+    pub rating: Option<RangeInclusive<usize>>,
+    pub buckets: Buckets,
+    pub attribution: Attribution,
+    /// timezone of every date-based grouping
+    pub zone: Zone,
+    /// games longer than this are deemed pathological, reported and skipped
+    pub max_plies: Option<u64>,
+    /// opening family of the games without an `Opening` header
+    pub eco_names: EcoNames,
+    /// games whose clocks do not match their plies are skipped, instead of being counted and
+    /// reported through `CountedGame::clock_mismatch`
+    pub skip_clock_mismatches: bool,
+    /// only games involving these users are counted, and only them are credited
+    pub users: Option<Usernames>,
+    /// the games of other users are skipped as soon as their players are read, without
+    /// decoding their remaining headers, so they are all reported as filtered by the users
+    pub fast: bool,
+    /// never credited, games where both players are excluded are skipped
+    pub excluded: Usernames,
+    /// inclusive bounds of the game start date, in the configured timezone
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    /// games of other perfs are skipped
+    pub perfs: Option<Vec<Perf>>,
+    /// only count rated games if `true`, casual ones if `false`, per the `Event` header
+    pub rated: Option<bool>,
+    /// skip the games involving a BOT account, whose side is otherwise just not credited
+    pub no_bots: bool,
+    /// only count each game with a probability of one out of this many, from a hash of its link
+    pub sample: Option<usize>,
+    /// clock comments are not read, for dumps without them: the exact and setup times stay at zero
+    pub approx_only: bool,
+    /// every clock of the counted games is kept, for `CountedGame::clocks`
+    pub keep_clocks: bool,
+    /// estimated thinking time of each move of the correspondence games, which have no clocks
+    /// [default: `DEFAULT_CORRESPONDENCE_MOVE`]
+    pub correspondence_move: Option<Duration>,
+    /// games of variants are counted apart, through `Aggregator::add_variant_game`,
+    /// instead of being skipped
+    pub variants: bool,
+}
+
+impl Config {
+    pub(crate) fn is_tracked(&self, username: &str) -> bool {
+        self.users
+            .as_ref()
+            .is_none_or(|users| users.contains(username))
+    }
+
+    pub(crate) fn perf_selected(&self, perf: Perf) -> bool {
+        self.perfs
+            .as_ref()
+            .is_none_or(|perfs| perfs.contains(&perf))
+    }
+
+    pub(crate) fn correspondence_move(&self) -> Duration {
+        self.correspondence_move
+            .unwrap_or(DEFAULT_CORRESPONDENCE_MOVE)
+    }
+
+    /// Games without a date are out of any range
+    pub(crate) fn date_in_range(&self, date: Option<NaiveDate>) -> bool {
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        date.is_some_and(|date| {
+            self.from.is_none_or(|from| from <= date) && self.to.is_none_or(|to| date <= to)
+        })
+    }
+
+    pub(crate) fn rating_in_range(&self, players: &Players) -> bool {
+        self.rating.as_ref().is_none_or(|range| {
+            range.contains(&players.white.rating.0) || range.contains(&players.black.rating.0)
+        })
+    }
+}
+
+/// ```no_run
+/// use username_time_spent::analysis::{AnalysisBuilder, Attribution, Buckets};
+///
+/// let users = AnalysisBuilder::new()
+///     .filter_rating(2200..=4000)
+///     .buckets(Buckets::default())
+///     .attribution(Attribution::GameDuration)
+///     .build()
+///     .run(std::io::stdin())?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Default)]
+pub struct AnalysisBuilder<'a> {
+    config: Config,
+    aggregators: Vec<&'a mut dyn Aggregator>,
+    pb: Option<(ProgressBar, bool)>,
+    max_games: Option<usize>,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl<'a> AnalysisBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn filter_rating(mut self, range: RangeInclusive<usize>) -> Self {
+        self.config.rating = Some(range);
+        self
+    }
+
+    pub fn buckets(mut self, buckets: Buckets) -> Self {
+        self.config.buckets = buckets;
+        self
+    }
+
+    pub fn attribution(mut self, attribution: Attribution) -> Self {
+        self.config.attribution = attribution;
+        self
+    }
+
+    pub fn timezone(mut self, zone: Zone) -> Self {
+        self.config.zone = zone;
+        self
+    }
+
+    pub fn users(mut self, users: Usernames) -> Self {
+        self.config.users = Some(users);
+        self
+    }
+
+    /// Name the openings of the games without an `Opening` header from these ECO families
+    /// instead of the bundled ones only
+    pub fn eco_names(mut self, names: EcoNames) -> Self {
+        self.config.eco_names = names;
+        self
+    }
+
+    /// Do not decode the headers following `White` and `Black` in the games of untracked users,
+    /// whose moves are skipped either way
+    pub fn fast(mut self) -> Self {
+        self.config.fast = true;
+        self
+    }
+
+    /// Skip the games started before `date`
+    pub fn from(mut self, date: NaiveDate) -> Self {
+        self.config.from = Some(date);
+        self
+    }
+
+    /// Skip the games started after `date`
+    pub fn to(mut self, date: NaiveDate) -> Self {
+        self.config.to = Some(date);
+        self
+    }
+
+    /// Stop reading the pgn after `max_games` games, counted or not
+    pub fn max_games(mut self, max_games: usize) -> Self {
+        self.max_games = Some(max_games);
+        self
+    }
+
+    /// Stop reading the pgn once `stop` is set, typically on Ctrl-C, after finishing the current game
+    pub fn stop_on(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Only count each game with a probability of `1 / n`, from a hash of its `Site` link,
+    /// the games without one being counted every `n`th
+    pub fn sample(mut self, n: usize) -> Self {
+        self.config.sample = Some(n);
+        self
+    }
+
+    /// Only compute the approximate time, ignoring the clocks
+    pub fn approx_only(mut self) -> Self {
+        self.config.approx_only = true;
+        self
+    }
+
+    /// Keep every clock of the games, for the aggregators needing the time of each move
+    pub fn keep_clocks(mut self) -> Self {
+        self.config.keep_clocks = true;
+        self
+    }
+
+    /// Estimate the moves of the correspondence games to take `move_time` each
+    pub fn correspondence_move(mut self, move_time: Duration) -> Self {
+        self.config.correspondence_move = Some(move_time);
+        self
+    }
+
+    /// Count the games of variants apart, instead of skipping them. They are never in the
+    /// per-user results, only given to `Aggregator::add_variant_game`
+    pub fn variants(mut self) -> Self {
+        self.config.variants = true;
+        self
+    }
+
+    pub fn no_bots(mut self) -> Self {
+        self.config.no_bots = true;
+        self
+    }
+
+    pub fn rated(mut self, rated: bool) -> Self {
+        self.config.rated = Some(rated);
+        self
+    }
+
+    pub fn perfs(mut self, perfs: Vec<Perf>) -> Self {
+        self.config.perfs = Some(perfs);
+        self
+    }
+
+    pub fn exclude(mut self, excluded: Usernames) -> Self {
+        self.config.excluded = excluded;
+        self
+    }
+
+    pub fn max_plies(mut self, max_plies: u64) -> Self {
+        self.config.max_plies = Some(max_plies);
+        self
+    }
+
+    /// See `Config::skip_clock_mismatches`
+    pub fn skip_clock_mismatches(mut self) -> Self {
+        self.config.skip_clock_mismatches = true;
+        self
+    }
+
+    pub fn aggregators(mut self, aggregators: Vec<&'a mut dyn Aggregator>) -> Self {
+        self.aggregators = aggregators;
+        self
+    }
+
+    /// If `by_games` the progress bar is advanced for each game,
+    /// otherwise it is expected to be driven by the bytes read
+    pub fn progress_bar(mut self, pb: ProgressBar, by_games: bool) -> Self {
+        self.pb = Some((pb, by_games));
+        self
+    }
+
+    pub fn build(self) -> Analysis<'a> {
+        let (pb, by_games) = self.pb.unwrap_or((ProgressBar::hidden(), false));
+        Analysis {
+            visitor: PgnVisitor::new(pb, by_games, self.config, self.aggregators),
+            max_games: self.max_games,
+            stop: self.stop,
+        }
+    }
+}
+
+pub struct Analysis<'a> {
+    visitor: PgnVisitor<'a>,
+    max_games: Option<usize>,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl Analysis<'_> {
+    /// Run the analysis over an uncompressed pgn
+    pub fn run(mut self, reader: impl Read) -> io::Result<Users> {
+        let mut reader = BufferedReader::new(reader);
+        let max_games = self.max_games.unwrap_or(usize::MAX);
+        while self.visitor.games < max_games
+            && !self
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.load(Ordering::Relaxed))
+            && reader.read_game(&mut self.visitor)?.is_some()
+        {}
+        self.visitor.pb.finish();
+        info!(
+            games = self.visitor.games,
+            users = self.visitor.users.len(),
+            "pgn read"
+        );
+        Ok(self.visitor.users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skips::{Filter, ParseErrorKind};
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[Site "https://lichess.org/aaaaaaaa"]
+[UTCDate "2023.01.31"]
+[UTCTime "23:30:00"]
+[White "alice"]
+[Black "bob"]
+[WhiteElo "2300"]
+[BlackElo "1500"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Bullet game"]
+[Site "https://lichess.org/bbbbbbbb"]
+[White "carol"]
+[Black "bob"]
+[WhiteElo "1400"]
+[BlackElo "1500"]
+[TimeControl "60+0"]
+
+1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 { [%clk 0:00:55] } 2... Nc6 { [%clk 0:00:50] } 0-1
+"#;
+
+    struct GameCounter(usize);
+
+    impl Aggregator for GameCounter {
+        fn add_game(&mut self, _: &CountedGame<'_>) {
+            self.0 += 1
+        }
+    }
+
+    #[derive(Default)]
+    struct Starts(Vec<Option<NaiveDateTime>>);
+
+    impl Aggregator for Starts {
+        fn add_game(&mut self, game: &CountedGame<'_>) {
+            self.0.push(game.start)
+        }
+    }
+
+    #[test]
+    fn test_buckets() {
+        let buckets = Buckets::default();
+        assert_eq!(buckets.perf(15), Perf::UltraBullet);
+        assert_eq!(buckets.perf(179), Perf::Bullet);
+        assert_eq!(buckets.perf(180), Perf::Blitz);
+        assert_eq!(buckets.perf(5400), Perf::Classical);
+    }
+
+    #[test]
+    fn test_buckets_from_str() {
+        assert_eq!("30, 60,120,179".parse(), Ok(Buckets::new(30, 60, 120, 179)));
+        assert!("30,60,120".parse::<Buckets>().is_err());
+        assert!("60,30,120,179".parse::<Buckets>().is_err());
+    }
+
+    #[test]
+    fn test_builder_filter_rating() {
+        let mut counter = GameCounter(0);
+        let users = AnalysisBuilder::new()
+            .filter_rating(2000..=4000)
+            .aggregators(vec![&mut counter])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(counter.0, 1);
+        assert_eq!(users.len(), 2);
+        assert_eq!(users["bob"].get(Perf::Blitz).nb_games, 1);
+        assert_eq!(users["bob"].get(Perf::Bullet).nb_games, 0);
+        assert_eq!(
+            users["alice"].get(Perf::Blitz).time_spent_exact,
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn test_builder_buckets() {
+        let users = AnalysisBuilder::new()
+            .buckets(Buckets::new(30, 60, 120, 179))
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(users["bob"].get(Perf::Bullet).nb_games, 1);
+        assert_eq!(users["bob"].get(Perf::Classical).nb_games, 1);
+    }
+
+    #[test]
+    fn test_builder_attribution() {
+        let users = AnalysisBuilder::new()
+            .attribution(Attribution::ThinkingTime)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let blitz = |username: &str| users[username].get(Perf::Blitz).time_spent_exact;
+        assert_eq!(blitz("alice"), Duration::from_secs(10));
+        assert_eq!(blitz("bob"), Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_builder_correspondence_move() {
+        let pgn = r#"[Event "Rated Correspondence game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "-"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0
+"#;
+        let users = AnalysisBuilder::new()
+            .correspondence_move(Duration::from_secs(600))
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        let alice = users["alice"].get(Perf::Correspondence);
+        assert_eq!(alice.nb_games, 1);
+        assert_eq!(alice.time_spent_exact, Duration::from_secs(5 * 600));
+        assert_eq!(alice.time_spent_approximate, 80 * 600);
+        let users = AnalysisBuilder::new()
+            .attribution(Attribution::ThinkingTime)
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        let bob = users["bob"].get(Perf::Correspondence);
+        assert_eq!(bob.time_spent_exact, 2 * DEFAULT_CORRESPONDENCE_MOVE);
+    }
+
+    #[test]
+    fn test_builder_users() {
+        let mut counter = GameCounter(0);
+        let users = AnalysisBuilder::new()
+            .users(["carol".to_string()].into_iter().collect())
+            .aggregators(vec![&mut counter])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(counter.0, 1);
+        assert_eq!(users.len(), 1);
+        assert_eq!(users["carol"].get(Perf::Bullet).nb_games, 1);
+    }
+
+    #[test]
+    fn test_builder_exclude() {
+        let mut counter = GameCounter(0);
+        let users = AnalysisBuilder::new()
+            .exclude(["alice", "bob"].map(str::to_string).into_iter().collect())
+            .aggregators(vec![&mut counter])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(counter.0, 1);
+        assert_eq!(users.len(), 1);
+        assert_eq!(users["carol"].get(Perf::Bullet).nb_games, 1);
+    }
+
+    #[test]
+    fn test_builder_dates() {
+        let date = NaiveDate::from_ymd_opt(2023, 1, 31).unwrap();
+        let users = AnalysisBuilder::new()
+            .from(date)
+            .to(date)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users["bob"].total_games(), 1);
+        // starting on the 1st of February in this timezone
+        let users = AnalysisBuilder::new()
+            .to(date)
+            .timezone("+01:00".parse().unwrap())
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert!(users.is_empty());
+    }
+
+    #[test]
+    fn test_builder_perfs() {
+        let users = AnalysisBuilder::new()
+            .perfs(vec![Perf::Bullet, Perf::Rapid])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users["bob"].total_games(), 1);
+        assert_eq!(users["carol"].get(Perf::Bullet).nb_games, 1);
+    }
+
+    #[test]
+    fn test_builder_rated() {
+        let casual = PGN.replacen("Rated Bullet", "Casual Bullet", 1);
+        let users = AnalysisBuilder::new()
+            .rated(true)
+            .build()
+            .run(casual.as_bytes())
+            .unwrap();
+        assert_eq!(users["bob"].get(Perf::Blitz).nb_games, 1);
+        assert_eq!(users["bob"].get(Perf::Bullet).nb_games, 0);
+        let users = AnalysisBuilder::new()
+            .rated(false)
+            .build()
+            .run(casual.as_bytes())
+            .unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users["bob"].get(Perf::Bullet).nb_games, 1);
+    }
+
+    #[test]
+    fn test_scores() {
+        let pgn = format!(
+            "{PGN}\n{}",
+            PGN.replace(" 1-0", " 1/2-1/2").replace(" 0-1", " *")
+        );
+        let users = AnalysisBuilder::new().build().run(pgn.as_bytes()).unwrap();
+        let blitz = users["bob"].get(Perf::Blitz);
+        assert_eq!((blitz.wins, blitz.draws, blitz.losses), (0, 1, 1));
+        // the unfinished game still counts
+        let bullet = users["bob"].get(Perf::Bullet);
+        assert_eq!(bullet.nb_games, 2);
+        assert_eq!((bullet.wins, bullet.draws, bullet.losses), (1, 0, 0));
+    }
+
+    #[test]
+    fn test_plies() {
+        let pgn = PGN.replacen(
+            " Nc6 { [%clk 0:02:40] } 1-0",
+            " Nc6 { [%clk 0:02:40] } 3. Bc4 { [%clk 0:02:40] } 1-0",
+            1,
+        );
+        let users = AnalysisBuilder::new().build().run(pgn.as_bytes()).unwrap();
+        assert_eq!(users["alice"].get(Perf::Blitz).total_plies, 5);
+        assert_eq!(users["bob"].get(Perf::Bullet).total_plies, 4);
+    }
+
+    #[test]
+    fn test_builder_no_bots() {
+        let with_bot = PGN.replacen(
+            "[White \"carol\"]",
+            "[White \"carol\"]\n[WhiteTitle \"BOT\"]",
+            1,
+        );
+        let users = AnalysisBuilder::new()
+            .build()
+            .run(with_bot.as_bytes())
+            .unwrap();
+        assert!(!users.contains_key("carol"));
+        assert_eq!(users["bob"].total_games(), 2);
+        let users = AnalysisBuilder::new()
+            .no_bots()
+            .build()
+            .run(with_bot.as_bytes())
+            .unwrap();
+        assert_eq!(users["bob"].total_games(), 1);
+    }
+
+    #[derive(Default)]
+    struct Skipped(Vec<SkipReason>);
+
+    impl Aggregator for Skipped {
+        fn add_game(&mut self, _: &CountedGame<'_>) {}
+
+        fn skip_game(&mut self, _link: &str, reason: SkipReason) {
+            self.0.push(reason)
+        }
+    }
+
+    #[test]
+    fn test_builder_fast() {
+        let pgn = PGN.replacen("180+0", "oops", 1);
+        let run = |fast: bool| {
+            let mut skipped = Skipped::default();
+            let mut builder = AnalysisBuilder::new();
+            if fast {
+                builder = builder.fast();
+            }
+            let users = builder
+                .users(["carol".to_string()].into_iter().collect())
+                .aggregators(vec![&mut skipped])
+                .build()
+                .run(pgn.as_bytes())
+                .unwrap();
+            assert_eq!(users["carol"].get(Perf::Bullet).nb_games, 1);
+            skipped.0
+        };
+        assert_eq!(
+            run(false),
+            [SkipReason::ParseError {
+                kind: ParseErrorKind::TimeControl
+            }]
+        );
+        // the time control of alice and bob is not even read
+        assert_eq!(
+            run(true),
+            [SkipReason::Filtered {
+                which: Filter::Users
+            }]
+        );
+    }
+
+    #[test]
+    fn test_builder_sample() {
+        let users = AnalysisBuilder::new()
+            .sample(2)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        // only the first link hashes to a multiple of 2
+        assert_eq!(users.len(), 2);
+        assert_eq!(users["alice"].total_games(), 1);
+        assert!(!users.contains_key("carol"));
+    }
+
+    #[test]
+    fn test_builder_approx_only() {
+        let users = AnalysisBuilder::new()
+            .approx_only()
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let blitz = users["alice"].get(Perf::Blitz);
+        assert_eq!(blitz.nb_games, 1);
+        assert_eq!(blitz.time_spent_approximate, 180);
+        assert_eq!(blitz.time_spent_exact, Duration::ZERO);
+        assert_eq!(blitz.setup_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_builder_max_games() {
+        let users = AnalysisBuilder::new()
+            .max_games(1)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(users.len(), 2);
+        assert!(!users.contains_key("carol"));
+    }
+
+    #[test]
+    fn test_builder_stop_on() {
+        let stop = Arc::new(AtomicBool::new(true));
+        let users = AnalysisBuilder::new()
+            .stop_on(Arc::clone(&stop))
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert!(users.is_empty());
+        stop.store(false, Ordering::Relaxed);
+        let users = AnalysisBuilder::new()
+            .stop_on(stop)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(users["bob"].total_games(), 2);
+    }
+
+    #[test]
+    fn test_builder_max_plies() {
+        let users = AnalysisBuilder::new()
+            .max_plies(3)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert!(users.is_empty());
+        let users = AnalysisBuilder::new()
+            .max_plies(4)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(users["bob"].total_games(), 2);
+    }
+
+    #[test]
+    fn test_builder_timezone() {
+        let mut starts = Starts::default();
+        AnalysisBuilder::new()
+            .timezone("+01:00".parse().unwrap())
+            .aggregators(vec![&mut starts])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(
+            starts.0,
+            vec![
+                Some(
+                    chrono::NaiveDate::from_ymd_opt(2023, 2, 1)
+                        .unwrap()
+                        .and_hms_opt(0, 30, 0)
+                        .unwrap()
+                ),
+                None
+            ]
+        );
+    }
+}
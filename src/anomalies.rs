@@ -0,0 +1,89 @@
+//! Daily counts of the games with clock anomalies, whose spikes correlate with server lag events
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use chrono::NaiveDate;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    results::Delimiter,
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Day {
+    pub games: u64,
+    pub anomalous_games: u64,
+}
+
+/// Among the counted games, those without a start date are ignored
+#[derive(Default, Debug)]
+pub struct ClockAnomalies(BTreeMap<NaiveDate, Day>);
+
+impl ClockAnomalies {
+    pub fn days(&self) -> &BTreeMap<NaiveDate, Day> {
+        &self.0
+    }
+
+    pub fn write_csv(&self, w: &mut impl Write, delimiter: Delimiter) -> io::Result<()> {
+        let d = delimiter.as_char();
+        writeln!(w, "date{d}games{d}anomalous_games")?;
+        for (date, day) in &self.0 {
+            writeln!(w, "{date}{d}{}{d}{}", day.games, day.anomalous_games)?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for ClockAnomalies {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if let Some(start) = game.start {
+            let day = self.0.entry(start.date()).or_default();
+            day.games += 1;
+            day.anomalous_games += u64::from(game.clock_anomaly);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[UTCDate "2023.01.31"]
+[UTCTime "12:00:00"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 3. Bc4 { [%clk 0:03:20] } 3... Bc5 { [%clk 0:03:10] } 4. O-O { [%clk 0:02:30] } 4... Nf6 { [%clk 0:02:20] } 1-0
+
+[Event "Rated Blitz game"]
+[UTCDate "2023.01.31"]
+[UTCTime "13:00:00"]
+[White "carol"]
+[Black "bob"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+"#;
+
+    #[test]
+    fn test_clock_anomalies() {
+        let mut anomalies = ClockAnomalies::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut anomalies])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let mut w = Vec::new();
+        anomalies.write_csv(&mut w, Delimiter::Comma).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "date,games,anomalous_games\n2023-01-31,2,1\n"
+        );
+    }
+}
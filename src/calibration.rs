@@ -0,0 +1,187 @@
+//! How far the `base + 40 * increment` approximation is from the real duration of the games,
+//! by time control
+
+use std::time::Duration;
+
+use plotters::{coord::Shift, prelude::*};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    plot::{DrawResult, Figure, Theme},
+    visitor::Tc,
+};
+
+/// Time controls drawn, the most played ones
+const MOST_PLAYED: usize = 20;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+struct Totals {
+    games: u64,
+    real: Duration,
+    /// in seconds
+    approximate: u64,
+}
+
+impl Totals {
+    fn ratio(&self) -> f64 {
+        self.real.as_secs_f64() / self.approximate as f64
+    }
+}
+
+// like lichess, `3+2` with the base in minutes, unless shorter
+fn label(tc: Tc) -> String {
+    if tc.base.is_multiple_of(60) {
+        format!("{}+{}", tc.base / 60, tc.increment)
+    } else {
+        format!("{}s+{}", tc.base, tc.increment)
+    }
+}
+
+/// Real and approximate time of the games with clocks, by time control
+#[derive(Default, Debug)]
+pub struct Calibration(FxHashMap<Tc, Totals>);
+
+impl Calibration {
+    /// Ratio of the real time to the approximate one of the games in `tc`, above 1 when the
+    /// approximation underestimates it
+    pub fn ratio(&self, tc: Tc) -> Option<f64> {
+        self.0.get(&tc).map(Totals::ratio)
+    }
+
+    /// The most played time controls, from the shortest approximate duration, and their ratio
+    fn most_played(&self) -> Vec<(Tc, f64)> {
+        let mut tcs: Vec<(Tc, Totals)> = self.0.iter().map(|(tc, t)| (*tc, *t)).collect();
+        tcs.sort_unstable_by(|(tc_a, a), (tc_b, b)| {
+            b.games
+                .cmp(&a.games)
+                .then((tc_a.base, tc_a.increment).cmp(&(tc_b.base, tc_b.increment)))
+        });
+        tcs.truncate(MOST_PLAYED);
+        tcs.sort_unstable_by_key(|(tc, _)| (tc.average_time(), tc.base));
+        tcs.into_iter().map(|(tc, t)| (tc, t.ratio())).collect()
+    }
+}
+
+impl Aggregator for Calibration {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        // without clocks the real duration is unknown, and `0+0` cannot be approximated
+        if !game.has_clocks || game.approximate_duration == 0 {
+            return;
+        }
+        let totals = self.0.entry(game.tc).or_default();
+        totals.games += 1;
+        totals.real += game.exact_duration;
+        totals.approximate += game.approximate_duration as u64;
+    }
+}
+
+impl Figure for Calibration {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let tcs = self.most_played();
+        let rows = tcs.len().max(1);
+        let (min, max) = tcs.iter().fold((1_f64, 1_f64), |(min, max), (_, r)| {
+            (min.min(*r), max.max(*r))
+        });
+        let margin = (max - min).max(0.1) * 0.1;
+        let mut chart = ChartBuilder::on(root)
+            .caption(
+                "Real time over its base + 40 × increment approximation",
+                theme.caption(30),
+            )
+            .margin(20)
+            .x_label_area_size(50)
+            .y_label_area_size(80)
+            // rows centered on the time controls
+            .build_cartesian_2d(min - margin..max + margin, -0.5..rows as f64 - 0.5)?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .disable_y_mesh()
+            .x_desc("real / approximate time")
+            .y_desc("time control")
+            .x_label_formatter(&|ratio| format!("{ratio:.2}"))
+            .y_labels(rows)
+            // the shortest at the top
+            .y_label_formatter(&|y: &f64| {
+                let row = rows as i64 - 1 - y.round() as i64;
+                usize::try_from(row)
+                    .ok()
+                    .filter(|_| (y - y.round()).abs() < 1e-6)
+                    .and_then(|row| tcs.get(row))
+                    .map_or(String::new(), |(tc, _)| label(*tc))
+            })
+            .draw()?;
+        // from 1, to the right when the approximation underestimates the real time
+        chart.draw_series(tcs.iter().enumerate().map(|(row, (_, ratio))| {
+            let y = (rows - 1 - row) as f64;
+            let color = Palette99::pick(usize::from(*ratio < 1.)).to_rgba();
+            Rectangle::new([(1., y - 0.4), (*ratio, y + 0.4)], color.filled())
+        }))?;
+        chart.draw_series(LineSeries::new(
+            [(1., -0.5), (1., rows as f64 - 0.5)],
+            theme.foreground().stroke_width(1),
+        ))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    fn game(tc: &str, clocks: &str) -> String {
+        format!(
+            r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "{tc}"]
+
+{clocks} 1-0
+
+"#
+        )
+    }
+
+    #[test]
+    fn test_calibration() {
+        let pgn = [
+            game(
+                "180+2",
+                "1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] }",
+            ),
+            game("180+2", "1. e4 e5 2. Nf3 Nc6"),
+            game(
+                "60+0",
+                "1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 { [%clk 0:00:50] } 2... Nc6 { [%clk 0:00:40] }",
+            ),
+        ]
+        .concat();
+        let mut calibration = Calibration::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut calibration])
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        // only the game with clocks, far shorter than its 180 + 40 * 2 = 260 seconds
+        assert_eq!(calibration.0[&Tc::new((180, 2))].games, 1);
+        assert!(calibration.ratio(Tc::new((180, 2))).unwrap() < 1.);
+        assert_eq!(calibration.ratio(Tc::new((300, 0))), None);
+        let most_played: Vec<_> = calibration
+            .most_played()
+            .into_iter()
+            .map(|(tc, _)| label(tc))
+            .collect();
+        assert_eq!(most_played, ["1+0", "3+2"]);
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label(Tc::new((15, 0))), "15s+0");
+        assert_eq!(label(Tc::new((600, 5))), "10+5");
+    }
+}
@@ -0,0 +1,124 @@
+//! Loading the results into ClickHouse over its HTTP interface, one row per user and perf played
+//! like the postgres
+
+use std::{
+    io::{self, BufWriter, Write},
+    thread,
+};
+
+use crate::{pg, sql, visitor::TimeSpents};
+
+fn create_table(table: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+    username String,
+    perf LowCardinality(String),
+    {}
+) ENGINE = MergeTree ORDER BY (username, perf)",
+        sql::column_definitions("UInt64")
+    )
+}
+
+fn query(url: &str, query: &str, body: impl io::Read) -> io::Result<()> {
+    match ureq::post(url).query("query", query).send(body) {
+        Ok(_) => Ok(()),
+        // the reason is in the body
+        Err(ureq::Error::Status(status, response)) => Err(io::Error::other(format!(
+            "clickhouse error {status}: {}",
+            response.into_string().unwrap_or_default().trim_end()
+        ))),
+        Err(e) => Err(io::Error::other(e)),
+    }
+}
+
+/// Insert the users into `table`, possibly qualified by its database and created if needed,
+/// streaming them as `TabSeparated`. `url` is the one of the HTTP interface, with the
+/// credentials as `user` and `password` parameters if any
+pub fn load<'a>(
+    url: &str,
+    table: &str,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)> + Send,
+) -> io::Result<()> {
+    let table = table
+        .split('.')
+        .map(|part| format!("`{}`", part.replace('`', "\\`")))
+        .collect::<Vec<_>>()
+        .join(".");
+    query(url, &create_table(&table), io::empty())?;
+    let (reader, writer) = io::pipe()?;
+    thread::scope(|s| {
+        let rows = s.spawn(move || {
+            let mut w = BufWriter::new(writer);
+            // same escapes as the text format of `COPY`
+            pg::write_copy_rows(&mut w, users)?;
+            w.flush()
+        });
+        let sent = query(
+            url,
+            &format!("INSERT INTO {table} FORMAT TabSeparated"),
+            reader,
+        );
+        // the request failing closes the pipe, making the rows fail too
+        let written = rows.join().expect("rows writer panicked");
+        sent.and(written)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{results::tests::one_game_each, visitor::Perf};
+
+    // answer `n` requests with 200, returning them
+    fn serve(listener: TcpListener, n: usize) -> Vec<String> {
+        (0..n)
+            .map(|_| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                // the body is chunked, ending with an empty chunk
+                if request.contains("chunked") {
+                    let mut body = Vec::new();
+                    while !body.ends_with(b"0\r\n\r\n") {
+                        let mut byte = [0];
+                        reader.read_exact(&mut byte).unwrap();
+                        body.push(byte[0]);
+                    }
+                    request.push_str(&String::from_utf8(body).unwrap());
+                }
+                (&stream)
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                    .unwrap();
+                request
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_load() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/?user=lab", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve(listener, 2));
+        let users = one_game_each("foo", &[Perf::Blitz]);
+        load(&url, "lab.time_spent", &users).unwrap();
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("user=lab&query=CREATE"));
+        assert!(requests[0].contains("black_real_time+UInt64+NOT+NULL"));
+        assert!(requests[1].contains("INSERT+INTO+%60lab%60.%60time_spent%60"));
+        assert!(requests[1]
+            .contains("foo\tblitz\t1\t2000\t300\t400500\t1200\t0\t0\t0\t0\t0\t0\t1\t400500\n"));
+    }
+}
@@ -0,0 +1,99 @@
+//! Split of the games and time spent between white and black
+
+use std::io::{self, Write};
+
+use crate::{
+    results::{escape, CsvOptions},
+    visitor::{TimeSpents, Users},
+};
+
+/// Number of standard deviations from an even split above which the split is flagged
+const IMBALANCE_Z_SCORE: f64 = 3.;
+
+/// Whether playing white `white_games` times out of `nb_games` is unlikely with an even chance
+/// of getting each color, per the normal approximation of the binomial distribution
+pub fn is_imbalanced(white_games: usize, nb_games: usize) -> bool {
+    if nb_games == 0 {
+        return false;
+    }
+    let deviation = (2. * white_games as f64 - nb_games as f64).abs();
+    deviation / (nb_games as f64).sqrt() > IMBALANCE_Z_SCORE
+}
+
+fn write_row(w: &mut impl Write, t: &TimeSpents, options: &CsvOptions) -> io::Result<()> {
+    let d = options.delimiter.as_char();
+    let unit = options.time_unit;
+    let (white, black) = (t.as_white(), t.as_black());
+    write!(
+        w,
+        "{d}{}{d}{}{d}{}{d}{}{d}{}",
+        white.nb_games,
+        black.nb_games,
+        unit.display(white.time_spent_exact),
+        unit.display(black.time_spent_exact),
+        is_imbalanced(white.nb_games, white.nb_games + black.nb_games),
+    )
+}
+
+/// `username,white_games,black_games,white_time,black_time,imbalanced`, sorted by username
+pub fn write_csv(w: &mut impl Write, users: &Users, options: &CsvOptions) -> io::Result<()> {
+    let d = options.delimiter.as_char();
+    writeln!(
+        w,
+        "username{d}white_games{d}black_games{d}white_time{d}black_time{d}imbalanced"
+    )?;
+    let mut rows: Vec<_> = users.iter().collect();
+    rows.sort_unstable_by_key(|(username, _)| *username);
+    for (username, time_spents) in rows {
+        write!(w, "{}", escape(username, d))?;
+        write_row(w, time_spents, options)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::visitor::{Perf, Rating};
+
+    #[test]
+    fn test_is_imbalanced() {
+        assert!(!is_imbalanced(0, 0));
+        assert!(!is_imbalanced(3, 3));
+        assert!(!is_imbalanced(60, 100));
+        assert!(is_imbalanced(70, 100));
+        assert!(is_imbalanced(0, 10));
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let mut alice = TimeSpents::default();
+        for is_white in [true, true, false] {
+            let exact = Duration::from_secs(if is_white { 100 } else { 50 });
+            alice.add_game(
+                Perf::Blitz,
+                exact,
+                Duration::ZERO,
+                180,
+                Rating(1500),
+                is_white,
+            );
+        }
+        // the colors are unknown for the results read from a csv
+        let mut bob = TimeSpents::default();
+        bob.get_mut(Perf::Blitz).nb_games = 4;
+        let users = Users::from_iter([("alice".to_string(), alice), ("bob".to_string(), bob)]);
+        let mut w = Vec::new();
+        write_csv(&mut w, &users, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,white_games,black_games,white_time,black_time,imbalanced
+alice,2,1,200,50,false
+bob,0,0,0,0,false
+"
+        );
+    }
+}
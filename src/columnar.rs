@@ -0,0 +1,380 @@
+//! Parquet and Arrow IPC exports of the results, with one row per user and the columns of the csv.
+//! Parquet has no duration type, the times are integers whose unit is in the field metadata
+
+use std::{fs::File, io::Write, sync::Arc};
+
+use arrow_array::{
+    builder::{Float64Builder, UInt64Builder},
+    cast::AsArray,
+    ArrayRef, RecordBatch, StringArray,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+    basic::{Compression, ZstdLevel},
+    errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use rustc_hash::FxHashSet;
+
+use crate::{
+    columns::{Cell, Column, Kind, Ranks},
+    results,
+    visitor::{Perf, TimeSpents},
+};
+
+/// Users per row group or record batch, bounding the memory used on top of the results
+const ROW_GROUP: usize = 1 << 16;
+
+fn field(perf: Perf, column: Column) -> Field {
+    let name = format!("{}_{}", perf.name(), column.header());
+    let data_type = match column.kind() {
+        Kind::Float => DataType::Float64,
+        Kind::Count | Kind::Time => DataType::UInt64,
+    };
+    let field = Field::new(name, data_type, true);
+    match column.integer_unit() {
+        Some(unit) => field.with_metadata([("unit".to_string(), unit.to_string())].into()),
+        None => field,
+    }
+}
+
+/// `username`, then `{perf}_{column}` for each perf and column, null for the perfs not played
+pub fn schema(columns: &[Column]) -> Schema {
+    let mut fields = vec![Field::new("username", DataType::Utf8, false)];
+    for perf in Perf::ALL {
+        fields.extend(columns.iter().map(|column| field(perf, *column)));
+    }
+    Schema::new(fields)
+}
+
+/// Like `schema`, with a `period` column after `username`, as written by `append_parquet`
+pub fn period_schema(columns: &[Column]) -> Schema {
+    let mut fields: Vec<_> = schema(columns).fields().iter().cloned().collect();
+    fields.insert(1, Arc::new(Field::new("period", DataType::Utf8, false)));
+    Schema::new(fields)
+}
+
+type Row<'a> = (&'a String, &'a TimeSpents, Option<&'a [Ranks; 6]>);
+
+// values of `column` for the `p`th perf, one per user
+fn array<'a>(column: Column, p: usize, rows: &[Row<'a>]) -> ArrayRef {
+    let perf = Perf::ALL[p];
+    let cells = rows.iter().map(|(_, t, ranks)| {
+        Some(t.get(perf))
+            .filter(|t| !t.is_empty())
+            .and_then(|t| column.value(t, ranks.map(|ranks| ranks[p])))
+    });
+    match column.kind() {
+        Kind::Float => {
+            let mut b = Float64Builder::with_capacity(rows.len());
+            for cell in cells {
+                b.append_option(match cell {
+                    Some(Cell::Float(x)) => Some(x),
+                    _ => None,
+                });
+            }
+            Arc::new(b.finish())
+        }
+        Kind::Count | Kind::Time => {
+            let mut b = UInt64Builder::with_capacity(rows.len());
+            for cell in cells {
+                b.append_option(cell.and_then(|cell| cell.as_u64(column)));
+            }
+            Arc::new(b.finish())
+        }
+    }
+}
+
+// pass the users to `f` by batches of `ROW_GROUP`, in the given order, with their `period`
+// if the schema has one
+fn for_each_batch<'a, E: From<ArrowError>>(
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    schema: &SchemaRef,
+    columns: &[Column],
+    period: Option<&str>,
+    mut f: impl FnMut(&RecordBatch) -> Result<(), E>,
+) -> Result<(), E> {
+    let users: Vec<_> = users.into_iter().collect();
+    let ranks = columns
+        .iter()
+        .any(|c| c.is_rank())
+        .then(|| results::ranks(&users));
+    let rows: Vec<Row<'_>> = users
+        .iter()
+        .enumerate()
+        .map(|(i, (username, t))| (*username, *t, ranks.as_ref().map(|ranks| &ranks[i])))
+        .collect();
+    for chunk in rows.chunks(ROW_GROUP) {
+        let usernames = StringArray::from_iter_values(chunk.iter().map(|(u, _, _)| u.as_str()));
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(usernames)];
+        if let Some(period) = period {
+            arrays.push(Arc::new(StringArray::from(vec![period; chunk.len()])));
+        }
+        for p in 0..Perf::ALL.len() {
+            for column in columns {
+                arrays.push(array(*column, p, chunk));
+            }
+        }
+        f(&RecordBatch::try_new(Arc::clone(schema), arrays)?)?;
+    }
+    Ok(())
+}
+
+fn properties() -> WriterProperties {
+    WriterProperties::builder()
+        .set_compression(Compression::ZSTD(ZstdLevel::default()))
+        .build()
+}
+
+/// Zstd compressed, the users being written in the given order
+pub fn write_parquet<'a>(
+    w: impl Write + Send,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    columns: &[Column],
+) -> Result<(), ParquetError> {
+    let schema = Arc::new(schema(columns));
+    let mut writer = ArrowWriter::try_new(w, Arc::clone(&schema), Some(properties()))?;
+    for_each_batch(users, &schema, columns, None, |batch| {
+        writer.write(batch)?;
+        writer.flush()
+    })?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Like `write_parquet`, the users being of `period`, after the rows of the `previous` parquet
+/// written the same way. A row being the perfs of a user in a period, the rows of the same
+/// username and period are replaced, usernames being matched with ASCII case folding like the
+/// `COLLATE NOCASE` of `sqlite::append`
+pub fn append_parquet<'a>(
+    w: impl Write + Send,
+    previous: Option<File>,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    columns: &[Column],
+    period: &str,
+) -> Result<(), ParquetError> {
+    let schema = Arc::new(period_schema(columns));
+    let mut writer = ArrowWriter::try_new(w, Arc::clone(&schema), Some(properties()))?;
+    let users: Vec<_> = users.into_iter().collect();
+    if let Some(previous) = previous {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(previous)?;
+        if reader.schema().fields() != schema.fields() {
+            return Err(ParquetError::General(
+                "the parquet to append to was written without --append or other --columns"
+                    .to_string(),
+            ));
+        }
+        let replaced: FxHashSet<_> = users.iter().map(|(u, _)| u.to_ascii_lowercase()).collect();
+        for batch in reader.build()? {
+            let batch = batch?;
+            let usernames = batch.column(0).as_string::<i32>();
+            let periods = batch.column(1).as_string::<i32>();
+            let is_replaced = |i| {
+                periods.value(i) == period
+                    && replaced.contains(&usernames.value(i).to_ascii_lowercase())
+            };
+            // the runs of rows kept
+            let mut start = 0;
+            for i in 0..=batch.num_rows() {
+                if i == batch.num_rows() || is_replaced(i) {
+                    if i > start {
+                        writer.write(&batch.slice(start, i - start))?;
+                    }
+                    start = i + 1;
+                }
+            }
+        }
+        writer.flush()?;
+    }
+    for_each_batch(users, &schema, columns, Some(period), |batch| {
+        writer.write(batch)?;
+        writer.flush()
+    })?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Uncompressed Arrow IPC file (Feather v2), which can be memory-mapped as is.
+/// Same schema as the parquet
+pub fn write_ipc<'a>(
+    w: impl Write,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    columns: &[Column],
+) -> Result<(), ArrowError> {
+    let schema = Arc::new(schema(columns));
+    let mut writer = FileWriter::try_new(w, &schema)?;
+    for_each_batch(users, &schema, columns, None, |batch| writer.write(batch))?;
+    writer.finish()
+}
+
+/// `CREATE VIEW` of a DuckDB `time_spent` table over the parquet at `path`,
+/// with the integer times converted to intervals
+pub fn duckdb_view(path: &str, columns: &[Column]) -> String {
+    let intervals: Vec<_> = Perf::ALL
+        .into_iter()
+        .flat_map(|perf| columns.iter().map(move |column| (perf, *column)))
+        .filter_map(|(perf, column)| {
+            let name = format!("{}_{}", perf.name(), column.header());
+            let to_interval = match column.integer_unit()? {
+                "s" => "to_seconds",
+                _ => "to_milliseconds",
+            };
+            Some(format!("    {to_interval}({name}::BIGINT) AS {name}"))
+        })
+        .collect();
+    let replace = if intervals.is_empty() {
+        String::new()
+    } else {
+        format!(" REPLACE (\n{}\n)", intervals.join(",\n"))
+    };
+    format!(
+        "CREATE OR REPLACE VIEW time_spent AS\nSELECT *{replace}\nFROM read_parquet('{}');\n",
+        path.replace('\'', "''")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Cursor};
+
+    use arrow_array::types::UInt64Type;
+    use arrow_ipc::reader::FileReader;
+
+    use super::*;
+    use crate::{results::tests::one_game_each, visitor::Users};
+
+    #[test]
+    fn test_write_parquet() {
+        let users = one_game_each("foo", &[Perf::Blitz]);
+        let path = std::env::temp_dir().join(format!("parquet-test-{}", std::process::id()));
+        let columns = [Column::Games, Column::Exact, Column::RankByGames];
+        write_parquet(File::create(&path).unwrap(), &users, &columns).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(path).unwrap();
+        let batch = &batches[0];
+        assert_eq!(batch.schema().as_ref(), &schema(&columns));
+        assert_eq!(batch.num_rows(), 1);
+        let column = |name| batch.column_by_name(name).unwrap();
+        assert_eq!(column("username").as_string::<i32>().value(0), "foo");
+        assert_eq!(
+            column("blitz_games").as_primitive::<UInt64Type>().value(0),
+            1
+        );
+        assert!(column("bullet_games").is_null(0));
+        let exact = column("blitz_real_time").as_primitive::<UInt64Type>();
+        assert_eq!(exact.value(0), 400_500);
+        assert_eq!(
+            column("blitz_rank_by_games")
+                .as_primitive::<UInt64Type>()
+                .value(0),
+            1
+        );
+    }
+
+    #[test]
+    fn test_append_parquet() {
+        let path = std::env::temp_dir().join(format!("parquet-append-{}", std::process::id()));
+        let columns = [Column::Games];
+        let append = |users: &Users, period: &str| {
+            let previous = File::open(&path).ok();
+            let mut w = Vec::new();
+            append_parquet(&mut w, previous, users, &columns, period).unwrap();
+            std::fs::write(&path, w).unwrap();
+        };
+        let mut users = one_game_each("foo", &[Perf::Blitz]);
+        users.extend(one_game_each("bar", &[Perf::Bullet]));
+        users.extend(one_game_each("Élodie", &[Perf::Bullet]));
+        append(&users, "2024-01");
+        append(&one_game_each("foo", &[Perf::Rapid]), "2024-02");
+        // a re-run of January, where foo is now Foo, and where only the ASCII letters of
+        // usernames fold
+        let mut again = one_game_each("Foo", &[Perf::Classical]);
+        again.extend(one_game_each("éLODIE", &[Perf::Blitz]));
+        append(&again, "2024-01");
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut rows: Vec<_> = reader
+            .map(Result::unwrap)
+            .flat_map(|batch| {
+                let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+                let (usernames, periods) = (column("username"), column("period"));
+                let games = ["bullet", "blitz", "rapid", "classical"]
+                    .map(|perf| column(&format!("{perf}_games")));
+                (0..batch.num_rows())
+                    .map(|i| {
+                        let played = games.iter().position(|g| !g.is_null(i));
+                        (
+                            usernames.as_string::<i32>().value(i).to_string(),
+                            periods.as_string::<i32>().value(i).to_string(),
+                            played,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        rows.sort();
+        let row = |username: &str, period: &str, played| {
+            (username.to_string(), period.to_string(), Some(played))
+        };
+        assert_eq!(
+            rows,
+            [
+                row("Foo", "2024-01", 3),
+                row("bar", "2024-01", 0),
+                row("foo", "2024-02", 2),
+                row("Élodie", "2024-01", 0),
+                row("éLODIE", "2024-01", 1),
+            ]
+        );
+        // not appended to a parquet without periods
+        write_parquet(File::create(&path).unwrap(), &users, &columns).unwrap();
+        let previous = File::open(&path).ok();
+        assert!(append_parquet(Vec::new(), previous, &users, &columns, "2024-03").is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_write_ipc() {
+        let mut w = Vec::new();
+        let columns = [Column::Games, Column::Approx];
+        write_ipc(&mut w, &one_game_each("foo", &[Perf::Blitz]), &columns).unwrap();
+        let mut reader = FileReader::try_new(Cursor::new(w), None).unwrap();
+        assert_eq!(reader.schema().as_ref(), &schema(&columns));
+        let batch = reader.next().unwrap().unwrap();
+        assert!(reader.next().is_none());
+        assert_eq!(batch.num_rows(), 1);
+        let approx = batch.column_by_name("blitz_approximate_time").unwrap();
+        assert_eq!(approx.as_primitive::<UInt64Type>().value(0), 300);
+        assert!(batch.column_by_name("blitz_real_time").is_none());
+    }
+
+    #[test]
+    fn test_duckdb_view() {
+        assert_eq!(
+            duckdb_view("/data/it's.parquet", &[Column::Games, Column::Setup]),
+            "CREATE OR REPLACE VIEW time_spent AS
+SELECT * REPLACE (
+    to_milliseconds(ultrabullet_setup_time::BIGINT) AS ultrabullet_setup_time,
+    to_milliseconds(bullet_setup_time::BIGINT) AS bullet_setup_time,
+    to_milliseconds(blitz_setup_time::BIGINT) AS blitz_setup_time,
+    to_milliseconds(rapid_setup_time::BIGINT) AS rapid_setup_time,
+    to_milliseconds(classical_setup_time::BIGINT) AS classical_setup_time,
+    to_milliseconds(correspondence_setup_time::BIGINT) AS correspondence_setup_time
+)
+FROM read_parquet('/data/it''s.parquet');
+"
+        );
+        assert_eq!(
+            duckdb_view("a.parquet", &[Column::Games]),
+            "CREATE OR REPLACE VIEW time_spent AS\nSELECT *\nFROM read_parquet('a.parquet');\n"
+        );
+    }
+}
@@ -0,0 +1,480 @@
+//! Per-perf columns of the results, shared by every writer
+
+use std::{
+    fmt,
+    io::{self, Write},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+
+use crate::{
+    results::CsvOptions,
+    visitor::{Perf, Rating, TimeSpent},
+};
+
+/// Unit of the durations in the outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    /// whole seconds, the only unit which can be read back
+    #[default]
+    Seconds,
+    /// with two decimals
+    Minutes,
+    /// with two decimals
+    Hours,
+    /// like `12d 3h 4m`
+    Human,
+}
+
+impl TimeUnit {
+    pub fn display(self, duration: Duration) -> impl fmt::Display {
+        DisplayDuration(self, duration)
+    }
+}
+
+struct DisplayDuration(TimeUnit, Duration);
+
+impl fmt::Display for DisplayDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.1.as_secs();
+        match self.0 {
+            TimeUnit::Seconds => write!(f, "{secs}"),
+            TimeUnit::Minutes => write!(f, "{:.2}", self.1.as_secs_f64() / 60.),
+            TimeUnit::Hours => write!(f, "{:.2}", self.1.as_secs_f64() / 3600.),
+            TimeUnit::Human => {
+                let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+                if days > 0 {
+                    write!(f, "{days}d {hours}h {minutes}m")
+                } else if hours > 0 {
+                    write!(f, "{hours}h {minutes}m")
+                } else {
+                    write!(f, "{minutes}m")
+                }
+            }
+        }
+    }
+}
+
+/// Column emitted for every perf, as `{perf}_{column}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Games,
+    Rating,
+    Approx,
+    Exact,
+    Setup,
+    Wins,
+    Draws,
+    Losses,
+    Plies,
+    /// games played as white
+    #[value(name = "white_games")]
+    #[serde(rename = "white_games")]
+    WhiteGames,
+    /// real time of the games played as white
+    #[value(name = "white_exact")]
+    #[serde(rename = "white_exact")]
+    WhiteExact,
+    /// games played as black
+    #[value(name = "black_games")]
+    #[serde(rename = "black_games")]
+    BlackGames,
+    /// real time of the games played as black
+    #[value(name = "black_exact")]
+    #[serde(rename = "black_exact")]
+    BlackExact,
+    /// games per hour of real time, derived from the other columns
+    #[value(name = "games_per_hour")]
+    #[serde(rename = "games_per_hour")]
+    GamesPerHour,
+    /// real time per game, derived from the other columns
+    #[value(name = "avg_game_time")]
+    #[serde(rename = "avg_game_time")]
+    AvgGameTime,
+    /// position among the written users by real time, 1 being the most
+    #[value(name = "rank_by_time")]
+    #[serde(rename = "rank_by_time")]
+    RankByTime,
+    /// position among the written users by number of games, 1 being the most
+    #[value(name = "rank_by_games")]
+    #[serde(rename = "rank_by_games")]
+    RankByGames,
+}
+
+/// Type of the values of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Count,
+    Float,
+    Time,
+}
+
+/// Value of a column for a perf played, before each writer formats it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cell {
+    Count(u64),
+    Float(f64),
+    Time(Duration),
+}
+
+impl Cell {
+    /// The times in the `integer_unit` of their column, as the typed outputs store them.
+    /// None for the floats
+    pub fn as_u64(self, column: Column) -> Option<u64> {
+        match self {
+            Self::Count(n) => Some(n),
+            Self::Float(_) => None,
+            Self::Time(time) if column.integer_unit() == Some("s") => Some(time.as_secs()),
+            Self::Time(time) => Some(time.as_millis() as u64),
+        }
+    }
+}
+
+/// Leaderboard positions of a user in a perf, ties sharing the same one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Ranks {
+    pub by_time: usize,
+    pub by_games: usize,
+}
+
+impl Column {
+    /// Written by default, and enough to read the results back
+    pub const ALL: [Self; 13] = [
+        Self::Games,
+        Self::Rating,
+        Self::Approx,
+        Self::Exact,
+        Self::Setup,
+        Self::Wins,
+        Self::Draws,
+        Self::Losses,
+        Self::Plies,
+        Self::WhiteGames,
+        Self::WhiteExact,
+        Self::BlackGames,
+        Self::BlackExact,
+    ];
+
+    /// Computed from the other columns when written, ignored when read
+    pub const DERIVED: [Self; 4] = [
+        Self::GamesPerHour,
+        Self::AvgGameTime,
+        Self::RankByTime,
+        Self::RankByGames,
+    ];
+
+    /// Not available without the clock comments
+    pub fn needs_clocks(self) -> bool {
+        matches!(
+            self,
+            Self::Exact
+                | Self::Setup
+                | Self::WhiteExact
+                | Self::BlackExact
+                | Self::GamesPerHour
+                | Self::AvgGameTime
+                | Self::RankByTime
+        )
+    }
+
+    /// Computed over all the written users
+    pub fn is_rank(self) -> bool {
+        matches!(self, Self::RankByTime | Self::RankByGames)
+    }
+
+    pub fn kind(self) -> Kind {
+        match self {
+            Self::Approx
+            | Self::Exact
+            | Self::Setup
+            | Self::WhiteExact
+            | Self::BlackExact
+            | Self::AvgGameTime => Kind::Time,
+            Self::GamesPerHour => Kind::Float,
+            Self::Games
+            | Self::Rating
+            | Self::Wins
+            | Self::Draws
+            | Self::Losses
+            | Self::Plies
+            | Self::WhiteGames
+            | Self::BlackGames
+            | Self::RankByTime
+            | Self::RankByGames => Kind::Count,
+        }
+    }
+
+    /// Unit of the times stored as integers, seconds for the approximate time which is whole,
+    /// milliseconds for the others
+    pub fn integer_unit(self) -> Option<&'static str> {
+        match self.kind() {
+            Kind::Time if self == Self::Approx => Some("s"),
+            Kind::Time => Some("ms"),
+            Kind::Count | Kind::Float => None,
+        }
+    }
+    pub fn header(self) -> &'static str {
+        match self {
+            Self::Games => "games",
+            Self::Rating => "avg_rating",
+            Self::Approx => "approximate_time",
+            Self::Exact => "real_time",
+            Self::Setup => "setup_time",
+            Self::Wins => "wins",
+            Self::Draws => "draws",
+            Self::Losses => "losses",
+            Self::Plies => "avg_plies",
+            Self::WhiteGames => "white_games",
+            Self::WhiteExact => "white_real_time",
+            Self::BlackGames => "black_games",
+            Self::BlackExact => "black_real_time",
+            Self::GamesPerHour => "games_per_hour",
+            Self::AvgGameTime => "avg_game_time",
+            Self::RankByTime => "rank_by_time",
+            Self::RankByGames => "rank_by_games",
+        }
+    }
+
+    /// `{perf}_{column}` header to its perf and column
+    pub fn parse_header(header: &str) -> Option<(Perf, Self)> {
+        Perf::ALL.into_iter().find_map(|perf| {
+            let column = header.strip_prefix(perf.name())?.strip_prefix('_')?;
+            Self::ALL
+                .into_iter()
+                .chain(Self::DERIVED)
+                .find(|c| c.header() == column)
+                .map(|c| (perf, c))
+        })
+    }
+
+    /// Of a perf played, none when a derived column cannot be computed, without real time
+    /// or `ranks`
+    pub fn value(self, t: &TimeSpent, ranks: Option<Ranks>) -> Option<Cell> {
+        let count = |n: usize| Cell::Count(n as u64);
+        Some(match self {
+            Self::Games => count(t.nb_games),
+            Self::Rating => count(t.total_rating.0 / t.nb_games),
+            Self::Approx => Cell::Time(Duration::from_secs(t.time_spent_approximate as u64)),
+            Self::Exact => Cell::Time(t.time_spent_exact),
+            Self::Setup => Cell::Time(t.setup_time),
+            Self::Wins => count(t.wins),
+            Self::Draws => count(t.draws),
+            Self::Losses => count(t.losses),
+            Self::Plies => count(t.total_plies / t.nb_games),
+            Self::WhiteGames => count(t.as_white.nb_games),
+            Self::WhiteExact => Cell::Time(t.as_white.time_spent_exact),
+            Self::BlackGames => count(t.as_black.nb_games),
+            Self::BlackExact => Cell::Time(t.as_black.time_spent_exact),
+            Self::GamesPerHour | Self::AvgGameTime if t.time_spent_exact.is_zero() => return None,
+            Self::GamesPerHour => {
+                Cell::Float(t.nb_games as f64 * 3600. / t.time_spent_exact.as_secs_f64())
+            }
+            Self::AvgGameTime => Cell::Time(t.average_game()),
+            Self::RankByTime => count(ranks?.by_time),
+            Self::RankByGames => count(ranks?.by_games),
+        })
+    }
+
+    fn write(
+        self,
+        w: &mut impl Write,
+        t: &TimeSpent,
+        unit: TimeUnit,
+        ranks: Option<Ranks>,
+    ) -> io::Result<()> {
+        match self.value(t, ranks) {
+            Some(Cell::Count(n)) => write!(w, "{n}"),
+            Some(Cell::Float(x)) => write!(w, "{x:.2}"),
+            Some(Cell::Time(time)) => write!(w, "{}", unit.display(time)),
+            None => Ok(()),
+        }
+    }
+
+    // the number of games must be read first, for the averages
+    fn read(self, t: &mut TimeSpent, field: &str) -> Option<()> {
+        match self {
+            Self::Games => t.nb_games = field.parse().ok()?,
+            Self::Rating => t.total_rating = Rating(field.parse::<usize>().ok()? * t.nb_games),
+            Self::Approx => t.time_spent_approximate = field.parse().ok()?,
+            Self::Exact => t.time_spent_exact = Duration::from_secs(field.parse().ok()?),
+            Self::Setup => t.setup_time = Duration::from_secs(field.parse().ok()?),
+            Self::Wins => t.wins = field.parse().ok()?,
+            Self::Draws => t.draws = field.parse().ok()?,
+            Self::Losses => t.losses = field.parse().ok()?,
+            Self::Plies => t.total_plies = field.parse::<usize>().ok()? * t.nb_games,
+            Self::WhiteGames => t.as_white.nb_games = field.parse().ok()?,
+            Self::WhiteExact => {
+                t.as_white.time_spent_exact = Duration::from_secs(field.parse().ok()?)
+            }
+            Self::BlackGames => t.as_black.nb_games = field.parse().ok()?,
+            Self::BlackExact => {
+                t.as_black.time_spent_exact = Duration::from_secs(field.parse().ok()?)
+            }
+            Self::GamesPerHour | Self::AvgGameTime | Self::RankByTime | Self::RankByGames => (),
+        }
+        Some(())
+    }
+}
+
+impl TimeSpent {
+    /// real time per game, which must have been played
+    pub(crate) fn average_game(&self) -> Duration {
+        self.time_spent_exact / self.nb_games as u32
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        // without clocks, only the approximate time is known
+        self.nb_games == 0 || (self.time_spent_exact.is_zero() && self.time_spent_approximate == 0)
+    }
+
+    /// The rank columns are left empty without `ranks`
+    pub(crate) fn to_csv(
+        &self,
+        w: &mut impl Write,
+        options: &CsvOptions,
+        ranks: Option<Ranks>,
+    ) -> io::Result<()> {
+        for column in &options.columns {
+            write!(w, "{}", options.delimiter.as_char())?;
+            if !self.is_empty() {
+                column.write(w, self, options.time_unit, ranks)?;
+            }
+        }
+        Ok(())
+    }
+
+    // inverse of `to_csv`, the average rating is rounded down
+    pub(crate) fn from_csv(fields: &[(Column, &str)]) -> Option<Self> {
+        let mut t = Self::default();
+        if fields.iter().all(|(_, field)| field.is_empty()) {
+            return Some(t);
+        }
+        let mut fields = fields.to_vec();
+        fields.sort_by_key(|(column, _)| *column);
+        for (column, field) in fields {
+            column.read(&mut t, field)?;
+        }
+        Some(t)
+    }
+}
+
+/// A cell of the json outputs, in the same unit as the csv but with numbers when possible
+struct JsonCell(Option<Cell>, TimeUnit);
+
+impl Serialize for JsonCell {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self.0, self.1) {
+            (None, _) => serializer.serialize_none(),
+            (Some(Cell::Count(n)), _) => serializer.serialize_u64(n),
+            (Some(Cell::Float(x)), _) => serializer.serialize_f64(x),
+            (Some(Cell::Time(time)), TimeUnit::Seconds) => serializer.serialize_u64(time.as_secs()),
+            (Some(Cell::Time(time)), TimeUnit::Minutes) => {
+                serializer.serialize_f64(time.as_secs_f64() / 60.)
+            }
+            (Some(Cell::Time(time)), TimeUnit::Hours) => {
+                serializer.serialize_f64(time.as_secs_f64() / 3600.)
+            }
+            (Some(Cell::Time(time)), TimeUnit::Human) => {
+                serializer.collect_str(&TimeUnit::Human.display(time))
+            }
+        }
+    }
+}
+
+/// The columns of a perf played, keyed by their header
+pub(crate) struct JsonPerf<'a> {
+    pub t: &'a TimeSpent,
+    pub options: &'a CsvOptions,
+    pub ranks: Option<Ranks>,
+}
+
+impl Serialize for JsonPerf<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.options.columns.len()))?;
+        for column in &self.options.columns {
+            let cell = JsonCell(column.value(self.t, self.ranks), self.options.time_unit);
+            map.serialize_entry(column.header(), &cell)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            Column::parse_header("ultrabullet_avg_rating"),
+            Some((Perf::UltraBullet, Column::Rating))
+        );
+        assert_eq!(
+            Column::parse_header("bullet_real_time"),
+            Some((Perf::Bullet, Column::Exact))
+        );
+        assert_eq!(Column::parse_header("bullet_unknown"), None);
+    }
+
+    #[test]
+    fn test_time_unit() {
+        let d = Duration::from_secs(12 * 86400 + 3 * 3600 + 4 * 60 + 5);
+        assert_eq!(TimeUnit::Seconds.display(d).to_string(), "1047845");
+        assert_eq!(TimeUnit::Minutes.display(d).to_string(), "17464.08");
+        assert_eq!(TimeUnit::Hours.display(d).to_string(), "291.07");
+        assert_eq!(TimeUnit::Human.display(d).to_string(), "12d 3h 4m");
+        assert_eq!(
+            TimeUnit::Human.display(Duration::from_secs(59)).to_string(),
+            "0m"
+        );
+    }
+
+    #[test]
+    fn test_games_per_hour() {
+        let t = TimeSpent {
+            nb_games: 5,
+            time_spent_exact: Duration::from_secs(2 * 3600),
+            ..Default::default()
+        };
+        let mut w = Vec::new();
+        Column::GamesPerHour
+            .write(&mut w, &t, TimeUnit::Seconds, None)
+            .unwrap();
+        assert_eq!(w, b"2.50");
+        assert_eq!(
+            Column::parse_header("blitz_games_per_hour"),
+            Some((Perf::Blitz, Column::GamesPerHour))
+        );
+        let read = TimeSpent::from_csv(&[(Column::Games, "5"), (Column::GamesPerHour, "2.50")]);
+        assert_eq!(read.unwrap().nb_games, 5);
+    }
+
+    #[test]
+    fn test_avg_game_time() {
+        let t = TimeSpent {
+            nb_games: 4,
+            time_spent_exact: Duration::from_secs(1210),
+            ..Default::default()
+        };
+        let mut w = Vec::new();
+        Column::AvgGameTime
+            .write(&mut w, &t, TimeUnit::Seconds, None)
+            .unwrap();
+        assert_eq!(w, b"302");
+        let mut w = Vec::new();
+        Column::AvgGameTime
+            .write(&mut w, &TimeSpent::default(), TimeUnit::Seconds, None)
+            .unwrap();
+        assert!(w.is_empty());
+    }
+
+    #[test]
+    fn test_rating_read_after_games() {
+        let t = TimeSpent::from_csv(&[(Column::Rating, "1500"), (Column::Games, "2")]).unwrap();
+        assert_eq!(t.total_rating, Rating(3000));
+    }
+}
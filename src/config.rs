@@ -0,0 +1,159 @@
+//! `--config` toml file, whose keys are the long names of the command line options
+
+use std::{ffi::OsString, fs, io};
+
+use clap::{parser::ValueSource, ArgAction, ArgMatches, Command};
+use toml::{Table, Value};
+
+/// Command line arguments, with the options of the `--config` file prepended when there is one,
+/// so that clap validates them together: the pgn is not required with `watch` in the config,
+/// and conflicting options are rejected wherever they come from
+pub fn with_config_file(cmd: Command, cli: Vec<OsString>) -> io::Result<Vec<OsString>> {
+    with_config(cmd, cli, load)
+}
+
+fn with_config(
+    mut cmd: Command,
+    cli: Vec<OsString>,
+    load: impl FnOnce(&str) -> io::Result<Table>,
+) -> io::Result<Vec<OsString>> {
+    cmd.build();
+    // the errors are left to the final parsing, with the config options
+    let Ok(matches) = cmd.clone().ignore_errors(true).try_get_matches_from(&cli) else {
+        return Ok(cli);
+    };
+    let Some(path) = matches.get_one::<String>("config") else {
+        return Ok(cli);
+    };
+    let config = config_args(&cmd, &matches, &load(path)?)?;
+    let mut cli = cli.into_iter();
+    Ok(cli.next().into_iter().chain(config).chain(cli).collect())
+}
+
+fn load(path: &str) -> io::Result<Table> {
+    toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// as if given before the command line, leaving out the options it sets itself or conflicts with
+fn config_args(cmd: &Command, matches: &ArgMatches, config: &Table) -> io::Result<Vec<OsString>> {
+    let on_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let mut args = Vec::new();
+    for (key, value) in config {
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key) && key != "config")
+            .ok_or_else(|| invalid(format!("unknown option `{key}` in the config file")))?;
+        // conflicts being declared on either side
+        let overridden = cmd.get_arguments().any(|other| {
+            on_cli(other.get_id().as_str())
+                && (other.get_id() == arg.get_id()
+                    || cmd.get_arg_conflicts_with(arg).contains(&other)
+                    || cmd.get_arg_conflicts_with(other).contains(&arg))
+        });
+        if overridden {
+            continue;
+        }
+        let flag = OsString::from(format!("--{key}"));
+        match (arg.get_action(), value) {
+            (ArgAction::SetTrue, Value::Boolean(set)) => {
+                if *set {
+                    args.push(flag);
+                }
+            }
+            (ArgAction::Count, Value::Integer(count)) => {
+                args.extend((0..*count).map(|_| flag.clone()));
+            }
+            (_, Value::Array(values)) => {
+                let values = values.iter().map(scalar).collect::<io::Result<Vec<_>>>()?;
+                let multiple = arg.get_num_args().is_some_and(|n| n.max_values() > 1);
+                if multiple {
+                    args.push(flag);
+                    args.extend(values.into_iter().map(OsString::from));
+                } else {
+                    args.push(format!("--{key}={}", values.join(",")).into());
+                }
+            }
+            (_, value) => args.push(format!("--{key}={}", scalar(value)?).into()),
+        }
+    }
+    Ok(args)
+}
+
+fn scalar(value: &Value) -> io::Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Datetime(datetime) => Ok(datetime.to_string()),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+        Value::Array(_) | Value::Table(_) => {
+            Err(invalid(format!("unexpected `{value}` in the config file")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use clap::{CommandFactory, Parser};
+    use username_time_spent::{analysis::Buckets, input::Compression};
+
+    use super::*;
+    use crate::Args;
+
+    fn args(config: &str, cli: &[&str]) -> io::Result<Vec<OsString>> {
+        let cli = ["lts", "--config", "config.toml"].iter().chain(cli);
+        with_config(Args::command(), cli.map(OsString::from).collect(), |path| {
+            assert_eq!(path, "config.toml");
+            Ok(toml::from_str(config).unwrap())
+        })
+    }
+
+    fn parse(config: &str, cli: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(args(config, cli).unwrap())
+    }
+
+    #[test]
+    fn test_cli_overrides_config() {
+        let args = parse(
+            r#"
+            output = "from-config.csv"
+            buckets = [30, 60, 120, 179]
+            format = "zst"
+            from = 2024-03-01
+            from-state = ["a.bin", "b.bin"]
+            verbose = 2
+            "#,
+            &["--output", "from-cli.csv"],
+        )
+        .unwrap();
+        assert_eq!(args.output.as_deref(), Some("from-cli.csv"));
+        assert_eq!(args.buckets, Some(Buckets::new(30, 60, 120, 179)));
+        assert_eq!(args.format, Some(Compression::Zst));
+        assert_eq!(args.from, NaiveDate::from_ymd_opt(2024, 3, 1));
+        assert_eq!(args.from_state, Some(vec!["a.bin".into(), "b.bin".into()]));
+        assert_eq!(args.verbose, 2);
+    }
+
+    #[test]
+    fn test_validated_with_cli() {
+        // the pgn is not required when watching
+        let args = parse("watch = \"dumps\"", &[]).unwrap();
+        assert_eq!(args.watch.as_deref(), Some("dumps"));
+        assert!(parse("count-first = true", &[]).is_err());
+        assert!(parse("rated-only = true\ncasual-only = true", &["lichess.pgn"]).is_err());
+        // unless the command line overrides one of them
+        let args = parse("casual-only = true", &["lichess.pgn", "--rated-only"]).unwrap();
+        assert!(args.rated_only && !args.casual_only);
+        assert!(!parse("no-bots = false", &["lichess.pgn"]).unwrap().no_bots);
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        assert!(args("ouptut = \"typo.csv\"", &["lichess.pgn"]).is_err());
+        assert!(args("config = \"other.toml\"", &["lichess.pgn"]).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Share of the games with clocks per perf, older dumps having none
+
+use std::io::{self, Write};
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    results::Delimiter,
+    visitor::Perf,
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coverage {
+    pub games: u64,
+    /// games whose exact time is computed from the clocks, the others only have an approximate time
+    pub games_with_clocks: u64,
+}
+
+#[derive(Default, Debug)]
+pub struct ClockCoverage([Coverage; 6]);
+
+impl ClockCoverage {
+    pub fn get(&self, perf: Perf) -> Coverage {
+        self.0[perf_index(perf)]
+    }
+
+    /// `perf,games,games_with_clocks,share_with_clocks`, perfs without games being omitted
+    pub fn write_csv(&self, w: &mut impl Write, delimiter: Delimiter) -> io::Result<()> {
+        let d = delimiter.as_char();
+        writeln!(w, "perf{d}games{d}games_with_clocks{d}share_with_clocks")?;
+        for perf in Perf::ALL {
+            let coverage = self.get(perf);
+            if coverage.games > 0 {
+                writeln!(
+                    w,
+                    "{}{d}{}{d}{}{d}{:.4}",
+                    perf.name(),
+                    coverage.games,
+                    coverage.games_with_clocks,
+                    coverage.games_with_clocks as f64 / coverage.games as f64
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn perf_index(perf: Perf) -> usize {
+    Perf::ALL
+        .iter()
+        .position(|p| *p == perf)
+        .expect("perf in Perf::ALL")
+}
+
+impl Aggregator for ClockCoverage {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        let coverage = &mut self.0[perf_index(game.perf)];
+        coverage.games += 1;
+        coverage.games_with_clocks += u64::from(game.has_clocks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+"#;
+
+    #[test]
+    fn test_clock_coverage() {
+        let mut coverage = ClockCoverage::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut coverage])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let mut w = Vec::new();
+        coverage.write_csv(&mut w, Delimiter::Comma).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "perf,games,games_with_clocks,share_with_clocks\nblitz,2,1,0.5000\n"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Real time played over the days of each month, to compare months at a glance
+
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{Datelike, NaiveDate};
+use plotters::{coord::Shift, prelude::*};
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    plot::{DrawResult, Figure, Theme},
+};
+
+/// Real time of the games by day of their start, in the timezone of the analysis.
+/// Games without a start are ignored
+#[derive(Default, Debug)]
+pub struct DailyTotals(BTreeMap<NaiveDate, Duration>);
+
+impl DailyTotals {
+    /// For each month played, by `YYYY-MM`, the hours played up to the end of each day
+    fn cumulative_hours(&self) -> BTreeMap<String, Vec<(u32, f64)>> {
+        let mut months: BTreeMap<String, Vec<(u32, f64)>> = BTreeMap::new();
+        for (date, time) in &self.0 {
+            let days = months.entry(date.format("%Y-%m").to_string()).or_default();
+            let before = days.last().map_or(0., |(_, hours)| *hours);
+            days.push((date.day(), before + time.as_secs_f64() / 3600.));
+        }
+        months
+    }
+}
+
+impl Aggregator for DailyTotals {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if let Some(start) = game.start {
+            *self.0.entry(start.date()).or_default() += game.exact_duration;
+        }
+    }
+}
+
+impl Figure for DailyTotals {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let months = self.cumulative_hours();
+        let max_hours = months
+            .values()
+            .filter_map(|days| days.last())
+            .map(|(_, hours)| *hours)
+            .fold(1., f64::max);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Cumulative real time over the month", theme.caption(30))
+            .margin(20)
+            .x_label_area_size(50)
+            .y_label_area_size(90)
+            .build_cartesian_2d(1_u32..31, 0_f64..max_hours * 1.05)?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .x_desc("day of the month")
+            .y_desc("hours")
+            .draw()?;
+        for (i, (month, days)) in months.into_iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(days, color.stroke_width(2)))?
+                .label(month)
+                .legend(move |(x, y)| {
+                    PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2))
+                });
+        }
+        theme
+            .legend(&mut chart.configure_series_labels())
+            .position(SeriesLabelPosition::UpperLeft)
+            .draw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    fn game(date: &str) -> String {
+        format!(
+            r#"[Event "Rated Blitz game"]
+[UTCDate "{date}"]
+[UTCTime "12:00:00"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+
+1. e4 {{ [%clk 0:03:00] }} 1... e5 {{ [%clk 0:03:00] }} 2. Nf3 {{ [%clk 0:02:00] }} 2... Nc6 {{ [%clk 0:02:00] }} 1-0
+
+"#
+        )
+    }
+
+    #[test]
+    fn test_cumulative_hours() {
+        let pgn = [
+            game("2023.01.30"),
+            game("2023.01.30"),
+            game("2023.01.31"),
+            game("2023.02.01"),
+        ]
+        .concat();
+        let mut daily = DailyTotals::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut daily])
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        let months = daily.cumulative_hours();
+        assert_eq!(months.keys().collect::<Vec<_>>(), ["2023-01", "2023-02"]);
+        let january = &months["2023-01"];
+        assert_eq!(january.len(), 2);
+        assert_eq!(january[0].0, 30);
+        assert!((january[1].1 - 1.5 * january[0].1).abs() < 1e-9);
+        assert_eq!(months["2023-02"].len(), 1);
+    }
+}
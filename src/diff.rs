@@ -0,0 +1,140 @@
+//! Comparing two `time-spent.csv` results
+
+use std::{
+    collections::BTreeSet,
+    io::{self, BufWriter, Write},
+};
+
+use crate::{
+    results,
+    visitor::{Perf, TimeSpent, TimeSpents, Users},
+};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct DiffStats {
+    new: usize,
+    gone: usize,
+    changed: usize,
+    unchanged: usize,
+}
+
+/// Print as csv the per-user and per-perf differences from `before` to `after`,
+/// and a summary on stderr
+pub fn run(before: &str, after: &str) -> io::Result<()> {
+    let before = results::load(before)?;
+    let after = results::load(after)?;
+    let mut w = BufWriter::new(io::stdout().lock());
+    let stats = write_diff(&mut w, &before, &after)?;
+    w.flush()?;
+    eprintln!(
+        "{} new users, {} gone users, {} changed users, {} unchanged users",
+        stats.new, stats.gone, stats.changed, stats.unchanged
+    );
+    Ok(())
+}
+
+fn write_diff(w: &mut impl Write, before: &Users, after: &Users) -> io::Result<DiffStats> {
+    let mut stats = DiffStats::default();
+    let usernames: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let empty = TimeSpents::default();
+    writeln!(
+        w,
+        "username,perf,status,games_delta,approximate_time_delta,real_time_delta"
+    )?;
+    for username in usernames {
+        let (status, b, a) = match (before.get(username), after.get(username)) {
+            (None, Some(a)) => ("new", &empty, a),
+            (Some(b), None) => ("gone", b, &empty),
+            (Some(b), Some(a)) => ("changed", b, a),
+            (None, None) => unreachable!("username comes from one of the results"),
+        };
+        let mut user_changed = false;
+        for perf in Perf::ALL {
+            let (games, approximate, exact) = deltas(b.get(perf), a.get(perf));
+            if (games, approximate, exact) != (0, 0, 0) {
+                user_changed = true;
+                writeln!(
+                    w,
+                    "{},{},{status},{games},{approximate},{exact}",
+                    results::escape(username, ','),
+                    perf.name()
+                )?;
+            }
+        }
+        match status {
+            "new" => stats.new += 1,
+            "gone" => stats.gone += 1,
+            _ if user_changed => stats.changed += 1,
+            _ => stats.unchanged += 1,
+        }
+    }
+    Ok(stats)
+}
+
+// games, approximate time and exact time, in seconds
+fn deltas(before: &TimeSpent, after: &TimeSpent) -> (i64, i64, i64) {
+    (
+        after.nb_games as i64 - before.nb_games as i64,
+        after.time_spent_approximate as i64 - before.time_spent_approximate as i64,
+        after.time_spent_exact.as_secs() as i64 - before.time_spent_exact.as_secs() as i64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::visitor::Rating;
+
+    #[test]
+    fn test_write_diff() {
+        let mut one_game = TimeSpents::default();
+        one_game.add_game(
+            Perf::Bullet,
+            Duration::from_secs(100),
+            Duration::ZERO,
+            60,
+            Rating(1500),
+            false,
+        );
+        let mut two_games = one_game.clone();
+        two_games.add_game(
+            Perf::Blitz,
+            Duration::from_secs(300),
+            Duration::ZERO,
+            300,
+            Rating(1500),
+            false,
+        );
+        let before = Users::from_iter([
+            ("same".to_string(), one_game.clone()),
+            ("more".to_string(), one_game.clone()),
+            ("gone".to_string(), one_game.clone()),
+        ]);
+        let after = Users::from_iter([
+            ("same".to_string(), one_game.clone()),
+            ("more".to_string(), two_games),
+            ("new,user".to_string(), one_game),
+        ]);
+        let mut w = Vec::new();
+        let stats = write_diff(&mut w, &before, &after).unwrap();
+        assert_eq!(
+            stats,
+            DiffStats {
+                new: 1,
+                gone: 1,
+                changed: 1,
+                unchanged: 1
+            }
+        );
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,perf,status,games_delta,approximate_time_delta,real_time_delta
+gone,bullet,gone,-1,-60,-100
+more,blitz,changed,1,300,300
+\"new,user\",bullet,new,1,60,100
+"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Distribution of the real durations of the games, against their `base + 40 * increment`
+//! approximation
+
+use std::time::Duration;
+
+use plotters::{coord::Shift, prelude::*};
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    plot::{tick_label, DrawResult, Figure, Theme},
+    visitor::Perf,
+};
+
+/// Bins per doubling of the duration
+const BINS_PER_OCTAVE: f64 = 4.;
+
+// bins from a second, games shorter being counted in the first one
+fn bin(duration: Duration) -> usize {
+    (BINS_PER_OCTAVE * duration.as_secs_f64().log2()).max(0.) as usize
+}
+
+fn lower_bound(bin: usize) -> f64 {
+    2_f64.powf(bin as f64 / BINS_PER_OCTAVE)
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+struct Counts {
+    real: Vec<u64>,
+    approximate: Vec<u64>,
+}
+
+fn add(counts: &mut Vec<u64>, bin: usize) {
+    if counts.len() <= bin {
+        counts.resize(bin + 1, 0);
+    }
+    counts[bin] += 1;
+}
+
+/// Number of games by real and approximate duration, per perf
+#[derive(Default, Debug)]
+pub struct GameDurations([Counts; 6]);
+
+impl Aggregator for GameDurations {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if !game.has_clocks {
+            return;
+        }
+        let p = Perf::ALL
+            .iter()
+            .position(|p| *p == game.perf)
+            .expect("perf in Perf::ALL");
+        let approximate = Duration::from_secs(game.approximate_duration as u64);
+        add(&mut self.0[p].real, bin(game.exact_duration));
+        add(&mut self.0[p].approximate, bin(approximate));
+    }
+}
+
+impl GameDurations {
+    fn draw_perf<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+        p: usize,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let counts = &self.0[p];
+        let series = [(&counts.real, "real"), (&counts.approximate, "approximate")];
+        let first = series
+            .iter()
+            .filter_map(|(counts, _)| counts.iter().position(|n| *n > 0))
+            .min()
+            .unwrap_or(0);
+        let end = counts
+            .real
+            .len()
+            .max(counts.approximate.len())
+            .max(first + 1);
+        let max_count = series
+            .iter()
+            .flat_map(|(counts, _)| counts.iter())
+            .max()
+            .copied()
+            .unwrap_or(0)
+            .max(1);
+        let mut chart = ChartBuilder::on(area)
+            .caption(Perf::ALL[p].name(), theme.caption(20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(
+                (lower_bound(first)..lower_bound(end)).log_scale(),
+                0..max_count + max_count / 10,
+            )?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .x_desc("seconds")
+            .y_desc("games")
+            .x_label_formatter(&tick_label)
+            .draw()?;
+        for (i, (counts, label)) in series.into_iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            let points = counts
+                .iter()
+                .enumerate()
+                .skip(first)
+                .map(|(bin, n)| (lower_bound(bin), *n));
+            chart
+                .draw_series(LineSeries::new(points, color.stroke_width(2)))?
+                .label(label)
+                .legend(move |(x, y)| {
+                    PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2))
+                });
+        }
+        theme.legend(&mut chart.configure_series_labels()).draw()
+    }
+}
+
+impl Figure for GameDurations {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let root = root.titled("Games by real and approximate duration", theme.caption(30))?;
+        // a panel per perf played
+        let played = (0..Perf::ALL.len()).filter(|p| !self.0[*p].real.is_empty());
+        for (area, p) in root.split_evenly((2, 3)).iter().zip(played) {
+            self.draw_perf(area, p, theme)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+"#;
+
+    #[test]
+    fn test_game_durations() {
+        let mut durations = GameDurations::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut durations])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let blitz = &durations.0[2];
+        // only the game with clocks, of 180 + 40 * 2 = 260 seconds approximately
+        assert_eq!(blitz.approximate.iter().sum::<u64>(), 1);
+        assert_eq!(blitz.approximate.len(), bin(Duration::from_secs(260)) + 1);
+        assert_eq!(blitz.real.iter().sum::<u64>(), 1);
+        assert!(durations.0[0].real.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+//! Clock left to the loser of decisive games, by how the game ended
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    results::CsvOptions,
+    visitor::Perf,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Termination {
+    Resignation,
+    Checkmate,
+    Flag,
+}
+
+impl Termination {
+    pub const ALL: [Self; 3] = [Self::Resignation, Self::Checkmate, Self::Flag];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Resignation => "resignation",
+            Self::Checkmate => "checkmate",
+            Self::Flag => "flag",
+        }
+    }
+}
+
+/// How a decisive game ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ending {
+    pub termination: Termination,
+    /// last clock of the loser, before their last move
+    pub loser_clock: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockStats {
+    pub games: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+impl ClockStats {
+    fn new(clock: Duration) -> Self {
+        Self {
+            games: 1,
+            min: clock,
+            max: clock,
+            total: clock,
+        }
+    }
+
+    fn add(&mut self, clock: Duration) {
+        self.games += 1;
+        self.min = self.min.min(clock);
+        self.max = self.max.max(clock);
+        self.total += clock;
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.total / self.games as u32
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct EndingClocks(FxHashMap<(Perf, Termination), ClockStats>);
+
+impl EndingClocks {
+    pub fn get(&self, perf: Perf, termination: Termination) -> Option<&ClockStats> {
+        self.0.get(&(perf, termination))
+    }
+
+    pub fn write_csv(&self, w: &mut impl Write, options: &CsvOptions) -> io::Result<()> {
+        let d = options.delimiter.as_char();
+        let unit = options.time_unit;
+        writeln!(
+            w,
+            "perf{d}termination{d}games{d}min_clock{d}max_clock{d}mean_clock"
+        )?;
+        for perf in Perf::ALL {
+            for termination in Termination::ALL {
+                if let Some(stats) = self.get(perf, termination) {
+                    writeln!(
+                        w,
+                        "{}{d}{}{d}{}{d}{}{d}{}{d}{}",
+                        perf.name(),
+                        termination.name(),
+                        stats.games,
+                        unit.display(stats.min),
+                        unit.display(stats.max),
+                        unit.display(stats.mean()),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for EndingClocks {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if let Some(ending) = game.ending {
+            self.0
+                .entry((game.perf, ending.termination))
+                .and_modify(|stats| stats.add(ending.loser_clock))
+                .or_insert_with(|| ClockStats::new(ending.loser_clock));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[Result "1-0"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+[Termination "Normal"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Bc4 { [%clk 0:02:58] } 2... Nc6 { [%clk 0:02:50] } 3. Qh5 { [%clk 0:02:56] } 3... Nf6 { [%clk 0:02:40] } 4. Qxf7# { [%clk 0:02:55] } 1-0
+
+[Event "Rated Blitz game"]
+[Result "1-0"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+[Termination "Normal"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:20] } 1-0
+
+[Event "Rated Blitz game"]
+[Result "0-1"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+[Termination "Time forfeit"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:00:01] } 2... Nc6 { [%clk 0:02:20] } 0-1
+
+[Event "Rated Blitz game"]
+[Result "0-1"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+[Termination "Normal"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:30] } 2... Nc6 { [%clk 0:02:20] } 0-1
+"#;
+
+    #[test]
+    fn test_ending_clocks() {
+        let mut endings = EndingClocks::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut endings])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let mut w = Vec::new();
+        endings.write_csv(&mut w, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "perf,termination,games,min_clock,max_clock,mean_clock
+blitz,resignation,2,140,150,145
+blitz,checkmate,1,160,160,160
+blitz,flag,1,1,1,1
+"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Time control each user played the most
+
+use std::io::{self, Write};
+
+use arrayvec::ArrayVec;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    placeholders::is_placeholder,
+    results::{escape, Delimiter},
+    visitor::Tc,
+};
+
+/// Time controls counted per user, beyond which the least played one is evicted
+const TRACKED: usize = 8;
+
+/// Games of a user in their most played time controls, counted like the Space-Saving
+/// algorithm: a new time control replaces the least played one and inherits its count, so that
+/// the memory is bounded. A time control played in over an eighth of the games is always kept,
+/// its games being at most overestimated by an eighth of them
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct UserTcs {
+    pub games: u64,
+    tcs: ArrayVec<(Tc, u64), TRACKED>,
+}
+
+impl UserTcs {
+    fn add(&mut self, tc: Tc) {
+        self.games += 1;
+        if let Some((_, games)) = self.tcs.iter_mut().find(|(t, _)| *t == tc) {
+            *games += 1;
+        } else if self.tcs.is_full() {
+            let least = self
+                .tcs
+                .iter_mut()
+                .min_by_key(|(_, games)| *games)
+                .expect("tracked time controls");
+            *least = (tc, least.1 + 1);
+        } else {
+            self.tcs.push((tc, 1));
+        }
+    }
+
+    /// The most played time control and its games, the first reached on ties
+    pub fn favorite(&self) -> Option<(Tc, u64)> {
+        self.tcs
+            .iter()
+            .rev()
+            .max_by_key(|(_, games)| *games)
+            .copied()
+    }
+
+    /// Share of the games played in the favorite time control
+    pub fn share(&self) -> f64 {
+        self.favorite()
+            .map_or(0., |(_, games)| games as f64 / self.games as f64)
+    }
+}
+
+/// Favorite time control of each user, all perfs together
+#[derive(Default, Debug)]
+pub struct FavoriteTcs(FxHashMap<String, UserTcs>);
+
+impl FavoriteTcs {
+    pub fn get(&self, username: &str) -> Option<&UserTcs> {
+        self.0.get(username)
+    }
+
+    /// By username
+    pub fn write_csv(&self, w: &mut impl Write, delimiter: Delimiter) -> io::Result<()> {
+        let d = delimiter.as_char();
+        writeln!(w, "username{d}time_control{d}games{d}share")?;
+        let mut rows: Vec<_> = self.0.iter().collect();
+        rows.sort_unstable_by_key(|(username, _)| *username);
+        for (username, tcs) in rows {
+            let Some((tc, games)) = tcs.favorite() else {
+                continue;
+            };
+            writeln!(
+                w,
+                "{}{d}{tc}{d}{games}{d}{:.3}",
+                escape(username, d),
+                tcs.share(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for FavoriteTcs {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        for player in [game.white, game.black] {
+            if player.is_bot || player.is_excluded || is_placeholder(&player.username) {
+                continue;
+            }
+            self.0
+                .entry(player.username.clone())
+                .or_default()
+                .add(game.tc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    #[test]
+    fn test_space_saving() {
+        let mut tcs = UserTcs::default();
+        for base in 0..TRACKED as u64 {
+            tcs.add(Tc::new((60 * (base + 1), 0)));
+        }
+        tcs.add(Tc::new((60, 0)));
+        // evicting the least played one, 120+0
+        tcs.add(Tc::new((15, 0)));
+        assert_eq!(tcs.games, 10);
+        assert_eq!(tcs.favorite(), Some((Tc::new((60, 0)), 2)));
+        assert!(tcs.tcs.contains(&(Tc::new((15, 0)), 2)));
+        assert!(!tcs.tcs.iter().any(|(tc, _)| tc.base == 120));
+    }
+
+    fn game(tc: &str, white: &str) -> String {
+        format!(
+            r#"[Event "Rated Blitz game"]
+[White "{white}"]
+[Black "bob"]
+[TimeControl "{tc}"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+
+"#
+        )
+    }
+
+    #[test]
+    fn test_favorite_tcs() {
+        let pgn = [
+            game("180+0", "alice"),
+            game("180+2", "alice"),
+            game("180+2", "alice"),
+            game("600+5", "carol"),
+        ]
+        .concat();
+        let mut favorites = FavoriteTcs::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut favorites])
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        assert_eq!(favorites.get("bob").unwrap().games, 4);
+        let mut w = Vec::new();
+        favorites.write_csv(&mut w, Delimiter::Comma).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,time_control,games,share\n\
+             alice,180+2,2,0.667\n\
+             bob,180+2,2,0.500\n\
+             carol,600+5,1,1.000\n"
+        );
+    }
+}
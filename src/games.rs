@@ -0,0 +1,98 @@
+//! One row per counted game, streamed as the games are read
+
+use std::io::{self, Write};
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    results::{escape, Delimiter},
+};
+
+/// Writes each game as soon as it is counted. The first write error is kept,
+/// and returned by `finish`
+pub struct PerGame<W: Write> {
+    w: W,
+    delimiter: Delimiter,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> PerGame<W> {
+    /// Writes the header right away
+    pub fn new(mut w: W, delimiter: Delimiter) -> io::Result<Self> {
+        let d = delimiter.as_char();
+        writeln!(
+            w,
+            "game_id{d}white{d}black{d}base{d}increment{d}perf{d}plies{d}real_time_ms"
+        )?;
+        Ok(Self {
+            w,
+            delimiter,
+            error: None,
+        })
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.w),
+        }
+    }
+
+    fn write(&mut self, game: &CountedGame<'_>) -> io::Result<()> {
+        let d = self.delimiter.as_char();
+        // the id ends the link, `https://lichess.org/abcd1234`
+        let id = game.link.rsplit('/').next().unwrap_or_default();
+        writeln!(
+            self.w,
+            "{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}",
+            escape(id, d),
+            escape(&game.white.username, d),
+            escape(&game.black.username, d),
+            game.tc.base,
+            game.tc.increment,
+            game.perf.name(),
+            game.plies,
+            game.exact_duration.as_millis()
+        )
+    }
+}
+
+impl<W: Write> Aggregator for PerGame<W> {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if self.error.is_none() {
+            self.error = self.write(game).err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[Site "https://lichess.org/abcd1234"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+"#;
+
+    #[test]
+    fn test_per_game() {
+        let mut per_game = PerGame::new(Vec::new(), Delimiter::Comma).unwrap();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut per_game])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let w = per_game.finish().unwrap();
+        let mut lines = std::str::from_utf8(&w).unwrap().lines().skip(1);
+        let row = lines.next().unwrap();
+        assert!(
+            row.starts_with("abcd1234,alice,bob,180,2,blitz,4,"),
+            "{row}"
+        );
+        assert_eq!(lines.next(), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Rating and time spent of each game of a set of users, to plot rating curves weighted by activity
+
+use std::io::{self, Write};
+
+use chrono::NaiveDate;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    usernames::Usernames,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Point {
+    pub date: NaiveDate,
+    pub rating: usize,
+    pub minutes: f64,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    username: &'a str,
+    history: &'a [Point],
+}
+
+/// Only the games of `users`, with a start date, are recorded, to bound the memory used
+#[derive(Debug)]
+pub struct RatingHistory {
+    users: Usernames,
+    histories: FxHashMap<String, Vec<Point>>,
+}
+
+impl RatingHistory {
+    pub fn new(users: Usernames) -> Self {
+        Self {
+            users,
+            histories: FxHashMap::default(),
+        }
+    }
+
+    pub fn get(&self, username: &str) -> Option<&[Point]> {
+        self.histories.get(username).map(Vec::as_slice)
+    }
+
+    /// One json object per user and line, `{"username":..,"history":[{"date":..,"rating":..,"minutes":..}]}`,
+    /// users being sorted and their history in the order of the pgn
+    pub fn write_ndjson(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut histories: Vec<_> = self.histories.iter().collect();
+        histories.sort_unstable_by_key(|(username, _)| *username);
+        for (username, history) in histories {
+            serde_json::to_writer(&mut *w, &Line { username, history })?;
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for RatingHistory {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        let Some(start) = game.start else {
+            return;
+        };
+        for player in [game.white, game.black] {
+            if self.users.contains(&player.username) {
+                self.histories
+                    .entry(player.username.clone())
+                    .or_default()
+                    .push(Point {
+                        date: start.date(),
+                        rating: player.rating.0,
+                        minutes: game.exact_duration.as_secs_f64() / 60.,
+                    });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[UTCDate "2023.01.31"]
+[UTCTime "12:00:00"]
+[White "alice"]
+[Black "bob"]
+[WhiteElo "2300"]
+[BlackElo "1500"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[UTCDate "2023.02.01"]
+[UTCTime "12:00:00"]
+[White "bob"]
+[Black "alice"]
+[WhiteElo "1490"]
+[BlackElo "2310"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:30] } 2... Nc6 { [%clk 0:02:30] } 0-1
+"#;
+
+    #[test]
+    fn test_rating_history() {
+        let mut history = RatingHistory::new(["alice".to_string()].into_iter().collect());
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut history])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert!(history.get("bob").is_none());
+        let mut w = Vec::new();
+        history.write_ndjson(&mut w).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            r#"{"username":"alice","history":[{"date":"2023-01-31","rating":2300,"minutes":0.5},{"date":"2023-02-01","rating":2310,"minutes":1.0}]}
+"#
+        );
+    }
+}
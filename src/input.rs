@@ -0,0 +1,328 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    time::Instant,
+};
+
+use clap::ValueEnum;
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use tracing::warn;
+
+/// Compression format of the input pgn file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zst,
+    Bz2,
+    Xz,
+    Gz,
+    Lz4,
+    Plain,
+}
+
+impl Compression {
+    const MAGICS: [(&'static [u8], Self); 5] = [
+        (&[0x28, 0xb5, 0x2f, 0xfd], Self::Zst),
+        (b"BZh", Self::Bz2),
+        (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], Self::Xz),
+        (&[0x1f, 0x8b], Self::Gz),
+        (&[0x04, 0x22, 0x4d, 0x18], Self::Lz4),
+    ];
+
+    fn from_magic(bytes: &[u8]) -> Option<Self> {
+        Self::MAGICS
+            .iter()
+            .find(|(magic, _)| bytes.starts_with(magic))
+            .map(|(_, compression)| *compression)
+    }
+
+    fn from_extension(path: &str) -> Self {
+        [
+            (".zst", Self::Zst),
+            (".bz2", Self::Bz2),
+            (".xz", Self::Xz),
+            (".gz", Self::Gz),
+            (".lz4", Self::Lz4),
+        ]
+        .into_iter()
+        .find(|(ext, _)| path.ends_with(ext))
+        .map_or(Self::Plain, |(_, compression)| compression)
+    }
+
+    // sniff the first bytes without consuming them, falling back to the extension
+    // if they are not conclusive (eg. plain pgn, or a pipe not filled yet)
+    fn detect(path: &str, reader: &mut impl BufRead) -> io::Result<Self> {
+        Ok(Self::from_magic(reader.fill_buf()?).unwrap_or_else(|| Self::from_extension(path)))
+    }
+
+    fn decoder<'a>(
+        self,
+        mut reader: impl BufRead + 'a,
+        decoding: Decoding,
+    ) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match (self, decoding) {
+            (Self::Zst, Decoding::ZstdWindowLog(log)) => {
+                let mut decoder = zstd::Decoder::with_buffer(reader)?;
+                decoder.window_log_max(log)?;
+                Box::new(decoder)
+            }
+            (Self::Zst, _) => Box::new(zstd::Decoder::with_buffer(reader)?),
+            (Self::Bz2, _) => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+            (Self::Xz, _) => Box::new(xz2::bufread::XzDecoder::new(reader)),
+            (Self::Gz, _) => Box::new(flate2::bufread::GzDecoder::new(reader)),
+            (Self::Lz4, Decoding::Lz4Blocks) => {
+                let block = lz4_block_size(reader.fill_buf()?);
+                Box::new(BufReader::with_capacity(block, lz4::Decoder::new(reader)?))
+            }
+            (Self::Lz4, _) => Box::new(lz4::Decoder::new(reader)?),
+            (Self::Plain, _) => Box::new(reader),
+        })
+    }
+
+    /// The decoder settings worth comparing for this format
+    fn decodings(self) -> Vec<Decoding> {
+        match self {
+            Self::Zst => vec![Decoding::Default, Decoding::ZstdWindowLog(31)],
+            Self::Lz4 => vec![Decoding::Default, Decoding::Lz4Blocks],
+            _ => vec![Decoding::Default],
+        }
+    }
+}
+
+/// Maximum block size of an lz4 frame, from its descriptor, 4 MiB if it cannot be read
+fn lz4_block_size(frame: &[u8]) -> usize {
+    match frame.get(5).map(|bd| (bd >> 4) & 0b111) {
+        Some(id @ 4..=7) => 1 << (8 + 2 * id),
+        _ => 4 << 20,
+    }
+}
+
+/// Setting of the decoder of the compressed input, beyond its read buffer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Decoding {
+    #[default]
+    Default,
+    /// zstd frames with windows of up to 2^n bytes are accepted, like with `zstd --long=n`,
+    /// instead of up to 2^27 bytes
+    ZstdWindowLog(u32),
+    /// lz4 frames are decoded a whole block at a time, in a buffer of the maximum block size
+    /// of their frame, rather than in the smaller reads of the pgn parser
+    Lz4Blocks,
+}
+
+impl fmt::Display for Decoding {
+    /// As the command line flag selecting it
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => f.write_str("default"),
+            Self::ZstdWindowLog(log) => write!(f, "--zstd-window-log {log}"),
+            Self::Lz4Blocks => f.write_str("--lz4-blocks"),
+        }
+    }
+}
+
+/// Capacity of the buffer of the compressed input, that of `BufReader`
+pub const DEFAULT_BUFFER: usize = 8 * 1024;
+
+/// Open the pgn at `path`, decompressing it on the fly.
+/// If `format` is `None`, the compression is guessed from the file content.
+/// If `pb` is set, it is advanced by the number of (compressed) bytes read
+pub fn open(
+    path: &str,
+    format: Option<Compression>,
+    pb: Option<&ProgressBar>,
+) -> io::Result<Box<dyn Read>> {
+    open_with(path, format, pb, DEFAULT_BUFFER, Decoding::Default)
+}
+
+/// Like `open`, reading the compressed input `buffer` bytes at a time, with the `decoding`
+/// setting
+pub fn open_with(
+    path: &str,
+    format: Option<Compression>,
+    pb: Option<&ProgressBar>,
+    buffer: usize,
+    decoding: Decoding,
+) -> io::Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    let counted: Box<dyn Read> = match pb {
+        Some(pb) => Box::new(pb.wrap_read(file)),
+        None => Box::new(file),
+    };
+    let mut reader = BufReader::with_capacity(buffer, counted);
+    let compression = match format {
+        Some(compression) => compression,
+        None => Compression::detect(path, &mut reader)?,
+    };
+    compression.decoder(reader, decoding)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Throughput {
+    pub buffer: usize,
+    pub decoding: Decoding,
+    /// in MB per second
+    pub compressed: f64,
+    pub uncompressed: f64,
+}
+
+/// Decompression throughput of the start of the pgn, up to `max_bytes` decompressed, for each
+/// input buffer size and decoder setting of its format. The configurations failing to decode it,
+/// like a zstd window too small, are left out with a warning. Sorted by decreasing throughput
+pub fn bench(
+    path: &str,
+    format: Option<Compression>,
+    buffers: &[usize],
+    max_bytes: u64,
+) -> io::Result<Vec<Throughput>> {
+    let compression = match format {
+        Some(compression) => compression,
+        None => Compression::detect(path, &mut BufReader::new(File::open(path)?))?,
+    };
+    let mut throughputs = Vec::new();
+    for decoding in compression.decodings() {
+        for &buffer in buffers {
+            let pb = ProgressBar::hidden();
+            let start = Instant::now();
+            let uncompressed = open_with(path, Some(compression), Some(&pb), buffer, decoding)
+                .and_then(|reader| io::copy(&mut reader.take(max_bytes), &mut io::sink()));
+            let uncompressed = match uncompressed {
+                Ok(uncompressed) => uncompressed,
+                Err(e) => {
+                    warn!(buffer, %decoding, "cannot decompress: {e}");
+                    continue;
+                }
+            };
+            let secs = start.elapsed().as_secs_f64().max(1e-9);
+            throughputs.push(Throughput {
+                buffer,
+                decoding,
+                compressed: pb.position() as f64 / secs / 1e6,
+                uncompressed: uncompressed as f64 / secs / 1e6,
+            });
+        }
+    }
+    throughputs.sort_by(|a, b| b.compressed.total_cmp(&a.compressed));
+    Ok(throughputs)
+}
+
+/// Count the games of a pgn by looking for `[Event ` headers at the start of a line,
+/// without parsing the pgn
+pub fn count_games(reader: impl Read) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(1 << 20, reader);
+    let mut line = Vec::new();
+    let mut nb_games = 0;
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        if line.starts_with(b"[Event ") {
+            nb_games += 1;
+        }
+        line.clear();
+    }
+    Ok(nb_games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_games() {
+        let pgn = b"[Event \"Rated Blitz game\"]\n[Site \"https://lichess.org/abc\"]\n\n1. e4 { [%clk 0:03:00] } 1-0\n\n[Event \"Casual Bullet game\"]\n\n1. d4 0-1\n";
+        assert_eq!(count_games(&pgn[..]).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_detect_magic_over_extension() {
+        let mut zst: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x00];
+        assert_eq!(
+            Compression::detect("/dev/fd/63", &mut zst).unwrap(),
+            Compression::Zst
+        );
+        let mut gz: &[u8] = &[0x1f, 0x8b, 0x08];
+        assert_eq!(
+            Compression::detect("renamed.pgn.zst", &mut gz).unwrap(),
+            Compression::Gz
+        );
+    }
+
+    fn bench_of(name: &str, content: &[u8]) -> Vec<Throughput> {
+        let path = std::env::temp_dir().join(format!("bench-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let throughputs = bench(path.to_str().unwrap(), None, &[16, 1 << 20], 1 << 30).unwrap();
+        std::fs::remove_file(path).unwrap();
+        throughputs
+    }
+
+    fn configurations(throughputs: &[Throughput]) -> Vec<(usize, String)> {
+        let mut configurations: Vec<_> = throughputs
+            .iter()
+            .map(|t| (t.buffer, t.decoding.to_string()))
+            .collect();
+        configurations.sort();
+        configurations
+    }
+
+    #[test]
+    fn test_bench() {
+        let pgn = "[Event \"Rated Blitz game\"]\n\n1. e4 1-0\n".repeat(1000);
+        let plain = bench_of("plain.pgn", pgn.as_bytes());
+        assert_eq!(
+            configurations(&plain),
+            [
+                (16, "default".to_string()),
+                (1 << 20, "default".to_string())
+            ]
+        );
+        assert!(plain
+            .iter()
+            .all(|t| t.compressed > 0. && t.compressed == t.uncompressed));
+
+        let zst = bench_of("pgn.zst", &zstd::encode_all(pgn.as_bytes(), 3).unwrap());
+        let mut lz4 = lz4::EncoderBuilder::new().build(Vec::new()).unwrap();
+        io::Write::write_all(&mut lz4, pgn.as_bytes()).unwrap();
+        let lz4 = bench_of("pgn.lz4", &lz4.finish().0);
+        for (throughputs, setting) in [(zst, "--zstd-window-log 31"), (lz4, "--lz4-blocks")] {
+            let expected = [16, 1 << 20]
+                .into_iter()
+                .flat_map(|buffer| {
+                    [
+                        (buffer, setting.to_string()),
+                        (buffer, "default".to_string()),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(configurations(&throughputs), expected);
+            // the pgn compresses well
+            assert!(throughputs
+                .iter()
+                .all(|t| t.compressed > 0. && t.uncompressed > t.compressed));
+        }
+    }
+
+    #[test]
+    fn test_lz4_block_size() {
+        let frame = [0x04, 0x22, 0x4d, 0x18, 0x64, 0x40];
+        assert_eq!(lz4_block_size(&frame), 64 << 10);
+        assert_eq!(
+            lz4_block_size(&[0x04, 0x22, 0x4d, 0x18, 0x64, 0x70]),
+            4 << 20
+        );
+        assert_eq!(lz4_block_size(&[]), 4 << 20);
+    }
+
+    #[test]
+    fn test_detect_fallback_extension() {
+        let mut pgn: &[u8] = b"[Event \"Rated Blitz game\"]";
+        assert_eq!(
+            Compression::detect("lichess.pgn", &mut pgn).unwrap(),
+            Compression::Plain
+        );
+        let mut empty: &[u8] = b"";
+        assert_eq!(
+            Compression::detect("lichess.pgn.bz2", &mut empty).unwrap(),
+            Compression::Bz2
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Extracting time spent playing for each player from pgn files
+
+pub mod activity;
+pub mod aliases;
+pub mod analysis;
+pub mod anomalies;
+pub mod calibration;
+pub mod clickhouse;
+pub mod colors;
+pub mod columnar;
+pub mod columns;
+pub mod coverage;
+pub mod cumulative;
+pub mod diff;
+pub mod durations;
+pub mod endings;
+pub mod favorite_tcs;
+pub mod games;
+pub mod history;
+pub mod input;
+pub mod longest;
+pub mod move_stats;
+pub mod moves;
+pub mod openings;
+pub mod outcomes;
+pub mod output;
+pub mod pg;
+pub mod placeholders;
+pub mod plot;
+pub mod quantiles;
+pub mod report;
+pub mod results;
+pub mod schema;
+pub mod sessions;
+pub mod skips;
+pub mod sql;
+pub mod sqlite;
+pub mod state;
+pub mod summary;
+pub mod terminations;
+pub mod time_controls;
+pub mod timestamp;
+pub mod usernames;
+pub mod variants;
+pub mod visitor;
+pub mod watch;
+pub mod xlsx;
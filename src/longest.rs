@@ -0,0 +1,133 @@
+//! Longest game of each user, per perf
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    placeholders::is_placeholder,
+    results::{escape, CsvOptions},
+    visitor::Perf,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct LongestGame {
+    pub duration: Duration,
+    /// from the `Site` header
+    pub link: String,
+}
+
+/// Real duration and link of the longest game of each user in each perf, among the games with
+/// clocks. The first one read is kept on ties
+#[derive(Default, Debug)]
+pub struct LongestGames(FxHashMap<(String, Perf), LongestGame>);
+
+impl LongestGames {
+    pub fn get(&self, username: &str, perf: Perf) -> Option<&LongestGame> {
+        self.0.get(&(username.to_string(), perf))
+    }
+
+    /// By username then perf
+    pub fn write_csv(&self, w: &mut impl Write, options: &CsvOptions) -> io::Result<()> {
+        let d = options.delimiter.as_char();
+        writeln!(w, "username{d}perf{d}real_time{d}link")?;
+        let mut rows: Vec<_> = self.0.iter().collect();
+        rows.sort_unstable_by_key(|((username, perf), _)| {
+            let p = Perf::ALL.iter().position(|p| p == perf);
+            (username, p)
+        });
+        for ((username, perf), game) in rows {
+            writeln!(
+                w,
+                "{}{d}{}{d}{}{d}{}",
+                escape(username, d),
+                perf.name(),
+                options.time_unit.display(game.duration),
+                escape(&game.link, d),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for LongestGames {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if !game.has_clocks {
+            return;
+        }
+        for player in [game.white, game.black] {
+            if player.is_bot || player.is_excluded || is_placeholder(&player.username) {
+                continue;
+            }
+            let longest = self
+                .0
+                .entry((player.username.clone(), game.perf))
+                .or_default();
+            if game.exact_duration > longest.duration || longest.link.is_empty() {
+                longest.duration = game.exact_duration;
+                longest.link = game.link.to_string();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[Site "https://lichess.org/aaaaaaaa"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[Site "https://lichess.org/bbbbbbbb"]
+[White "bob"]
+[Black "carol"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:00] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[Site "https://lichess.org/cccccccc"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+"#;
+
+    #[test]
+    fn test_longest_games() {
+        let mut longest = LongestGames::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut longest])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(
+            longest.get("bob", Perf::Blitz),
+            Some(&LongestGame {
+                duration: Duration::from_secs(80),
+                link: "https://lichess.org/bbbbbbbb".to_string(),
+            })
+        );
+        let mut w = Vec::new();
+        longest.write_csv(&mut w, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,perf,real_time,link\n\
+             alice,blitz,30,https://lichess.org/aaaaaaaa\n\
+             bob,blitz,80,https://lichess.org/bbbbbbbb\n\
+             carol,blitz,80,https://lichess.org/bbbbbbbb\n"
+        );
+    }
+}
@@ -1,70 +1,1306 @@
 //! Extracting time spent playing for each player from pgn files
 
 use std::{
-    env,
-    fs::File,
-    io::{self, BufWriter, Write},
-    writeln,
+    env, fs,
+    io::{self, IsTerminal, Write},
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
 };
 
-use indicatif::{ProgressBar, ProgressStyle};
-use pgn_reader::BufferedReader;
+use chrono::NaiveDate;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use tracing::{info, info_span, level_filters::LevelFilter, warn};
+use username_time_spent::{
+    activity::Activity,
+    aliases::{self, Aliases},
+    analysis::{Aggregator, AnalysisBuilder, Attribution, Buckets},
+    anomalies::ClockAnomalies,
+    calibration::Calibration,
+    clickhouse, colors, columnar,
+    columns::{Column, TimeUnit},
+    coverage::ClockCoverage,
+    cumulative::DailyTotals,
+    diff,
+    durations::GameDurations,
+    endings::EndingClocks,
+    favorite_tcs::FavoriteTcs,
+    games::PerGame,
+    history::RatingHistory,
+    input,
+    longest::LongestGames,
+    move_stats::{MoveStats, TIME_TROUBLE},
+    moves::MoveTimes,
+    openings::{EcoNames, OpeningTimes},
+    outcomes::OutcomeTimes,
+    output::{Compress, Output},
+    pg,
+    placeholders::Placeholders,
+    plot::{
+        self, Histogram, Lorenz, PlotFormat, PlotKind, PlotSize, PlotStyle, RatingDensity, Theme,
+        Top,
+    },
+    report,
+    results::{self, CsvOptions, Delimiter, OutputFormat, SortBy},
+    schema::Schema,
+    sessions::Sessions,
+    skips::SkipCounts,
+    sqlite, state,
+    summary::Summary,
+    terminations::Terminations,
+    time_controls::TcTimes,
+    timestamp::Zone,
+    usernames::{self, Usernames},
+    variants::VariantTimes,
+    visitor::{Perf, Users},
+    watch::Watcher,
+};
+
+mod config;
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Extract time spent playing for each player from pgn files",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path to the pgn file, possibly compressed
+    #[arg(required_unless_present_any = ["watch", "from_state"])]
+    pgn: Option<String>,
+    /// Total number of games in the pgn. If not set, the progress is estimated from the bytes read
+    nb_games: Option<u64>,
+    /// Compression of the pgn, guessed from its first bytes if not set
+    #[arg(long, value_enum)]
+    format: Option<input::Compression>,
+    /// Count the games with a fast first pass over the file, for an exact time estimate
+    #[arg(long, conflicts_with = "nb_games")]
+    count_first: bool,
+    /// `main,alt` csv file, alt accounts are merged into their main one.
+    /// The contribution of each alt is written to `time-spent-aliases.csv`
+    #[arg(long)]
+    aliases: Option<String>,
+    /// Where to write the results, `-` for stdout
+    /// [default: time-spent.csv, or the extension of the other --output-format]
+    #[arg(long)]
+    output: Option<String>,
+    /// Format of the results [default: csv]
+    #[arg(long, value_enum)]
+    output_format: Option<OutputFormat>,
+    /// Add the results to the sqlite or parquet --output as those of this period, like `2024-01`,
+    /// replacing the rows of the same users and period
+    #[arg(long)]
+    append: Option<String>,
+    /// Results of a previous run, in seconds, whose games and times are added to the new ones
+    #[arg(long)]
+    merge: Option<String>,
+    /// Dump the raw per-user state once the pgn is read, before --merge, --aliases and
+    /// --min-games, so it can be combined with others by --from-state
+    #[arg(long)]
+    save_state: Option<String>,
+    /// States written by --save-state, merged and written as results instead of reading a pgn
+    #[arg(long, num_args = 1.., conflicts_with_all = [
+        "pgn", "count_first", "validate", "clock_anomalies", "rating_history", "sessions",
+        "clock_coverage", "endings", "terminations", "variants", "outcome_times", "longest_games",
+        "move_stats", "openings", "time_controls", "favorite_tcs", "summary", "per_game",
+        "move_times", "report_md", "watch",
+        "bench_io", "save_state", "plot_durations", "plot_activity", "plot_cumulative",
+        "plot_calibration",
+    ])]
+    from_state: Option<Vec<String>>,
+    /// Compress the results, adding the extension to the default output
+    #[arg(long, value_enum)]
+    compress: Option<Compress>,
+    /// `postgres://` url of a database where to bulk-load the results at the end of the run,
+    /// in addition to the output
+    #[arg(long)]
+    postgres: Option<String>,
+    /// Table of the database where to load the results, created if needed
+    /// [default: time_spent]
+    #[arg(long)]
+    postgres_table: Option<String>,
+    /// Add the games and times to the users already in the table, like when merging months,
+    /// instead of failing
+    #[arg(long, requires = "postgres")]
+    postgres_upsert: bool,
+    /// Url of the HTTP interface of a ClickHouse server where to insert the results at the end
+    /// of the run, in addition to the output
+    #[arg(long)]
+    clickhouse: Option<String>,
+    /// Table of the ClickHouse server where to insert the results, created if needed
+    /// [default: time_spent]
+    #[arg(long)]
+    clickhouse_table: Option<String>,
+    /// Write to this sql file a DuckDB `time_spent` view over the parquet results,
+    /// with the times as intervals
+    #[arg(long)]
+    duckdb_view: Option<String>,
+    /// Write to this json file the name, type and unit of every column of the csv, parquet or
+    /// arrow results, with the perf buckets, the inputs and the counts of skipped games
+    #[arg(long)]
+    schema: Option<String>,
+    /// Inclusive upper bounds, in seconds of estimated game duration, of the
+    /// `ultrabullet,bullet,blitz,rapid` perfs [default: 29,179,479,1499]
+    #[arg(long)]
+    buckets: Option<Buckets>,
+    /// How the real time of a game is credited to its players [default: game_duration]
+    #[arg(long, value_enum)]
+    attribution: Option<Attribution>,
+    /// Estimated thinking time in seconds of each move of the correspondence games, which
+    /// have no clocks [default: 60]
+    #[arg(long)]
+    correspondence_move: Option<u64>,
+    /// Field separator of the csv outputs [default: comma]
+    #[arg(long, value_enum)]
+    delimiter: Option<Delimiter>,
+    /// Comma-separated columns emitted for each perf
+    /// [default: all, or games,rating,approx with --approx-only]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    columns: Option<Vec<Column>>,
+    /// Unit of the durations of the csv, json, ndjson and xlsx outputs [default: seconds]
+    #[arg(long, value_enum)]
+    time_unit: Option<TimeUnit>,
+    /// Write to this csv file the daily number of games whose clocks both jumped,
+    /// typical of server lag
+    #[arg(long)]
+    clock_anomalies: Option<String>,
+    /// File of usernames, one per line. Only the games involving them are counted,
+    /// and only them are written
+    #[arg(long)]
+    users_file: Option<String>,
+    /// Only count the games of this user, skipping the others before their moves are parsed,
+    /// and write their profile to `--profile`
+    #[arg(long, conflicts_with = "users_file")]
+    only_user: Option<String>,
+    /// Do not decode the headers following `White` and `Black` in the games of other users.
+    /// Their moves are skipped either way, so only the header decoding is saved
+    #[arg(long, requires = "only_user")]
+    fast: bool,
+    /// Where to write the profile of the `--only-user`, `-` for stdout [default: -]
+    #[arg(long, requires = "only_user")]
+    profile: Option<String>,
+    /// Write to this ndjson file the date, rating and minutes spent of every game
+    /// of the users of `--users-file`, one line per user
+    #[arg(long)]
+    rating_history: Option<String>,
+    /// Write to this csv file the number of sessions of each user and perf,
+    /// with their median and max number of games
+    #[arg(long)]
+    sessions: Option<String>,
+    /// Minutes without playing a perf after which a new session starts [default: 30]
+    #[arg(long)]
+    session_gap: Option<u64>,
+    /// File of usernames, one per line, which are never credited, like known bots or cheaters
+    #[arg(long)]
+    exclude_file: Option<String>,
+    /// Write to this csv file the games and time spent of each user as white and as black,
+    /// flagging significant imbalances
+    #[arg(long)]
+    colors: Option<String>,
+    /// Write to this csv file the number of games per perf, and how many of them have clocks
+    #[arg(long)]
+    clock_coverage: Option<String>,
+    /// Write to this csv file, or json if it ends with `.json`, the games, players, real and
+    /// approximate hours and average game duration per perf and overall
+    #[arg(long)]
+    summary: Option<String>,
+    /// Write to this csv file one row per counted game, as the games are read: its id, players,
+    /// time control, perf, plies and real time
+    #[arg(long)]
+    per_game: Option<String>,
+    /// Write to this csv file the think time of every move of the games with clocks, one game
+    /// per line. Compressed if it ends with `.zst` or `.gz`
+    #[arg(long, conflicts_with = "approx_only")]
+    move_times: Option<String>,
+    /// Write to this markdown file the top players by real time, the totals per perf and the
+    /// skipped games, ready to paste in a forum post
+    #[arg(long)]
+    report_md: Option<String>,
+    /// Write to this svg file the histogram of the users by real time played, per perf
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_histogram: Option<String>,
+    /// Write to this svg file the density of the users by average rating and real time played,
+    /// per perf
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_rating: Option<String>,
+    /// Write to this svg file the distribution of the real durations of the games with clocks,
+    /// against their approximate ones, per perf
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_durations: Option<String>,
+    /// Write to this svg file the Lorenz curves of the real time of the users, overall and per
+    /// perf, with their Gini coefficient
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_lorenz: Option<String>,
+    /// Write to this svg file the heatmap of the real time played by hour of the day and day of
+    /// the week, in the --utc-offset timezone
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_activity: Option<String>,
+    /// Write to this svg file the cumulative real time played over the days of the month, one
+    /// curve per month, in the --utc-offset timezone
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_cumulative: Option<String>,
+    /// Write to this svg file the bar chart of the --plot-top-n users with the most real time
+    /// played, per perf
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_top: Option<String>,
+    /// Users per perf in --plot-top [default: 10]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    plot_top_n: Option<u64>,
+    /// Write to this svg file the ratio of the real time of the games with clocks to its
+    /// approximation, for the most played time controls
+    #[arg(long, conflicts_with = "approx_only")]
+    plot_calibration: Option<String>,
+    /// Format of the figures [default: png for the paths ending with `.png`, svg otherwise]
+    #[arg(long, value_enum)]
+    plot_format: Option<PlotFormat>,
+    /// Width and height of the figures in pixels, like `1920x1080` [default: 1024x768]
+    #[arg(long)]
+    plot_size: Option<PlotSize>,
+    /// Colors of the figures [default: light]
+    #[arg(long, value_enum)]
+    plot_theme: Option<Theme>,
+    /// Write to this csv file the min, max and mean clock left to the loser of decisive games,
+    /// per perf and by resignation, checkmate or flag
+    #[arg(long)]
+    endings: Option<String>,
+    /// Write to this csv file the games of each user per perf by termination, and those they
+    /// lost and won on time
+    #[arg(long)]
+    terminations: Option<String>,
+    /// Write to this csv file the games and time of each user per variant, the games of
+    /// variants being otherwise skipped
+    #[arg(long)]
+    variants: Option<String>,
+    /// Write to this csv file the real time of each user per perf spent in the games they won,
+    /// drew and lost
+    #[arg(long, conflicts_with = "approx_only")]
+    outcome_times: Option<String>,
+    /// Write to this csv file the real time and link of the longest game with clocks of each
+    /// user, per perf
+    #[arg(long, conflicts_with = "approx_only")]
+    longest_games: Option<String>,
+    /// Write to this csv file the moves of each user per perf with clocks, and how many of them
+    /// were played in time trouble or instantly
+    #[arg(long, conflicts_with = "approx_only")]
+    move_stats: Option<String>,
+    /// Clock in seconds below which a move is played in time trouble in --move-stats
+    /// [default: 10]
+    #[arg(long)]
+    time_trouble: Option<u64>,
+    /// Write to this csv file the games and time per opening family, from the `Opening` header
+    /// or else the family of the `ECO` code, both human players being credited the game duration
+    #[arg(long)]
+    openings: Option<String>,
+    /// Break --openings down per user
+    #[arg(long, requires = "openings")]
+    openings_per_user: bool,
+    /// Tsv of opening names per ECO code, in the format of the lichess chess-openings files,
+    /// naming the --openings without an `Opening` header instead of the bundled families
+    #[arg(long, requires = "openings")]
+    eco_names: Option<String>,
+    /// Write to this csv file the games and time of each exact time control, like `180+2`, per
+    /// perf
+    #[arg(long)]
+    time_controls: Option<String>,
+    /// Write to this csv file the time control each user played the most, and its share of
+    /// their games
+    #[arg(long)]
+    favorite_tcs: Option<String>,
+    /// Skip the games where both players are rated below this
+    #[arg(long)]
+    min_rating: Option<usize>,
+    /// Skip the games where both players are rated above this
+    #[arg(long)]
+    max_rating: Option<usize>,
+    /// Comma-separated perfs to count, the games of the others being skipped [default: all]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    perfs: Option<Vec<Perf>>,
+    /// Stop reading after this many games, and write what was accumulated
+    #[arg(long)]
+    max_games: Option<u64>,
+    /// Only count each game with a probability of 1/N, picked from a hash of its `Site` link,
+    /// for quick exploratory runs
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    sample: Option<u64>,
+    /// Skip the games involving a BOT account. Otherwise only the bot side is not credited
+    #[arg(long)]
+    no_bots: bool,
+    /// Only count rated games
+    #[arg(long, conflicts_with = "casual_only")]
+    rated_only: bool,
+    /// Only count casual games
+    #[arg(long)]
+    casual_only: bool,
+    /// Skip the games started before this date, like `2024-03-01`
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Skip the games started after this date, like `2024-03-15`
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    /// Games with more plies are deemed pathological, reported and skipped [default: no limit]
+    #[arg(long)]
+    max_plies: Option<u64>,
+    /// Skip the games with a clock/ply mismatch, whose number of clocks is not their number of
+    /// plies, which are otherwise counted and reported. Games without clocks are not checked
+    #[arg(long)]
+    skip_clock_mismatches: bool,
+    /// Only write the users with at least this many games, over all perfs
+    #[arg(long)]
+    min_games: Option<usize>,
+    /// Order of the result rows, unordered if not set
+    #[arg(long, value_enum)]
+    sort_by: Option<SortBy>,
+    /// Timezone of the date-based groupings, as an offset like `+02:00`
+    /// or a name like `Europe/Paris` [default: UTC]
+    #[arg(long, visible_alias = "timezone")]
+    utc_offset: Option<Zone>,
+    /// Only report how many games would be counted or skipped, and why, without writing any output
+    #[arg(long)]
+    validate: bool,
+    /// Ignore the clock comments, for dumps without them: only the approximate time is computed
+    #[arg(long)]
+    approx_only: bool,
+    /// Directory whose `.pgn.zst` files are processed as they are downloaded, their results
+    /// being merged into the output after each one. Runs until interrupted
+    #[arg(long, conflicts_with_all = [
+        "pgn", "count_first", "aliases", "validate", "only_user", "min_games", "clock_anomalies",
+        "rating_history", "sessions", "clock_coverage", "endings", "terminations", "variants",
+        "outcome_times", "longest_games", "move_stats", "openings", "time_controls",
+        "favorite_tcs",
+        "colors",
+        "postgres",
+        "clickhouse", "summary", "per_game", "append",
+        "move_times", "report_md", "schema", "plot_histogram",
+        "plot_rating", "plot_durations", "plot_lorenz",
+        "plot_activity", "plot_cumulative", "plot_top", "plot_calibration",
+    ])]
+    watch: Option<String>,
+    /// Size in KiB of the buffer the compressed pgn is read with [default: 8]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    read_buffer: Option<u64>,
+    /// Accept zstd windows of up to 2^N bytes, like `zstd --long=N`, instead of 2^27
+    #[arg(long, value_parser = clap::value_parser!(u32).range(10..=31))]
+    zstd_window_log: Option<u32>,
+    /// Decode lz4 frames a whole block at a time
+    #[arg(long, conflicts_with = "zstd_window_log")]
+    lz4_blocks: bool,
+    /// Measure how fast the pgn is decompressed with various --read-buffer sizes and decoder
+    /// settings, without counting any game
+    #[arg(long)]
+    bench_io: bool,
+    /// Log the progress with `-v`, and every skipped game with `-vv`
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Toml file of options, named like their command line flag.
+    /// Flags given on the command line take precedence
+    #[arg(long)]
+    config: Option<String>,
+}
 
-mod visitor;
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare two results, printing per-user and per-perf differences as csv
+    Diff {
+        /// Older `time-spent.csv`
+        before: String,
+        /// Newer `time-spent.csv`
+        after: String,
+    },
+    /// Draw a figure from results, without reading the pgn again
+    Plot {
+        /// `time-spent.csv`, in seconds
+        results: String,
+        /// Which figure to draw
+        #[arg(long, value_enum)]
+        kind: PlotKind,
+        /// Where to write the figure [default: time-spent-<kind>.svg, or .png]
+        #[arg(long)]
+        output: Option<String>,
+        /// Users per perf with `--kind top` [default: 10]
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        top_n: Option<u64>,
+        /// Format of the figure [default: png for the paths ending with `.png`, svg otherwise]
+        #[arg(long, value_enum)]
+        format: Option<PlotFormat>,
+        /// Width and height of the figure in pixels, like `1920x1080` [default: 1024x768]
+        #[arg(long)]
+        size: Option<PlotSize>,
+        /// Colors of the figure [default: light]
+        #[arg(long, value_enum)]
+        theme: Option<Theme>,
+    },
+}
 
-pub fn get_progress_bar(nb_games: u64) -> ProgressBar {
-    let pb = ProgressBar::new(nb_games);
+/// What the progress bar is counting
+pub enum Progress {
+    Games(u64),
+    /// compressed bytes of the input file
+    Bytes(u64),
+    /// compressed bytes of an input of unknown size, like a pipe, without a bar nor eta
+    Stream,
+}
+
+impl Progress {
+    /// By bytes for a regular file, `/dev/fd/*` and pipes having no length
+    fn of_input(path: impl AsRef<Path>) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(if metadata.is_file() && metadata.len() > 0 {
+            Self::Bytes(metadata.len())
+        } else {
+            Self::Stream
+        })
+    }
+}
+
+pub fn get_progress_bar(bars: &MultiProgress, progress: Progress) -> ProgressBar {
+    let (pb, counter) = match progress {
+        Progress::Games(nb_games) => (
+            ProgressBar::new(nb_games),
+            "[{wide_bar:.cyan/blue}] {pos}/{len} {per_sec} ({eta})",
+        ),
+        Progress::Bytes(file_size) => (
+            ProgressBar::new(file_size),
+            "[{wide_bar:.cyan/blue}] {bytes}/{total_bytes} {binary_bytes_per_sec} ({eta})",
+        ),
+        Progress::Stream => (ProgressBar::new_spinner(), "{bytes} {binary_bytes_per_sec}"),
+    };
     pb.set_style(
-            ProgressStyle::with_template(
-                "{msg} {spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} ({eta})",
-            )
-            .expect("Invalid indicatif template syntax")
-            .progress_chars("#>-"),
-        );
-    pb
+        ProgressStyle::with_template(&format!(
+            "{{msg}} {{spinner:.green}} [{{elapsed_precise}}] {counter}"
+        ))
+        .expect("Invalid indicatif template syntax")
+        .progress_chars("#>-"),
+    );
+    bars.add(pb)
+}
+
+/// Logs are written to stderr above the progress bars
+struct LogWriter(MultiProgress);
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+fn init_logs(verbose: u8, bars: &MultiProgress) {
+    let level = match verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let bars = bars.clone();
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .with_ansi(io::stderr().is_terminal())
+        .with_writer(move || LogWriter(bars.clone()))
+        .init();
 }
 
 fn main() -> io::Result<()> {
-    let mut args = env::args();
-    let path = args.nth(1).expect("pgn path expected");
-    let nb_games = args
-        .next()
-        .and_then(|s| u64::from_str_radix(&s, 10).ok())
-        .expect("input total number of games from the pgn, to get proper time estimate");
-    let file = File::open(&path).expect("fopen");
-
-    let uncompressed: Box<dyn io::Read> = if path.ends_with(".zst") {
-        Box::new(zstd::Decoder::new(file).expect("zst decoder"))
-    } else if path.ends_with(".bz2") {
-        Box::new(bzip2::read::MultiBzDecoder::new(file))
-    } else if path.ends_with(".xz") {
-        Box::new(xz2::read::XzDecoder::new(file))
-    } else if path.ends_with(".gz") {
-        Box::new(flate2::read::GzDecoder::new(file))
-    } else if path.ends_with(".lz4") {
-        Box::new(lz4::Decoder::new(file).expect("lz4 decoder"))
+    let args = Args::parse_from(config::with_config_file(
+        Args::command(),
+        env::args_os().collect(),
+    )?);
+    // never on stdout, which can be used for the results
+    let bars = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+    init_logs(args.verbose, &bars);
+    match args.command {
+        Some(Command::Diff {
+            ref before,
+            ref after,
+        }) => diff::run(before, after),
+        Some(Command::Plot {
+            ref results,
+            kind,
+            ref output,
+            top_n,
+            format,
+            size,
+            theme,
+        }) => {
+            let style = PlotStyle {
+                format,
+                size: size.unwrap_or_default(),
+                theme: theme.unwrap_or_default(),
+            };
+            plot::run(
+                results,
+                kind,
+                output.as_deref(),
+                top_n.map_or(plot::TOP_N, |n| n as usize),
+                &style,
+            )
+        }
+        None => run(args, &bars),
+    }
+}
+
+fn run(args: Args, bars: &MultiProgress) -> io::Result<()> {
+    if args.duckdb_view.is_some()
+        && (args.output_format != Some(OutputFormat::Parquet)
+            || args.output.as_deref() == Some("-")
+            || args.compress.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--duckdb-view requires --output-format parquet written to an uncompressed file",
+        ));
+    }
+    if args.schema.is_some()
+        && !matches!(
+            args.output_format.unwrap_or_default(),
+            OutputFormat::Csv | OutputFormat::Parquet | OutputFormat::Arrow
+        )
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--schema only describes csv, parquet and arrow results",
+        ));
+    }
+    if args.append.is_some()
+        && (!matches!(
+            args.output_format,
+            Some(OutputFormat::Sqlite | OutputFormat::Parquet)
+        ) || args.output.as_deref() == Some("-")
+            || args.compress.is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--append requires --output-format sqlite or parquet written to an uncompressed file",
+        ));
+    }
+    if args.compress.is_some() && args.output_format == Some(OutputFormat::Sqlite) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "sqlite databases cannot be compressed",
+        ));
+    }
+    if let Some(dir) = args.watch.clone() {
+        return watch(args, &dir, bars);
+    }
+    if let Some(paths) = &args.from_state {
+        return from_state(&args, paths);
+    }
+    let pgn = args.pgn.clone().expect("pgn path required by clap");
+    let _span = info_span!("pgn", path = %pgn).entered();
+    if args.bench_io {
+        return bench_io(&pgn, args.format);
+    }
+    let read_buffer = read_buffer(&args);
+    let csv_options = csv_options(&args)?;
+    // loaded before the long parsing, to fail early on invalid file
+    let aliases = args.aliases.as_deref().map(aliases::load).transpose()?;
+    let previous = load_previous(&args)?;
+    let (users, excluded) = load_usernames(&args)?;
+    let mut history = match (&args.rating_history, &users) {
+        (Some(_), Some(users)) => Some(RatingHistory::new(users.clone())),
+        (Some(_), None) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--rating-history requires --users-file",
+            ))
+        }
+        (None, _) => None,
+    };
+    let nb_games = if args.count_first {
+        let pb = get_progress_bar(bars, Progress::of_input(&pgn)?);
+        pb.set_message("counting games");
+        let nb_games = input::count_games(input::open_with(
+            &pgn,
+            args.format,
+            Some(&pb),
+            read_buffer,
+            decoding(&args),
+        )?)?;
+        pb.finish_and_clear();
+        bars.remove(&pb);
+        info!(nb_games, "games counted");
+        Some(nb_games)
+    } else {
+        args.nb_games
+    };
+    let nb_games = match (nb_games, args.max_games) {
+        (Some(nb_games), Some(max_games)) => Some(nb_games.min(max_games)),
+        (nb_games, _) => nb_games,
+    };
+    let progress = match nb_games {
+        Some(nb_games) => Progress::Games(nb_games),
+        None => Progress::of_input(&pgn)?,
+    };
+    if let Progress::Bytes(file_size) = progress {
+        info!(file_size, "reading");
     } else {
-        Box::new(file)
+        info!("reading");
+    }
+    let pb = get_progress_bar(bars, progress);
+    let bytes_pb = nb_games.is_none().then_some(&pb);
+    let uncompressed = input::open_with(&pgn, args.format, bytes_pb, read_buffer, decoding(&args))?;
+
+    let mut anomalies = ClockAnomalies::default();
+    let mut aggregators: Vec<&mut dyn Aggregator> = Vec::new();
+    if args.clock_anomalies.is_some() {
+        aggregators.push(&mut anomalies);
+    }
+    if let Some(history) = history.as_mut() {
+        aggregators.push(history);
+    }
+    let mut sessions = Sessions::new(Duration::from_secs(60 * args.session_gap.unwrap_or(30)));
+    if args.sessions.is_some() {
+        aggregators.push(&mut sessions);
+    }
+    let mut coverage = ClockCoverage::default();
+    if args.clock_coverage.is_some() {
+        aggregators.push(&mut coverage);
+    }
+    let mut summary = args.sample.map_or_else(Summary::default, Summary::sampled);
+    if args.summary.is_some() || args.report_md.is_some() {
+        aggregators.push(&mut summary);
+    }
+    let mut per_game = args
+        .per_game
+        .as_deref()
+        .map(|path| PerGame::new(Output::create(path, None)?, csv_options.delimiter))
+        .transpose()?;
+    if let Some(per_game) = &mut per_game {
+        aggregators.push(per_game);
+    }
+    let mut move_times = args
+        .move_times
+        .as_deref()
+        .map(|path| {
+            let w = Output::create(path, Compress::from_extension(path))?;
+            MoveTimes::new(w, csv_options.delimiter)
+        })
+        .transpose()?;
+    if let Some(move_times) = &mut move_times {
+        aggregators.push(move_times);
+    }
+    let mut endings = EndingClocks::default();
+    if args.endings.is_some() {
+        aggregators.push(&mut endings);
+    }
+    let mut terminations = Terminations::default();
+    if args.terminations.is_some() {
+        aggregators.push(&mut terminations);
+    }
+    let mut variants = VariantTimes::default();
+    if args.variants.is_some() {
+        aggregators.push(&mut variants);
+    }
+    let mut outcomes = OutcomeTimes::default();
+    if args.outcome_times.is_some() {
+        aggregators.push(&mut outcomes);
+    }
+    let mut longest = LongestGames::default();
+    if args.longest_games.is_some() {
+        aggregators.push(&mut longest);
+    }
+    let mut move_stats =
+        MoveStats::new(args.time_trouble.map_or(TIME_TROUBLE, Duration::from_secs));
+    if args.move_stats.is_some() {
+        aggregators.push(&mut move_stats);
+    }
+    let mut openings = OpeningTimes::new(args.openings_per_user);
+    if args.openings.is_some() {
+        aggregators.push(&mut openings);
+    }
+    let mut tcs = TcTimes::default();
+    if args.time_controls.is_some() {
+        aggregators.push(&mut tcs);
+    }
+    let mut favorite_tcs = FavoriteTcs::default();
+    if args.favorite_tcs.is_some() {
+        aggregators.push(&mut favorite_tcs);
+    }
+    let mut durations = GameDurations::default();
+    if args.plot_durations.is_some() {
+        aggregators.push(&mut durations);
+    }
+    let mut activity = Activity::default();
+    if args.plot_activity.is_some() {
+        aggregators.push(&mut activity);
+    }
+    let mut daily = DailyTotals::default();
+    if args.plot_cumulative.is_some() {
+        aggregators.push(&mut daily);
+    }
+    let mut calibration = Calibration::default();
+    if args.plot_calibration.is_some() {
+        aggregators.push(&mut calibration);
+    }
+    let mut placeholders = Placeholders::default();
+    aggregators.push(&mut placeholders);
+    // also for the games with a clock/ply mismatch
+    let mut skips = SkipCounts::default();
+    aggregators.push(&mut skips);
+    if let Some(n) = args.sample {
+        pb.set_message(format!("sampling 1 game in {n}"));
+    }
+    let builder = AnalysisBuilder::new()
+        .aggregators(aggregators)
+        .progress_bar(pb.clone(), nb_games.is_some());
+    let interrupted = interrupt_flag()?;
+    let mut builder =
+        with_filters(builder, &args, users, excluded).stop_on(Arc::clone(&interrupted));
+    if args.move_times.is_some() || args.move_stats.is_some() {
+        builder = builder.keep_clocks();
+    }
+    if args.variants.is_some() {
+        builder = builder.variants();
+    }
+    if let Some(path) = &args.eco_names {
+        builder = builder.eco_names(EcoNames::load(path)?);
+    }
+    let users = builder.build().run(uncompressed)?;
+    if interrupted.load(Ordering::Relaxed) {
+        warn!("interrupted, writing the partial results");
+    }
+    for (username, games) in placeholders.sorted() {
+        warn!(
+            username,
+            games = games.games,
+            hours = games.time_spent_exact.as_secs() / 3600,
+            "placeholder player, not credited"
+        );
+    }
+    if skips.clock_mismatches() > 0 {
+        warn!(
+            games = skips.clock_mismatches(),
+            "counted games with clocks not matching their plies, see --skip-clock-mismatches"
+        );
+    }
+    if args.validate {
+        return skips.write_report(&mut io::stdout().lock());
+    }
+    if let Some(path) = &args.clock_anomalies {
+        let mut w = Output::create(path, None)?;
+        anomalies.write_csv(&mut w, csv_options.delimiter)?;
+        w.finish()?;
+    }
+    if let Some((path, history)) = args.rating_history.as_deref().zip(history) {
+        let mut w = Output::create(path, None)?;
+        history.write_ndjson(&mut w)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.sessions {
+        let mut w = Output::create(path, None)?;
+        sessions.write_csv(&mut w, csv_options.delimiter)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.clock_coverage {
+        let mut w = Output::create(path, None)?;
+        coverage.write_csv(&mut w, csv_options.delimiter)?;
+        w.finish()?;
+    }
+    if let Some(per_game) = per_game {
+        per_game.finish()?.finish()?;
+    }
+    if let Some(move_times) = move_times {
+        move_times.finish()?.finish()?;
+    }
+    if let Some(path) = &args.summary {
+        let mut w = Output::create(path, None)?;
+        if path.ends_with(".json") {
+            summary.write_json(&mut w, &users)?;
+        } else {
+            summary.write_csv(&mut w, &users, csv_options.delimiter)?;
+        }
+        w.finish()?;
+    }
+    if let Some(path) = &args.endings {
+        let mut w = Output::create(path, None)?;
+        endings.write_csv(&mut w, &csv_options)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.terminations {
+        let mut w = Output::create(path, None)?;
+        terminations.write_csv(&mut w, csv_options.delimiter)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.variants {
+        let mut w = Output::create(path, None)?;
+        variants.write_csv(&mut w, &csv_options)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.outcome_times {
+        let mut w = Output::create(path, None)?;
+        outcomes.write_csv(&mut w, &csv_options)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.longest_games {
+        let mut w = Output::create(path, None)?;
+        longest.write_csv(&mut w, &csv_options)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.move_stats {
+        let mut w = Output::create(path, None)?;
+        move_stats.write_csv(&mut w, csv_options.delimiter)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.openings {
+        let mut w = Output::create(path, None)?;
+        openings.write_csv(&mut w, &csv_options)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.time_controls {
+        let mut w = Output::create(path, None)?;
+        tcs.write_csv(&mut w, &csv_options)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.favorite_tcs {
+        let mut w = Output::create(path, None)?;
+        favorite_tcs.write_csv(&mut w, csv_options.delimiter)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.plot_durations {
+        plot::write(path, &durations, &plot_style(&args))?;
+    }
+    if let Some(path) = &args.plot_activity {
+        plot::write(path, &activity, &plot_style(&args))?;
+    }
+    if let Some(path) = &args.plot_cumulative {
+        plot::write(path, &daily, &plot_style(&args))?;
+    }
+    if let Some(path) = &args.plot_calibration {
+        plot::write(path, &calibration, &plot_style(&args))?;
+    }
+    if let Some(path) = &args.save_state {
+        let mut w = Output::create(path, None)?;
+        state::write(&mut w, &users)?;
+        w.finish()?;
+    }
+    let users = finish(&args, users, aliases, previous, &csv_options)?;
+    if let Some(path) = &args.report_md {
+        let mut w = Output::create(path, None)?;
+        report::write_markdown(&mut w, &users, &summary, &skips, REPORT_TOP)?;
+        w.finish()?;
+    }
+    write_schema(&args, &[pgn], Some(&skips))
+}
+
+/// Merge the states of previous runs, and write them like the results of a pgn
+fn from_state(args: &Args, paths: &[String]) -> io::Result<()> {
+    let csv_options = csv_options(args)?;
+    let aliases = args.aliases.as_deref().map(aliases::load).transpose()?;
+    let previous = load_previous(args)?;
+    let mut users = Users::default();
+    for path in paths {
+        info!(path, "reading the state");
+        results::merge(&mut users, state::read(input::open(path, None, None)?)?);
+    }
+    finish(args, users, aliases, previous, &csv_options)?;
+    write_schema(args, paths, None)
+}
+
+fn write_schema(args: &Args, inputs: &[String], skips: Option<&SkipCounts>) -> io::Result<()> {
+    if let Some(path) = &args.schema {
+        let schema = Schema::new(
+            args.output_format.unwrap_or_default(),
+            &csv_options(args)?,
+            args.buckets.unwrap_or_default(),
+            inputs,
+            skips,
+        )?;
+        let mut w = Output::create(path, None)?;
+        schema.write_json(&mut w)?;
+        w.finish()?;
+    }
+    Ok(())
+}
+
+/// Merge the previous results and the aliases, then write the results everywhere asked
+fn finish(
+    args: &Args,
+    mut users: Users,
+    aliases: Option<Aliases>,
+    previous: Option<Users>,
+    csv_options: &CsvOptions,
+) -> io::Result<Users> {
+    if let Some(previous) = previous {
+        results::merge(&mut users, previous);
+    }
+    if let Some(aliases) = aliases {
+        let contributions = aliases::merge(&mut users, &aliases);
+        let mut w = Output::create("time-spent-aliases.csv", None)?;
+        aliases::write_csv(&mut w, &contributions, csv_options)?;
+        w.finish()?;
+    }
+    if let Some(min_games) = args.min_games {
+        users.retain(|_, time_spents| time_spents.total_games() >= min_games);
+    }
+    if let Some(username) = &args.only_user {
+        let time_spents = users.get(username).cloned().unwrap_or_default();
+        let mut w = Output::create(args.profile.as_deref().unwrap_or("-"), None)?;
+        results::write_profile(&mut w, username, &time_spents)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.colors {
+        let mut w = Output::create(path, None)?;
+        colors::write_csv(&mut w, &users, csv_options)?;
+        w.finish()?;
+    }
+    if let Some(path) = &args.plot_histogram {
+        plot::write(path, &Histogram::new(&users), &plot_style(args))?;
+    }
+    if let Some(path) = &args.plot_rating {
+        plot::write(path, &RatingDensity::new(&users), &plot_style(args))?;
+    }
+    if let Some(path) = &args.plot_lorenz {
+        let lorenz = Lorenz::new(&users);
+        if let Some(gini) = lorenz.gini() {
+            info!(gini, "concentration of the real time");
+        }
+        plot::write(path, &lorenz, &plot_style(args))?;
+    }
+    if let Some(path) = &args.plot_top {
+        let top = Top::new(&users, args.plot_top_n.map_or(plot::TOP_N, |n| n as usize));
+        plot::write(path, &top, &plot_style(args))?;
+    }
+    write_results(args, &users, csv_options)?;
+    if let Some(url) = &args.postgres {
+        let table = args.postgres_table.as_deref().unwrap_or("time_spent");
+        info!(table, "loading the results into postgres");
+        pg::load(url, table, &users, args.postgres_upsert)?;
+    }
+    if let Some(url) = &args.clickhouse {
+        let table = args.clickhouse_table.as_deref().unwrap_or("time_spent");
+        info!(table, "inserting the results into clickhouse");
+        clickhouse::load(url, table, &users)?;
+    }
+    Ok(users)
+}
+
+/// Process the dumps of `dir` as they are downloaded, rewriting the results after each one
+fn watch(args: Args, dir: &str, bars: &MultiProgress) -> io::Result<()> {
+    let _span = info_span!("watch", dir).entered();
+    let csv_options = csv_options(&args)?;
+    let (users_filter, excluded) = load_usernames(&args)?;
+    let interrupted = interrupt_flag()?;
+    let mut watcher = Watcher::new(dir);
+    let mut users = match &args.merge {
+        Some(path) => results::load(path)?,
+        None => Users::default(),
+    };
+    while !interrupted.load(Ordering::Relaxed) {
+        for pgn in watcher.poll()? {
+            info!(path = %pgn.display(), "reading");
+            let pb = get_progress_bar(bars, Progress::of_input(&pgn)?);
+            let uncompressed = input::open_with(
+                &pgn.to_string_lossy(),
+                args.format,
+                Some(&pb),
+                read_buffer(&args),
+                decoding(&args),
+            )?;
+            let builder = AnalysisBuilder::new().progress_bar(pb.clone(), false);
+            let pgn_users = with_filters(builder, &args, users_filter.clone(), excluded.clone())
+                .stop_on(Arc::clone(&interrupted))
+                .build()
+                .run(uncompressed)?;
+            bars.remove(&pb);
+            results::merge(&mut users, pgn_users);
+            write_results(&args, &users, &csv_options)?;
+            if interrupted.load(Ordering::Relaxed) {
+                warn!(path = %pgn.display(), "interrupted, the results of this pgn are partial");
+                return Ok(());
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+    Ok(())
+}
+
+/// Between two polls of the watched directory, a file whose size did not change in the meantime
+/// being deemed downloaded
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Players listed in the markdown report
+const REPORT_TOP: usize = 50;
+
+fn csv_options(args: &Args) -> io::Result<CsvOptions> {
+    let format = args.output_format.unwrap_or_default();
+    let database =
+        format == OutputFormat::Sqlite || args.postgres.is_some() || args.clickhouse.is_some();
+    if args.time_unit.is_some()
+        && (database || matches!(format, OutputFormat::Parquet | OutputFormat::Arrow))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--time-unit does not apply to parquet, arrow and the databases, \
+            whose times are integers in seconds or milliseconds",
+        ));
+    }
+    if args.columns.is_some() && database {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--columns does not apply to the databases, which have the columns read back",
+        ));
+    }
+    let columns = match (args.columns.clone(), args.approx_only) {
+        (Some(columns), true) if columns.iter().any(|c| c.needs_clocks()) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--approx-only has no real time based columns",
+            ))
+        }
+        (Some(columns), _) => columns,
+        (None, true) => vec![Column::Games, Column::Rating, Column::Approx],
+        (None, false) => Column::ALL.to_vec(),
+    };
+    Ok(CsvOptions {
+        delimiter: args.delimiter.unwrap_or_default(),
+        columns,
+        time_unit: args.time_unit.unwrap_or_default(),
+    })
+}
+
+/// The results of `--merge`, which must have the columns per color for `--colors`
+fn load_previous(args: &Args) -> io::Result<Option<Users>> {
+    let Some(path) = &args.merge else {
+        return Ok(None);
+    };
+    if args.colors.is_some() {
+        let columns = results::load_columns(path)?;
+        let colors = [
+            Column::WhiteGames,
+            Column::WhiteExact,
+            Column::BlackGames,
+            Column::BlackExact,
+        ];
+        if let Some(missing) = colors
+            .into_iter()
+            .find(|color| !columns.iter().any(|(_, column)| column == color))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "--colors needs the *_{} columns of the --merge results",
+                    missing.header()
+                ),
+            ));
+        }
+    }
+    results::load(path).map(Some)
+}
+
+/// The tracked users, and the excluded ones
+fn load_usernames(args: &Args) -> io::Result<(Option<Usernames>, Option<Usernames>)> {
+    let users = match args.only_user.clone() {
+        Some(username) => Some(Usernames::from_iter([username])),
+        None => args
+            .users_file
+            .as_deref()
+            .map(usernames::load)
+            .transpose()?,
     };
-    let mut reader = BufferedReader::new(uncompressed);
-
-    let mut visitor = visitor::PgnVisitor::new(get_progress_bar(nb_games));
-    reader.read_all(&mut visitor).expect("Valid pgn file");
-    visitor.pb.finish();
-    let file = File::create("time-spent.csv")?;
-    let mut w = BufWriter::new(file);
-    write!(w, "username")?;
-    for perf in ["ultrabullet", "bullet", "blitz", "rapid", "classical"] {
-        write!(
+    let excluded = args
+        .exclude_file
+        .as_deref()
+        .map(usernames::load)
+        .transpose()?;
+    Ok((users, excluded))
+}
+
+/// The options deciding which games are counted, and how
+fn with_filters<'a>(
+    mut builder: AnalysisBuilder<'a>,
+    args: &Args,
+    users: Option<Usernames>,
+    excluded: Option<Usernames>,
+) -> AnalysisBuilder<'a> {
+    builder = builder
+        .buckets(args.buckets.unwrap_or_default())
+        .attribution(args.attribution.unwrap_or_default())
+        .timezone(args.utc_offset.unwrap_or_default());
+    if let Some(max_plies) = args.max_plies {
+        builder = builder.max_plies(max_plies);
+    }
+    if args.skip_clock_mismatches {
+        builder = builder.skip_clock_mismatches();
+    }
+    if let Some(seconds) = args.correspondence_move {
+        builder = builder.correspondence_move(Duration::from_secs(seconds));
+    }
+    if let Some(users) = users {
+        builder = builder.users(users);
+    }
+    if let Some(excluded) = excluded {
+        builder = builder.exclude(excluded);
+    }
+    if args.min_rating.is_some() || args.max_rating.is_some() {
+        builder = builder
+            .filter_rating(args.min_rating.unwrap_or(0)..=args.max_rating.unwrap_or(usize::MAX));
+    }
+    if args.no_bots {
+        builder = builder.no_bots();
+    }
+    if let Some(max_games) = args.max_games {
+        builder = builder.max_games(max_games as usize);
+    }
+    if let Some(n) = args.sample {
+        builder = builder.sample(n as usize);
+    }
+    if args.fast {
+        builder = builder.fast();
+    }
+    if args.rated_only || args.casual_only {
+        builder = builder.rated(args.rated_only);
+    }
+    if let Some(perfs) = args.perfs.clone() {
+        builder = builder.perfs(perfs);
+    }
+    if let Some(from) = args.from {
+        builder = builder.from(from);
+    }
+    if let Some(to) = args.to {
+        builder = builder.to(to);
+    }
+    if args.approx_only {
+        info!("clocks ignored, only the approximate time is computed");
+        builder = builder.approx_only();
+    }
+    builder
+}
+
+/// Compare the decompression speed of the first gigabyte of the pgn with various read buffers
+/// and decoder settings
+fn bench_io(pgn: &str, format: Option<input::Compression>) -> io::Result<()> {
+    const BUFFERS_KIB: [usize; 5] = [8, 64, 256, 1024, 8192];
+    let buffers = BUFFERS_KIB.map(|kib| kib * 1024);
+    let throughputs = input::bench(pgn, format, &buffers, 1_000_000_000)?;
+    let mut w = io::stdout().lock();
+    writeln!(
+        w,
+        "read buffer  decoder               compressed MB/s  uncompressed MB/s"
+    )?;
+    for t in &throughputs {
+        writeln!(
             w,
-            ",{perf}_games,{perf}_avg_rating,{perf}_approximate_time,{perf}_real_time"
+            "{:>7} KiB  {:<20}  {:>15.1}  {:>17.1}",
+            t.buffer / 1024,
+            t.decoding.to_string(),
+            t.compressed,
+            t.uncompressed
         )?;
     }
-    writeln!(w)?;
-    for (username, time_spents) in visitor.users.into_iter() {
-        write!(w, "{username}")?;
-        time_spents.to_csv(&mut w)?;
+    if let Some(fastest) = throughputs.first() {
+        write!(w, "fastest with --read-buffer {}", fastest.buffer / 1024)?;
+        if fastest.decoding != input::Decoding::Default {
+            write!(w, " {}", fastest.decoding)?;
+        }
         writeln!(w)?;
     }
     Ok(())
 }
+
+fn plot_style(args: &Args) -> PlotStyle {
+    PlotStyle {
+        format: args.plot_format,
+        size: args.plot_size.unwrap_or_default(),
+        theme: args.plot_theme.unwrap_or_default(),
+    }
+}
+
+fn read_buffer(args: &Args) -> usize {
+    args.read_buffer
+        .map_or(input::DEFAULT_BUFFER, |kib| kib as usize * 1024)
+}
+
+fn decoding(args: &Args) -> input::Decoding {
+    match args.zstd_window_log {
+        Some(log) => input::Decoding::ZstdWindowLog(log),
+        None if args.lz4_blocks => input::Decoding::Lz4Blocks,
+        None => input::Decoding::Default,
+    }
+}
+
+/// Set on Ctrl-C, so the results read so far are written
+fn interrupt_flag() -> io::Result<Arc<AtomicBool>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let stop = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || {
+        // a second Ctrl-C does not wait for the partial results
+        if stop.swap(true, Ordering::Relaxed) {
+            process::exit(130);
+        }
+    })
+    .map_err(io::Error::other)?;
+    Ok(interrupted)
+}
+
+fn write_results(args: &Args, users: &Users, csv_options: &CsvOptions) -> io::Result<()> {
+    let format = args.output_format.unwrap_or_default();
+    let default_path = format!(
+        "{}{}",
+        format.default_path(),
+        args.compress.map_or("", Compress::extension)
+    );
+    let output = args.output.as_deref().unwrap_or(&default_path);
+    info!(output, users = users.len(), "writing the results");
+    if let Some(period) = &args.append {
+        return append_results(output, users, format, period, args.sort_by, csv_options);
+    }
+    if format == OutputFormat::Sqlite && output != "-" {
+        return match args.sort_by {
+            Some(by) => sqlite::write(Path::new(output), results::sort(users, by)),
+            None => sqlite::write(Path::new(output), users),
+        };
+    }
+    let mut w = Output::create(output, args.compress)?;
+    match args.sort_by {
+        Some(by) => results::write(&mut w, results::sort(users, by), format, csv_options)?,
+        None => results::write(&mut w, users, format, csv_options)?,
+    }
+    w.finish()?;
+    if let Some(path) = &args.duckdb_view {
+        // absolute, so the view can be used from any directory
+        let parquet = fs::canonicalize(output)?;
+        let view = columnar::duckdb_view(&parquet.to_string_lossy(), &csv_options.columns);
+        let mut w = Output::create(path, None)?;
+        w.write_all(view.as_bytes())?;
+        w.finish()?;
+    }
+    Ok(())
+}
+
+/// Add the results to the sqlite or parquet at `output`, as those of `period`
+fn append_results(
+    output: &str,
+    users: &Users,
+    format: OutputFormat,
+    period: &str,
+    sort_by: Option<SortBy>,
+    csv_options: &CsvOptions,
+) -> io::Result<()> {
+    let sorted = match sort_by {
+        Some(by) => results::sort(users, by),
+        None => users.iter().collect(),
+    };
+    if format == OutputFormat::Sqlite {
+        return sqlite::append(Path::new(output), sorted, period);
+    }
+    let previous = match fs::File::open(output) {
+        Ok(previous) => Some(previous),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let mut w = Output::create(output, None)?;
+    columnar::append_parquet(&mut w, previous, sorted, &csv_options.columns, period)
+        .map_err(io::Error::other)?;
+    w.finish()
+}
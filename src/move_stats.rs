@@ -0,0 +1,187 @@
+//! Statistics of the moves of each user, from their clocks, per perf
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    moves::think_times,
+    placeholders::is_placeholder,
+    results::{escape, Delimiter},
+    visitor::Perf,
+};
+
+/// Clock below which a move is played in time trouble, by default
+pub const TIME_TROUBLE: Duration = Duration::from_secs(10);
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerMoves {
+    /// with a clock
+    pub moves: u64,
+    /// started with less than the time trouble threshold on the clock
+    pub time_trouble: u64,
+    /// which took no time from the clock, most of them premoves
+    pub instant: u64,
+    /// of all the moves, as in `--move-times`
+    pub think_time: Duration,
+}
+
+impl PlayerMoves {
+    /// share of the moves played instantly, 0 without moves
+    pub fn instant_rate(&self) -> f64 {
+        if self.moves == 0 {
+            0.
+        } else {
+            self.instant as f64 / self.moves as f64
+        }
+    }
+
+    /// mean think time of a move, in seconds, 0 without moves
+    pub fn average_think_seconds(&self) -> f64 {
+        if self.moves == 0 {
+            0.
+        } else {
+            self.think_time.as_secs_f64() / self.moves as f64
+        }
+    }
+}
+
+/// Moves of each user in each perf, from the games with clocks. A move is in time trouble
+/// when the clock of the player was below the threshold as they started thinking, which is
+/// their clock after their previous move. The clocks being in whole seconds, an instant move
+/// took less than a second, increment included. The think time of a move is the one of
+/// `--move-times`, from the clocks and increments of the player
+#[derive(Debug)]
+pub struct MoveStats {
+    time_trouble: Duration,
+    users: FxHashMap<(String, Perf), PlayerMoves>,
+}
+
+impl MoveStats {
+    pub fn new(time_trouble: Duration) -> Self {
+        Self {
+            time_trouble,
+            users: FxHashMap::default(),
+        }
+    }
+
+    pub fn get(&self, username: &str, perf: Perf) -> Option<&PlayerMoves> {
+        self.users.get(&(username.to_string(), perf))
+    }
+
+    /// By username then perf
+    pub fn write_csv(&self, w: &mut impl Write, delimiter: Delimiter) -> io::Result<()> {
+        let d = delimiter.as_char();
+        writeln!(
+            w,
+            "username{d}perf{d}moves{d}time_trouble_moves{d}instant_moves{d}instant_rate{d}\
+             avg_think_seconds"
+        )?;
+        let mut rows: Vec<_> = self.users.iter().collect();
+        rows.sort_unstable_by_key(|((username, perf), _)| {
+            let p = Perf::ALL.iter().position(|p| p == perf);
+            (username, p)
+        });
+        for ((username, perf), moves) in rows {
+            writeln!(
+                w,
+                "{}{d}{}{d}{}{d}{}{d}{}{d}{:.3}{d}{:.2}",
+                escape(username, d),
+                perf.name(),
+                moves.moves,
+                moves.time_trouble,
+                moves.instant,
+                moves.instant_rate(),
+                moves.average_think_seconds(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for MoveStats {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if game.clocks.is_empty() {
+            return;
+        }
+        // white moves first
+        for (side, player) in [game.white, game.black].into_iter().enumerate() {
+            if player.is_bot || player.is_excluded || is_placeholder(&player.username) {
+                continue;
+            }
+            let moves = self
+                .users
+                .entry((player.username.clone(), game.perf))
+                .or_default();
+            // the first move is played before the clock starts
+            let mut before = None;
+            let plies = game
+                .clocks
+                .iter()
+                .zip(think_times(game.clocks, game.tc, game.berserks));
+            for (clock, think_time) in plies.skip(side).step_by(2) {
+                moves.moves += 1;
+                moves.think_time += think_time;
+                if let Some(before) = before {
+                    moves.time_trouble += u64::from(before < self.time_trouble);
+                    moves.instant += u64::from(think_time.is_zero());
+                }
+                before = Some(*clock);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Bullet game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "60+0"]
+
+1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 { [%clk 0:00:09] } 2... Nc6 { [%clk 0:00:30] } 3. Bc4 { [%clk 0:00:09] } 3... Bc5 { [%clk 0:00:20] } 4. c3 { [%clk 0:00:01] } 1-0
+
+[Event "Rated Bullet game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "60+0"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+"#;
+
+    #[test]
+    fn test_move_stats() {
+        let mut stats = MoveStats::new(TIME_TROUBLE);
+        AnalysisBuilder::new()
+            .keep_clocks()
+            .aggregators(vec![&mut stats])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        // alice started Bc4 and c3 with 9 seconds left, and Bc4 was a premove
+        assert_eq!(
+            stats.get("alice", Perf::Bullet),
+            Some(&PlayerMoves {
+                moves: 4,
+                time_trouble: 2,
+                instant: 1,
+                think_time: Duration::from_secs(59),
+            })
+        );
+        let mut w = Vec::new();
+        stats.write_csv(&mut w, Delimiter::Comma).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,perf,moves,time_trouble_moves,instant_moves,instant_rate,avg_think_seconds\n\
+             alice,bullet,4,2,1,0.250,14.75\n\
+             bob,bullet,3,0,0,0.000,13.33\n"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Think time of every move, streamed as the games are read
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    results::{escape, Delimiter},
+    visitor::Tc,
+};
+
+/// Time taken by each ply, from the previous clock of the same side and the increment.
+/// The first move of each side is the base time minus its clock, as lichess only starts
+/// the clocks afterwards. A side that berserked, white first, started from half the base
+/// time and gets no increment
+pub fn think_times(
+    clocks: &[Duration],
+    tc: Tc,
+    berserks: [bool; 2],
+) -> impl Iterator<Item = Duration> + '_ {
+    let base = Duration::from_secs(tc.base);
+    let increment = Duration::from_secs(tc.increment);
+    clocks.iter().enumerate().map(move |(i, clock)| {
+        let berserk = berserks[i % 2];
+        match i {
+            0 | 1 if berserk => (base / 2).saturating_sub(*clock),
+            0 | 1 => base.saturating_sub(*clock),
+            _ if berserk => clocks[i - 2].saturating_sub(*clock),
+            _ => (clocks[i - 2] + increment).saturating_sub(*clock),
+        }
+    })
+}
+
+/// Writes the think times of each game with clocks as soon as it is counted, one game per line.
+/// The first write error is kept, and returned by `finish`
+pub struct MoveTimes<W: Write> {
+    w: W,
+    delimiter: Delimiter,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> MoveTimes<W> {
+    /// Writes the header right away
+    pub fn new(mut w: W, delimiter: Delimiter) -> io::Result<Self> {
+        let d = delimiter.as_char();
+        writeln!(w, "game_id{d}think_times")?;
+        Ok(Self {
+            w,
+            delimiter,
+            error: None,
+        })
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.w),
+        }
+    }
+
+    // the seconds of each ply, space separated, white moving first
+    fn write(&mut self, game: &CountedGame<'_>) -> io::Result<()> {
+        let d = self.delimiter.as_char();
+        let id = game.link.rsplit('/').next().unwrap_or_default();
+        write!(self.w, "{}{d}", escape(id, d))?;
+        for (i, think_time) in think_times(game.clocks, game.tc, game.berserks).enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            write!(self.w, "{sep}{}", think_time.as_secs())?;
+        }
+        writeln!(self.w)
+    }
+}
+
+impl<W: Write> Aggregator for MoveTimes<W> {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if self.error.is_none() && !game.clocks.is_empty() {
+            self.error = self.write(game).err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[Site "https://lichess.org/abcd1234"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[Site "https://lichess.org/nocl0cks"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+
+[Event "Rated Blitz tournament https://lichess.org/tournament/abcdefgh"]
+[Site "https://lichess.org/berserk1"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:01:29] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:01:20] } 2... Nc6 { [%clk 0:02:40] } 1-0
+"#;
+
+    #[test]
+    fn test_move_times() {
+        let mut move_times = MoveTimes::new(Vec::new(), Delimiter::Comma).unwrap();
+        AnalysisBuilder::new()
+            .keep_clocks()
+            .aggregators(vec![&mut move_times])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let w = move_times.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "game_id,think_times\nabcd1234,0 0 12 22\nberserk1,1 0 9 22\n"
+        );
+    }
+}
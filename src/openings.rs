@@ -0,0 +1,355 @@
+//! Time spent per opening family, site-wide or per user
+
+use std::{
+    fs,
+    io::{self, Write},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    placeholders::is_placeholder,
+    results::{escape, CsvOptions},
+};
+
+/// Family of a lichess `Opening` header, the name before its variation, like `Sicilian Defense`
+/// for `Sicilian Defense: Najdorf Variation`. None for the unknown `?` opening
+pub fn family(opening: &str) -> Option<&str> {
+    let family = opening.split(':').next().unwrap_or_default().trim();
+    (!family.is_empty() && family != "?").then_some(family)
+}
+
+/// First ECO code of each range of codes sharing an opening family, named like the families of
+/// the lichess `Opening` headers
+const ECO_FAMILIES: &[(&str, &str)] = &[
+    ("A00", "Uncommon Opening"),
+    ("A01", "Nimzo-Larsen Attack"),
+    ("A02", "Bird Opening"),
+    ("A04", "Zukertort Opening"),
+    ("A07", "King's Indian Attack"),
+    ("A09", "Réti Opening"),
+    ("A10", "English Opening"),
+    ("A40", "Queen's Pawn Game"),
+    ("A42", "Modern Defense"),
+    ("A43", "Benoni Defense"),
+    ("A45", "Indian Defense"),
+    ("A47", "Queen's Indian Defense"),
+    ("A48", "Indian Defense"),
+    ("A51", "Budapest Defense"),
+    ("A53", "Old Indian Defense"),
+    ("A56", "Benoni Defense"),
+    ("A57", "Benko Gambit"),
+    ("A60", "Benoni Defense"),
+    ("A80", "Dutch Defense"),
+    ("B00", "King's Pawn Game"),
+    ("B01", "Scandinavian Defense"),
+    ("B02", "Alekhine Defense"),
+    ("B06", "Modern Defense"),
+    ("B07", "Pirc Defense"),
+    ("B10", "Caro-Kann Defense"),
+    ("B20", "Sicilian Defense"),
+    ("C00", "French Defense"),
+    ("C20", "King's Pawn Game"),
+    ("C21", "Center Game"),
+    ("C23", "Bishop's Opening"),
+    ("C25", "Vienna Game"),
+    ("C30", "King's Gambit"),
+    ("C40", "King's Knight Opening"),
+    ("C41", "Philidor Defense"),
+    ("C42", "Russian Game"),
+    ("C44", "King's Knight Opening"),
+    ("C45", "Scotch Game"),
+    ("C46", "Three Knights Opening"),
+    ("C47", "Four Knights Game"),
+    ("C50", "Italian Game"),
+    ("C60", "Ruy Lopez"),
+    ("D00", "Queen's Pawn Game"),
+    ("D01", "Richter-Veresov Attack"),
+    ("D02", "Queen's Pawn Game"),
+    ("D06", "Queen's Gambit"),
+    ("D07", "Queen's Gambit Declined"),
+    ("D10", "Slav Defense"),
+    ("D20", "Queen's Gambit Accepted"),
+    ("D30", "Queen's Gambit Declined"),
+    ("D43", "Semi-Slav Defense"),
+    ("D50", "Queen's Gambit Declined"),
+    ("D70", "Neo-Grünfeld Defense"),
+    ("D80", "Grünfeld Defense"),
+    ("E00", "Indian Defense"),
+    ("E01", "Catalan Opening"),
+    ("E10", "Indian Defense"),
+    ("E11", "Bogo-Indian Defense"),
+    ("E12", "Queen's Indian Defense"),
+    ("E20", "Nimzo-Indian Defense"),
+    ("E60", "King's Indian Defense"),
+];
+
+fn is_eco(code: &str) -> bool {
+    matches!(code.as_bytes(), [b'A'..=b'E', b'0'..=b'9', b'0'..=b'9'])
+}
+
+/// Opening family of each ECO code, for the games without an `Opening` header. The families of a
+/// `--eco-names` tsv take precedence over the bundled ones
+#[derive(Default, Debug, Clone)]
+pub struct EcoNames(FxHashMap<String, String>);
+
+impl EcoNames {
+    /// Load a tsv in the format of the lichess chess-openings files, `eco`, `name` and `pgn`
+    /// columns with a header. Each code gets the family of its shortest line
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|(i, line)| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{path}:{}: expected `eco<TAB>name<TAB>pgn`, got {line:?}",
+                    i + 1
+                ),
+            )
+        })
+    }
+
+    fn parse(content: &str) -> Result<Self, (usize, &str)> {
+        // the family of each code and the length of its line
+        let mut families: FxHashMap<String, (String, usize)> = FxHashMap::default();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || (i == 0 && line.starts_with("eco\t")) {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let (Some(eco), Some(name), Some(pgn)) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err((i, line));
+            };
+            let Some(name) = family(name).filter(|_| is_eco(eco)) else {
+                return Err((i, line));
+            };
+            let shortest = families.get(eco).is_none_or(|(_, len)| pgn.len() < *len);
+            if shortest {
+                families.insert(eco.to_string(), (name.to_string(), pgn.len()));
+            }
+        }
+        Ok(Self(
+            families
+                .into_iter()
+                .map(|(eco, (name, _))| (eco, name))
+                .collect(),
+        ))
+    }
+
+    /// None for an invalid code
+    pub fn family(&self, eco: &str) -> Option<&str> {
+        if !is_eco(eco) {
+            return None;
+        }
+        self.0.get(eco).map(String::as_str).or_else(|| {
+            let i = ECO_FAMILIES.partition_point(|(first, _)| *first <= eco);
+            Some(ECO_FAMILIES[i - 1].1)
+        })
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct OpeningTime {
+    pub games: usize,
+    /// in seconds
+    pub approximate: usize,
+    pub exact: Duration,
+}
+
+/// Games and time per opening family, from the `Opening` header or else the `ECO` code through
+/// `EcoNames`. Each
+/// human player is credited the game duration, site-wide as per user, so that the site-wide
+/// times are human hours. The games without either header are left out
+#[derive(Default, Debug)]
+pub struct OpeningTimes {
+    per_user: bool,
+    // keyed by an empty username site-wide
+    times: FxHashMap<(String, String), OpeningTime>,
+}
+
+impl OpeningTimes {
+    pub fn new(per_user: bool) -> Self {
+        Self {
+            per_user,
+            ..Self::default()
+        }
+    }
+
+    /// `username` being ignored site-wide
+    pub fn get(&self, username: &str, opening: &str) -> Option<&OpeningTime> {
+        let username = if self.per_user { username } else { "" };
+        self.times.get(&(username.to_string(), opening.to_string()))
+    }
+
+    /// By username per user, then from the most real time
+    pub fn write_csv(&self, w: &mut impl Write, options: &CsvOptions) -> io::Result<()> {
+        let d = options.delimiter.as_char();
+        let unit = options.time_unit;
+        if self.per_user {
+            write!(w, "username{d}")?;
+        }
+        writeln!(w, "opening{d}games{d}approximate_time{d}real_time")?;
+        let mut rows: Vec<_> = self.times.iter().collect();
+        rows.sort_unstable_by(|((user_a, a), time_a), ((user_b, b), time_b)| {
+            user_a
+                .cmp(user_b)
+                .then(time_b.exact.cmp(&time_a.exact))
+                .then(a.cmp(b))
+        });
+        for ((username, opening), time) in rows {
+            if self.per_user {
+                write!(w, "{}{d}", escape(username, d))?;
+            }
+            writeln!(
+                w,
+                "{}{d}{}{d}{}{d}{}",
+                escape(opening, d),
+                time.games,
+                unit.display(Duration::from_secs(time.approximate as u64)),
+                unit.display(time.exact),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for OpeningTimes {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        if game.opening.is_empty() {
+            return;
+        }
+        let humans: Vec<_> = [game.white, game.black]
+            .into_iter()
+            .filter(|p| !(p.is_bot || p.is_excluded || is_placeholder(&p.username)))
+            .collect();
+        if self.per_user {
+            for player in humans {
+                let time = self
+                    .times
+                    .entry((player.username.clone(), game.opening.to_string()))
+                    .or_default();
+                time.games += 1;
+                time.approximate += game.approximate_duration;
+                time.exact += game.exact_duration;
+            }
+        } else if !humans.is_empty() {
+            let time = self
+                .times
+                .entry((String::new(), game.opening.to_string()))
+                .or_default();
+            time.games += 1;
+            time.approximate += game.approximate_duration * humans.len();
+            time.exact += game.exact_duration * humans.len() as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[ECO "B90"]
+[Opening "Sicilian Defense: Najdorf Variation"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... c5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... d6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "bob"]
+[Black "carol"]
+[ECO "B50"]
+[Opening "Sicilian Defense"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... c5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:30] } 2... d6 { [%clk 0:02:40] } 0-1
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "carol"]
+[ECO "C20"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Qh5 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:50] } 1-0
+"#;
+
+    #[test]
+    fn test_eco_names() {
+        assert!(ECO_FAMILIES.windows(2).all(|w| w[0].0 < w[1].0));
+        let bundled = EcoNames::default();
+        assert_eq!(bundled.family("A00"), Some("Uncommon Opening"));
+        assert_eq!(bundled.family("B33"), Some("Sicilian Defense"));
+        assert_eq!(bundled.family("C20"), Some("King's Pawn Game"));
+        assert_eq!(bundled.family("E99"), Some("King's Indian Defense"));
+        assert_eq!(bundled.family("F00"), None);
+        assert_eq!(bundled.family("?"), None);
+        let tsv = "eco\tname\tpgn\n\
+                   C20\tKing's Pawn Game: Wayward Queen Attack\t1. e4 e5 2. Qh5\n\
+                   C20\tKing's Pawn Game\t1. e4 e5\n\
+                   C44\tPonziani Opening\t1. e4 e5 2. Nf3 Nc6 3. c3\n";
+        let names = EcoNames::parse(tsv).unwrap();
+        assert_eq!(names.family("C20"), Some("King's Pawn Game"));
+        assert_eq!(names.family("C44"), Some("Ponziani Opening"));
+        assert_eq!(names.family("B90"), Some("Sicilian Defense"));
+        assert_eq!(EcoNames::parse("X1\tSomething\t1. e4").unwrap_err().0, 0);
+        assert_eq!(EcoNames::parse("eco\tname\tpgn\nC20").unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn test_family() {
+        assert_eq!(
+            family("Queen's Gambit Declined: Exchange Variation, Positional Line"),
+            Some("Queen's Gambit Declined")
+        );
+        assert_eq!(family("Sicilian Defense"), Some("Sicilian Defense"));
+        assert_eq!(family("?"), None);
+    }
+
+    fn run(openings: &mut OpeningTimes) -> String {
+        AnalysisBuilder::new()
+            .aggregators(vec![openings])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let mut w = Vec::new();
+        openings.write_csv(&mut w, &CsvOptions::default()).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    #[test]
+    fn test_site_wide() {
+        let mut openings = OpeningTimes::new(false);
+        let csv = run(&mut openings);
+        let sicilian = openings.get("alice", "Sicilian Defense").unwrap();
+        assert_eq!(sicilian.games, 2);
+        // both players of games of 30 and 50 seconds
+        assert_eq!(sicilian.exact, Duration::from_secs(160));
+        assert_eq!(
+            csv,
+            "opening,games,approximate_time,real_time\n\
+             Sicilian Defense,2,720,160\n\
+             King's Pawn Game,1,360,40\n"
+        );
+    }
+
+    #[test]
+    fn test_per_user() {
+        let mut openings = OpeningTimes::new(true);
+        let csv = run(&mut openings);
+        assert_eq!(openings.get("carol", "Sicilian Defense").unwrap().games, 1);
+        assert_eq!(
+            csv,
+            "username,opening,games,approximate_time,real_time\n\
+             alice,Sicilian Defense,1,180,30\n\
+             alice,King's Pawn Game,1,180,20\n\
+             bob,Sicilian Defense,2,360,80\n\
+             carol,Sicilian Defense,1,180,50\n\
+             carol,King's Pawn Game,1,180,20\n"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Real time spent by each user per perf, split by the result of the games
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    placeholders::is_placeholder,
+    results::{escape, CsvOptions},
+    visitor::{Perf, Score},
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct OutcomeTime {
+    pub winning: Duration,
+    pub drawing: Duration,
+    pub losing: Duration,
+}
+
+/// Real time of each user in each perf by result, both players being credited the game
+/// duration. Unfinished games count in none of them
+#[derive(Default, Debug)]
+pub struct OutcomeTimes(FxHashMap<(String, Perf), OutcomeTime>);
+
+impl OutcomeTimes {
+    pub fn get(&self, username: &str, perf: Perf) -> Option<&OutcomeTime> {
+        self.0.get(&(username.to_string(), perf))
+    }
+
+    /// By username then perf
+    pub fn write_csv(&self, w: &mut impl Write, options: &CsvOptions) -> io::Result<()> {
+        let d = options.delimiter.as_char();
+        let unit = options.time_unit;
+        writeln!(w, "username{d}perf{d}win_time{d}draw_time{d}loss_time")?;
+        let mut rows: Vec<_> = self.0.iter().collect();
+        rows.sort_unstable_by_key(|((username, perf), _)| {
+            let p = Perf::ALL.iter().position(|p| p == perf);
+            (username, p)
+        });
+        for ((username, perf), time) in rows {
+            writeln!(
+                w,
+                "{}{d}{}{d}{}{d}{}{d}{}",
+                escape(username, d),
+                perf.name(),
+                unit.display(time.winning),
+                unit.display(time.drawing),
+                unit.display(time.losing),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for OutcomeTimes {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        let Some(scores) = game.scores else {
+            return;
+        };
+        for (player, score) in [game.white, game.black].into_iter().zip(scores) {
+            if player.is_bot || player.is_excluded || is_placeholder(&player.username) {
+                continue;
+            }
+            let time = self
+                .0
+                .entry((player.username.clone(), game.perf))
+                .or_default();
+            *match score {
+                Score::Win => &mut time.winning,
+                Score::Draw => &mut time.drawing,
+                Score::Loss => &mut time.losing,
+            } += game.exact_duration;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "bob"]
+[Black "alice"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:30] } 2... Nc6 { [%clk 0:02:40] } 1/2-1/2
+
+[Event "Rated Bullet game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "60+0"]
+
+1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 { [%clk 0:00:50] } 2... Nc6 { [%clk 0:00:50] } *
+"#;
+
+    #[test]
+    fn test_outcome_times() {
+        let mut outcomes = OutcomeTimes::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut outcomes])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(
+            outcomes.get("bob", Perf::Blitz),
+            Some(&OutcomeTime {
+                winning: Duration::ZERO,
+                drawing: Duration::from_secs(50),
+                losing: Duration::from_secs(30),
+            })
+        );
+        // the unfinished bullet game
+        assert_eq!(outcomes.get("alice", Perf::Bullet), None);
+        let mut w = Vec::new();
+        outcomes.write_csv(&mut w, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,perf,win_time,draw_time,loss_time\n\
+             alice,blitz,30,50,0\n\
+             bob,blitz,0,50,30\n"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Writing the results, possibly compressed, without ever leaving a truncated file
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+
+/// Compression of the results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compress {
+    Zst,
+    Gz,
+}
+
+impl Compress {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Zst => ".zst",
+            Self::Gz => ".gz",
+        }
+    }
+
+    pub fn from_extension(path: &str) -> Option<Self> {
+        [Self::Zst, Self::Gz]
+            .into_iter()
+            .find(|compress| path.ends_with(compress.extension()))
+    }
+}
+
+type Inner = BufWriter<Box<dyn Write + Send>>;
+
+enum Encoder {
+    Plain(Inner),
+    Zst(zstd::Encoder<'static, Inner>),
+    Gz(GzEncoder<Inner>),
+}
+
+/// Where a file is written before being renamed to `path`
+pub fn tmp_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut tmp = path.as_ref().as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// File or stdout the results are written to, `finish` must be called once done.
+/// Files are written next to their path, and only renamed to it by `finish`
+pub struct Output {
+    w: Encoder,
+    // temporary path, and final one
+    rename: Option<(PathBuf, PathBuf)>,
+}
+
+impl Output {
+    /// `-` stands for stdout
+    pub fn create(path: &str, compress: Option<Compress>) -> io::Result<Self> {
+        if path == "-" {
+            return Self::new(Box::new(io::stdout()), compress);
+        }
+        let tmp = tmp_path(path);
+        let mut output = Self::new(Box::new(File::create(&tmp)?), compress)?;
+        output.rename = Some((tmp, PathBuf::from(path)));
+        Ok(output)
+    }
+
+    pub fn new(w: Box<dyn Write + Send>, compress: Option<Compress>) -> io::Result<Self> {
+        let w = BufWriter::new(w);
+        let w = match compress {
+            None => Encoder::Plain(w),
+            Some(Compress::Zst) => Encoder::Zst(zstd::Encoder::new(w, 0)?),
+            Some(Compress::Gz) => Encoder::Gz(GzEncoder::new(w, flate2::Compression::default())),
+        };
+        Ok(Self { w, rename: None })
+    }
+
+    /// Write the end of the compressed stream, flush, and move the file to its path
+    pub fn finish(self) -> io::Result<()> {
+        let mut w = match self.w {
+            Encoder::Plain(w) => w,
+            Encoder::Zst(w) => w.finish()?,
+            Encoder::Gz(w) => w.finish()?,
+        };
+        w.flush()?;
+        drop(w);
+        match self.rename {
+            Some((tmp, path)) => fs::rename(tmp, path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.w {
+            Encoder::Plain(w) => w.write(buf),
+            Encoder::Zst(w) => w.write(buf),
+            Encoder::Gz(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.w {
+            Encoder::Plain(w) => w.flush(),
+            Encoder::Zst(w) => w.flush(),
+            Encoder::Gz(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    // shared so it can be read once the output is finished
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_compress() {
+        for compress in [None, Some(Compress::Zst), Some(Compress::Gz)] {
+            let shared = Shared::default();
+            let mut output = Output::new(Box::new(shared.clone()), compress).unwrap();
+            output.write_all(b"username,blitz_games\nfoo,1\n").unwrap();
+            output.finish().unwrap();
+            let written = shared.0.lock().unwrap().clone();
+            let mut decoded = String::new();
+            match compress {
+                None => decoded = String::from_utf8(written).unwrap(),
+                Some(Compress::Zst) => {
+                    zstd::Decoder::new(&written[..])
+                        .unwrap()
+                        .read_to_string(&mut decoded)
+                        .unwrap();
+                }
+                Some(Compress::Gz) => {
+                    flate2::read::GzDecoder::new(&written[..])
+                        .read_to_string(&mut decoded)
+                        .unwrap();
+                }
+            };
+            assert_eq!(decoded, "username,blitz_games\nfoo,1\n");
+        }
+    }
+
+    #[test]
+    fn test_renamed_on_finish() {
+        let path = std::env::temp_dir().join(format!("output-test-{}.csv", std::process::id()));
+        fs::write(&path, "previous").unwrap();
+        let mut output = Output::create(path.to_str().unwrap(), None).unwrap();
+        output.write_all(b"username\n").unwrap();
+        output.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous");
+        output.finish().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "username\n");
+        assert!(!tmp_path(&path).exists());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Loading the results into PostgreSQL, one row per user and perf played like the sqlite
+
+use std::io::{self, Write};
+
+use postgres::{Client, NoTls};
+
+use crate::{columns::Column, sql, visitor::TimeSpents};
+
+/// `schema.table` with each part quoted
+fn quote_table(table: &str) -> String {
+    table
+        .split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+// keyed on the lowercase username, as usernames are case insensitive on lichess
+fn create_table(table: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+    username TEXT NOT NULL,
+    username_key TEXT GENERATED ALWAYS AS (lower(username)) STORED,
+    perf TEXT NOT NULL,
+    {},
+    PRIMARY KEY (username_key, perf)
+)",
+        sql::column_definitions("BIGINT")
+    )
+}
+
+// add to the existing rows, the averages being weighted by the games and rounded, and the
+// username taking the case of the latest load
+fn upsert(table: &str, from: &str) -> String {
+    let set: Vec<_> = Column::ALL
+        .map(|column| {
+            let name = column.header();
+            match column {
+                Column::Rating | Column::Plies => format!(
+                    "{name} = round(({table}.{name} * {table}.games \
+                    + excluded.{name} * excluded.games)::numeric \
+                    / ({table}.games + excluded.games))"
+                ),
+                _ => format!("{name} = {table}.{name} + excluded.{name}"),
+            }
+        })
+        .into();
+    let columns = sql::column_names();
+    format!(
+        "INSERT INTO {table} ({columns}) SELECT {columns} FROM {from}
+ON CONFLICT (username_key, perf) DO UPDATE SET
+    username = excluded.username,
+    {}",
+        set.join(",\n    ")
+    )
+}
+
+// text format of `COPY`
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// The rows in the text format of `COPY`, in the order of `sql::column_names`
+pub fn write_copy_rows<'a>(
+    w: &mut impl Write,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+) -> io::Result<()> {
+    for (username, perf, values) in sql::rows(users) {
+        write!(w, "{}\t{}", escape(username), perf.name())?;
+        for value in values {
+            write!(w, "\t{value}")?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Bulk-load the users into `table`, created if needed, in a single transaction.
+/// With `upsert`, users already in the table have the new games and times added,
+/// otherwise loading them again is an error
+pub fn load<'a>(
+    url: &str,
+    table: &str,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    upsert: bool,
+) -> io::Result<()> {
+    load_with(url, &quote_table(table), users, upsert).map_err(io::Error::other)
+}
+
+fn load_with<'a>(
+    url: &str,
+    table: &str,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    upsert: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut client = Client::connect(url, NoTls)?;
+    let mut tx = client.transaction()?;
+    tx.batch_execute(&create_table(table))?;
+    let target = if upsert {
+        tx.batch_execute(&format!(
+            "CREATE TEMP TABLE time_spent_load (LIKE {table} INCLUDING GENERATED) ON COMMIT DROP"
+        ))?;
+        "time_spent_load"
+    } else {
+        table
+    };
+    let columns = sql::column_names();
+    let mut copy = tx.copy_in(&format!("COPY {target} ({columns}) FROM STDIN"))?;
+    let mut w = io::BufWriter::new(&mut copy);
+    write_copy_rows(&mut w, users)?;
+    w.flush()?;
+    drop(w);
+    copy.finish()?;
+    if upsert {
+        tx.batch_execute(&self::upsert(table, target))?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{results::tests::one_game_each, visitor::Perf};
+
+    #[test]
+    fn test_quote_table() {
+        assert_eq!(quote_table("time_spent"), "\"time_spent\"");
+        assert_eq!(quote_table("lab.x\"y"), "\"lab\".\"x\"\"y\"");
+    }
+
+    #[test]
+    fn test_upsert() {
+        let upsert = upsert("t", "load");
+        assert!(upsert.starts_with(&format!("INSERT INTO t ({}) SELECT", sql::column_names())));
+        assert!(upsert.contains("ON CONFLICT (username_key, perf) DO UPDATE SET"));
+        assert!(upsert.contains(
+            "avg_plies = round((t.avg_plies * t.games + excluded.avg_plies * excluded.games)::numeric \
+            / (t.games + excluded.games)),"
+        ));
+        assert!(upsert.ends_with("black_real_time = t.black_real_time + excluded.black_real_time"));
+    }
+
+    // against the server of `LTS_TEST_POSTGRES`, like `postgres://postgres@localhost/postgres`
+    #[test]
+    fn test_merge_case_insensitive() {
+        let Ok(url) = std::env::var("LTS_TEST_POSTGRES") else {
+            return;
+        };
+        let table = format!("time_spent_test_{}", std::process::id());
+        let mut users = one_game_each("Foo", &[Perf::Blitz]);
+        load(&url, &table, &users, true).unwrap();
+        let blitz = users.get_mut("Foo").unwrap().get_mut(Perf::Blitz);
+        blitz.total_rating.0 = 2 * 2001;
+        blitz.nb_games = 2;
+        let renamed = users.remove("Foo").unwrap();
+        users.insert("foo".to_string(), renamed);
+        load(&url, &table, &users, true).unwrap();
+        let mut client = Client::connect(&url, NoTls).unwrap();
+        let rows = client
+            .query(
+                &format!("SELECT username, games, avg_rating FROM {table}"),
+                &[],
+            )
+            .unwrap();
+        client
+            .batch_execute(&format!("DROP TABLE {table}"))
+            .unwrap();
+        let rows: Vec<(String, i64, i64)> = rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+        // (2000 + 2 * 2001) / 3, rounded up
+        assert_eq!(rows, [("foo".to_string(), 3, 2001)]);
+    }
+
+    #[test]
+    fn test_write_copy_rows() {
+        let users = one_game_each("a\\b", &[Perf::Blitz]);
+        let mut w = Vec::new();
+        write_copy_rows(&mut w, &users).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "a\\\\b\tblitz\t1\t2000\t300\t400500\t1200\t0\t0\t0\t0\t0\t0\t1\t400500\n"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Players which are not actual accounts, like the `?` of some exports or the `ghost` of closed
+//! accounts. They are never credited, and their games are counted here instead
+
+use std::time::Duration;
+
+use rustc_hash::FxHashMap;
+
+use crate::analysis::{Aggregator, CountedGame};
+
+/// Username of the closed accounts
+const GHOST: &str = "ghost";
+
+pub fn is_placeholder(username: &str) -> bool {
+    matches!(username, "" | "?" | "-") || username.eq_ignore_ascii_case(GHOST)
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaceholderGames {
+    pub games: u64,
+    pub time_spent_exact: Duration,
+}
+
+/// Counted games by placeholder username, as written in the pgn
+#[derive(Default, Debug)]
+pub struct Placeholders(FxHashMap<String, PlaceholderGames>);
+
+impl Placeholders {
+    pub fn get(&self, username: &str) -> PlaceholderGames {
+        self.0.get(username).copied().unwrap_or_default()
+    }
+
+    /// Sorted by decreasing number of games
+    pub fn sorted(&self) -> Vec<(&str, PlaceholderGames)> {
+        let mut placeholders: Vec<_> = self.0.iter().map(|(u, g)| (u.as_str(), *g)).collect();
+        placeholders.sort_by(|(u1, g1), (u2, g2)| g2.games.cmp(&g1.games).then(u1.cmp(u2)));
+        placeholders
+    }
+}
+
+impl Aggregator for Placeholders {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        for player in [game.white, game.black] {
+            if is_placeholder(&player.username) {
+                let games = self.0.entry(player.username.clone()).or_default();
+                games.games += 1;
+                games.time_spent_exact += game.exact_duration;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "?"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "Ghost"]
+[Black "alice"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+"#;
+
+    #[test]
+    fn test_placeholders() {
+        let mut placeholders = Placeholders::default();
+        let users = AnalysisBuilder::new()
+            .aggregators(vec![&mut placeholders])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(users.keys().collect::<Vec<_>>(), vec!["alice"]);
+        assert_eq!(users["alice"].total_games(), 2);
+        assert_eq!(placeholders.get("?").games, 1);
+        assert_eq!(
+            placeholders.sorted(),
+            vec![
+                ("?", placeholders.get("?")),
+                ("Ghost", placeholders.get("Ghost"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_placeholder() {
+        assert!(is_placeholder(""));
+        assert!(is_placeholder("?"));
+        assert!(is_placeholder("ghost"));
+        assert!(!is_placeholder("ghost123"));
+    }
+}
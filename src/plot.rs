@@ -0,0 +1,851 @@
+//! Figures of the results, written as svg or png
+
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::Once,
+};
+
+use clap::ValueEnum;
+use plotters::{
+    chart::{MeshStyle, SeriesLabelStyle},
+    coord::{ranged1d::Ranged, CoordTranslate, Shift},
+    prelude::*,
+    style::{register_font, FontStyle},
+};
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use crate::{
+    output::Output,
+    results,
+    visitor::{Perf, Users},
+};
+
+/// Format of the figures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotFormat {
+    Svg,
+    Png,
+}
+
+impl PlotFormat {
+    /// Paths ending with `.png` are png, the others svg
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".png") {
+            Self::Png
+        } else {
+            Self::Svg
+        }
+    }
+}
+
+/// Colors of the figures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Light,
+    /// like the default lichess background
+    Dark,
+}
+
+impl Theme {
+    pub fn background(self) -> RGBColor {
+        match self {
+            Self::Light => WHITE,
+            Self::Dark => RGBColor(0x16, 0x15, 0x12),
+        }
+    }
+
+    pub fn foreground(self) -> RGBColor {
+        match self {
+            Self::Light => BLACK,
+            Self::Dark => RGBColor(0xba, 0xba, 0xba),
+        }
+    }
+
+    pub fn caption(self, size: u32) -> TextStyle<'static> {
+        ("sans-serif", size).into_font().color(&self.foreground())
+    }
+
+    fn text(self) -> TextStyle<'static> {
+        self.caption(15)
+    }
+
+    /// Axes, labels and grid in the colors of the theme
+    pub fn mesh<'m, 'a, 'b, X: Ranged, Y: Ranged, DB: DrawingBackend>(
+        self,
+        mesh: &'m mut MeshStyle<'a, 'b, X, Y, DB>,
+    ) -> &'m mut MeshStyle<'a, 'b, X, Y, DB> {
+        let foreground = self.foreground();
+        mesh.axis_style(foreground)
+            .bold_line_style(foreground.mix(0.2))
+            .light_line_style(foreground.mix(0.05))
+            .label_style(self.text())
+            .axis_desc_style(self.text())
+    }
+
+    /// Legend of the series in the colors of the theme
+    pub fn legend<'l, 'a, 'b, DB: DrawingBackend + 'a, CT: CoordTranslate>(
+        self,
+        legend: &'l mut SeriesLabelStyle<'a, 'b, DB, CT>,
+    ) -> &'l mut SeriesLabelStyle<'a, 'b, DB, CT> {
+        legend
+            .background_style(self.background().mix(0.8))
+            .border_style(self.foreground())
+            .label_font(self.text())
+    }
+}
+
+/// Width and height of the figures in pixels, like `1024x768`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PlotSize(pub u32, pub u32);
+
+impl Default for PlotSize {
+    fn default() -> Self {
+        Self(1024, 768)
+    }
+}
+
+impl FromStr for PlotSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {s:?}"))?;
+        let parse = |n: &str| match n.parse() {
+            Ok(0) => Err("the figure cannot be empty".to_string()),
+            Ok(n) => Ok(n),
+            Err(e) => Err(format!("{n:?}: {e}")),
+        };
+        Ok(Self(parse(width)?, parse(height)?))
+    }
+}
+
+impl TryFrom<String> for PlotSize {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for PlotSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.0, self.1)
+    }
+}
+
+/// How every figure is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlotStyle {
+    /// guessed from the path if not set
+    pub format: Option<PlotFormat>,
+    pub size: PlotSize,
+    pub theme: Theme,
+}
+
+pub type DrawResult<DB> = Result<(), DrawingAreaErrorKind<<DB as DrawingBackend>::ErrorType>>;
+
+/// A figure which can be drawn on any plotters backend
+pub trait Figure {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB>;
+}
+
+/// The text is rendered with an embedded font, so the png figures do not depend on the
+/// fonts installed
+fn register_fonts() {
+    static FONTS: Once = Once::new();
+    FONTS.call_once(|| {
+        for (style, font) in [
+            (FontStyle::Normal, notosans::REGULAR_TTF),
+            (FontStyle::Bold, notosans::BOLD_TTF),
+        ] {
+            if register_font("sans-serif", style, font).is_err() {
+                unreachable!("embedded font is valid");
+            }
+        }
+    });
+}
+
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    figure: &impl Figure,
+    theme: Theme,
+) -> io::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&theme.background()).map_err(io::Error::other)?;
+    figure.draw(&root, theme).map_err(io::Error::other)?;
+    root.present().map_err(io::Error::other)
+}
+
+/// Draw `figure` to a file, `-` for stdout
+pub fn write(path: &str, figure: &impl Figure, style: &PlotStyle) -> io::Result<()> {
+    register_fonts();
+    let size = (style.size.0, style.size.1);
+    let mut w = Output::create(path, None)?;
+    match style.format.unwrap_or(PlotFormat::from_path(path)) {
+        PlotFormat::Svg => {
+            let mut svg = String::new();
+            draw(
+                SVGBackend::with_string(&mut svg, size).into_drawing_area(),
+                figure,
+                style.theme,
+            )?;
+            w.write_all(svg.as_bytes())?;
+        }
+        PlotFormat::Png => {
+            let mut rgb = vec![0; size.0 as usize * size.1 as usize * 3];
+            draw(
+                BitMapBackend::with_buffer(&mut rgb, size).into_drawing_area(),
+                figure,
+                style.theme,
+            )?;
+            let mut encoder = png::Encoder::new(&mut w, size.0, size.1);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .write_header()
+                .and_then(|mut png| png.write_image_data(&rgb))
+                .map_err(io::Error::other)?;
+        }
+    }
+    w.finish()
+}
+
+/// Tick label without the trailing zeros, like `0.1` or `1000` on a log scale
+pub fn tick_label(value: &f64) -> String {
+    let label = format!("{value:.2}");
+    label
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Users with less real time are counted in the first bin
+const MIN_HOURS: f64 = 1. / 60.;
+
+// bins of hours doubling every two of them, from `MIN_HOURS`
+fn hours_bin(hours: f64) -> usize {
+    (2. * (hours / MIN_HOURS).log2()).max(0.) as usize
+}
+
+fn hours_lower_bound(bin: usize) -> f64 {
+    MIN_HOURS * 2_f64.powf(bin as f64 / 2.)
+}
+
+/// Number of users by hours of real time played, for each perf.
+/// The bins double every two of them, from a minute
+#[derive(Debug, Default)]
+pub struct Histogram {
+    counts: [Vec<u64>; 6],
+}
+
+impl Histogram {
+    pub fn new(users: &Users) -> Self {
+        let mut histogram = Self::default();
+        for time_spents in users.values() {
+            for (p, perf) in Perf::ALL.into_iter().enumerate() {
+                let exact = time_spents.get(perf).time_spent_exact;
+                if exact.is_zero() {
+                    continue;
+                }
+                let bin = hours_bin(exact.as_secs_f64() / 3600.);
+                let counts = &mut histogram.counts[p];
+                if counts.len() <= bin {
+                    counts.resize(bin + 1, 0);
+                }
+                counts[bin] += 1;
+            }
+        }
+        histogram
+    }
+}
+
+impl Figure for Histogram {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let nb_bins = self.counts.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        let max_count = self.counts.iter().flatten().max().copied().unwrap_or(1);
+        let mut chart = ChartBuilder::on(root)
+            .caption("Users by real time played", theme.caption(30))
+            .margin(20)
+            .x_label_area_size(50)
+            .y_label_area_size(70)
+            .build_cartesian_2d(
+                (MIN_HOURS..hours_lower_bound(nb_bins)).log_scale(),
+                (1_f64..max_count as f64 * 2.).log_scale(),
+            )?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .x_desc("hours")
+            .y_desc("users")
+            .x_label_formatter(&tick_label)
+            .y_label_formatter(&tick_label)
+            .draw()?;
+        for (p, perf) in Perf::ALL.into_iter().enumerate() {
+            let color = Palette99::pick(p).to_rgba();
+            // at the middle of each bin, in log scale
+            let points = self.counts[p]
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(bin, count)| (hours_lower_bound(bin) * 2_f64.powf(0.25), *count as f64));
+            chart
+                .draw_series(LineSeries::new(points, color.stroke_width(2)))?
+                .label(perf.name())
+                .legend(move |(x, y)| {
+                    PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2))
+                });
+        }
+        theme.legend(&mut chart.configure_series_labels()).draw()
+    }
+}
+
+/// Width of the bins of average rating
+const RATING_BIN: usize = 50;
+
+/// Number of users by average rating and hours of real time played, for each perf.
+/// Binned as there can be millions of users
+#[derive(Debug, Default)]
+pub struct RatingDensity {
+    // (rating bin, hours bin) to users
+    counts: [FxHashMap<(usize, usize), u64>; 6],
+}
+
+impl RatingDensity {
+    pub fn new(users: &Users) -> Self {
+        let mut density = Self::default();
+        for time_spents in users.values() {
+            for (p, perf) in Perf::ALL.into_iter().enumerate() {
+                let t = time_spents.get(perf);
+                if t.nb_games == 0 || t.time_spent_exact.is_zero() {
+                    continue;
+                }
+                let rating = t.total_rating.0 / t.nb_games / RATING_BIN;
+                let hours = hours_bin(t.time_spent_exact.as_secs_f64() / 3600.);
+                *density.counts[p].entry((rating, hours)).or_default() += 1;
+            }
+        }
+        density
+    }
+
+    fn draw_perf<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+        p: usize,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let counts = &self.counts[p];
+        let ratings = counts.keys().map(|(rating, _)| *rating);
+        let (min_rating, max_rating) = (
+            ratings.clone().min().unwrap_or(0),
+            ratings.max().unwrap_or(0),
+        );
+        let max_hours = counts.keys().map(|(_, hours)| *hours).max().unwrap_or(0);
+        let max_count = counts.values().max().copied().unwrap_or(1);
+        let mut chart = ChartBuilder::on(area)
+            .caption(Perf::ALL[p].name(), theme.caption(20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(
+                (min_rating * RATING_BIN) as f64..((max_rating + 1) * RATING_BIN) as f64,
+                (MIN_HOURS..hours_lower_bound(max_hours + 1)).log_scale(),
+            )?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .disable_mesh()
+            .x_desc("average rating")
+            .y_desc("hours")
+            .x_labels(6)
+            .x_label_formatter(&|rating| format!("{rating:.0}"))
+            .y_label_formatter(&tick_label)
+            .draw()?;
+        // log scale of the counts, the densest cells being hundreds of times the sparse ones
+        let scale = (max_count as f64).ln().max(1.);
+        chart.draw_series(counts.iter().map(|((rating, hours), count)| {
+            let x = (rating * RATING_BIN) as f64;
+            let color = ViridisRGB.get_color(((*count as f64).ln() / scale) as f32);
+            Rectangle::new(
+                [
+                    (x, hours_lower_bound(*hours)),
+                    (x + RATING_BIN as f64, hours_lower_bound(hours + 1)),
+                ],
+                color.filled(),
+            )
+        }))?;
+        Ok(())
+    }
+}
+
+impl Figure for RatingDensity {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let root = root.titled(
+            "Users by average rating and real time played",
+            theme.caption(30),
+        )?;
+        // a panel per perf played
+        let played = (0..Perf::ALL.len()).filter(|p| !self.counts[*p].is_empty());
+        for (area, p) in root.split_evenly((2, 3)).iter().zip(played) {
+            self.draw_perf(area, p, theme)?;
+        }
+        Ok(())
+    }
+}
+
+/// Players per perf in the top figure, unless set
+pub const TOP_N: usize = 10;
+
+/// Longest username shown in full on the top figure
+const TOP_NAME_LEN: usize = 14;
+
+/// The users with the most real time played, for each perf
+#[derive(Debug, Default)]
+pub struct Top {
+    /// username and hours, from the most active
+    perfs: [Vec<(String, f64)>; 6],
+}
+
+impl Top {
+    /// The `n` first users of each perf, ties broken by username
+    pub fn new(users: &Users, n: usize) -> Self {
+        let mut top = Self::default();
+        for (p, perf) in Perf::ALL.into_iter().enumerate() {
+            let mut hours: Vec<(&String, f64)> = users
+                .iter()
+                .map(|(username, t)| (username, t.get(perf).time_spent_exact.as_secs_f64() / 3600.))
+                .filter(|(_, hours)| *hours > 0.)
+                .collect();
+            let by_hours =
+                |a: &(&String, f64), b: &(&String, f64)| b.1.total_cmp(&a.1).then(a.0.cmp(b.0));
+            // no need to sort the millions of users after the first ones
+            if n < hours.len() {
+                hours.select_nth_unstable_by(n, by_hours);
+                hours.truncate(n);
+            }
+            hours.sort_unstable_by(by_hours);
+            top.perfs[p] = hours
+                .into_iter()
+                .map(|(username, hours)| (username.clone(), hours))
+                .collect();
+        }
+        top
+    }
+
+    fn draw_perf<DB: DrawingBackend>(
+        &self,
+        area: &DrawingArea<DB, Shift>,
+        p: usize,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let users = &self.perfs[p];
+        let max_hours = users.first().map_or(1., |(_, hours)| *hours);
+        let rows = users.len().max(1);
+        let mut chart = ChartBuilder::on(area)
+            .caption(Perf::ALL[p].name(), theme.caption(20))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(130)
+            // rows centered on the usernames
+            .build_cartesian_2d(0_f64..max_hours * 1.05, -0.5..rows as f64 - 0.5)?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .disable_y_mesh()
+            .x_desc("hours")
+            .x_labels(5)
+            .x_label_formatter(&|hours| format!("{hours:.0}"))
+            .y_labels(rows)
+            // the most active at the top
+            .y_label_formatter(&|y: &f64| {
+                let row = rows as i64 - 1 - y.round() as i64;
+                let username = usize::try_from(row)
+                    .ok()
+                    .filter(|_| (y - y.round()).abs() < 1e-6)
+                    .and_then(|row| users.get(row))
+                    .map_or("", |(username, _)| username.as_str());
+                match username.char_indices().nth(TOP_NAME_LEN) {
+                    Some((end, _)) => format!("{}…", &username[..end]),
+                    None => username.to_string(),
+                }
+            })
+            .draw()?;
+        let color = Palette99::pick(p).to_rgba();
+        chart.draw_series(users.iter().enumerate().map(|(row, (_, hours))| {
+            let y = (rows - 1 - row) as f64;
+            Rectangle::new([(0., y - 0.4), (*hours, y + 0.4)], color.filled())
+        }))?;
+        Ok(())
+    }
+}
+
+impl Figure for Top {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let root = root.titled("Users with the most real time played", theme.caption(30))?;
+        // a panel per perf played
+        let played = (0..Perf::ALL.len()).filter(|p| !self.perfs[*p].is_empty());
+        for (area, p) in root.split_evenly((2, 3)).iter().zip(played) {
+            self.draw_perf(area, p, theme)?;
+        }
+        Ok(())
+    }
+}
+
+/// Points of each Lorenz curve
+const LORENZ_POINTS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Curve {
+    label: &'static str,
+    /// share of the users, from the least active, to their share of the real time
+    points: Vec<(f64, f64)>,
+    gini: f64,
+}
+
+impl Curve {
+    // `times` sorted in increasing order
+    fn new(label: &'static str, times: &[f64]) -> Self {
+        let total: f64 = times.iter().sum();
+        let n = times.len();
+        let mut cumulative = Vec::with_capacity(n + 1);
+        cumulative.push(0.);
+        for time in times {
+            cumulative.push(cumulative.last().copied().unwrap_or(0.) + time);
+        }
+        let points = (0..=LORENZ_POINTS)
+            .map(|k| {
+                let users = n * k / LORENZ_POINTS;
+                (k as f64 / LORENZ_POINTS as f64, cumulative[users] / total)
+            })
+            .collect();
+        let weighted: f64 = times
+            .iter()
+            .enumerate()
+            .map(|(i, time)| (i + 1) as f64 * time)
+            .sum();
+        let n = n as f64;
+        Self {
+            label,
+            points,
+            gini: 2. * weighted / (n * total) - (n + 1.) / n,
+        }
+    }
+}
+
+/// How concentrated the real time is among the users, overall and per perf.
+/// The Gini coefficient is 0 when everyone plays as much, and nears 1 when a few play it all
+#[derive(Debug, Default)]
+pub struct Lorenz {
+    curves: Vec<Curve>,
+}
+
+impl Lorenz {
+    pub fn new(users: &Users) -> Self {
+        let mut lorenz = Self::default();
+        let mut add = |label, mut times: Vec<f64>| {
+            times.retain(|time| *time > 0.);
+            if !times.is_empty() {
+                times.sort_unstable_by(f64::total_cmp);
+                lorenz.curves.push(Curve::new(label, &times));
+            }
+        };
+        add(
+            "all",
+            users
+                .values()
+                .map(|t| t.total_exact().as_secs_f64())
+                .collect(),
+        );
+        for perf in Perf::ALL {
+            add(
+                perf.name(),
+                users
+                    .values()
+                    .map(|t| t.get(perf).time_spent_exact.as_secs_f64())
+                    .collect(),
+            );
+        }
+        lorenz
+    }
+
+    /// Gini coefficient of the real time over all perfs, none without users
+    pub fn gini(&self) -> Option<f64> {
+        self.curves
+            .first()
+            .filter(|curve| curve.label == "all")
+            .map(|curve| curve.gini)
+    }
+}
+
+impl Figure for Lorenz {
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        theme: Theme,
+    ) -> DrawResult<DB> {
+        let mut chart = ChartBuilder::on(root)
+            .caption("Concentration of the real time played", theme.caption(30))
+            .margin(20)
+            .x_label_area_size(50)
+            .y_label_area_size(70)
+            .build_cartesian_2d(0_f64..1., 0_f64..1.)?;
+        theme
+            .mesh(&mut chart.configure_mesh())
+            .x_desc("share of the users, from the least active")
+            .y_desc("share of the real time")
+            .x_label_formatter(&|x| format!("{:.0}%", x * 100.))
+            .y_label_formatter(&|y| format!("{:.0}%", y * 100.))
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            [(0., 0.), (1., 1.)],
+            theme.foreground().mix(0.3),
+        ))?;
+        for (i, curve) in self.curves.iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(
+                    curve.points.iter().copied(),
+                    color.stroke_width(2),
+                ))?
+                .label(format!("{} (Gini {:.2})", curve.label, curve.gini))
+                .legend(move |(x, y)| {
+                    PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2))
+                });
+        }
+        theme
+            .legend(&mut chart.configure_series_labels())
+            .position(SeriesLabelPosition::UpperLeft)
+            .draw()
+    }
+}
+
+/// Figures which only need the results, and can be drawn again from a `time-spent.csv`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PlotKind {
+    /// like --plot-histogram
+    Histogram,
+    /// like --plot-rating
+    Rating,
+    /// like --plot-lorenz
+    Lorenz,
+    /// like --plot-top
+    Top,
+}
+
+impl PlotKind {
+    /// `time-spent-<kind>.svg`, or `.png`
+    pub fn default_path(self, format: Option<PlotFormat>) -> String {
+        let kind = self.to_possible_value().expect("no skipped variant");
+        let extension = match format {
+            Some(PlotFormat::Png) => "png",
+            _ => "svg",
+        };
+        format!("time-spent-{}.{extension}", kind.get_name())
+    }
+}
+
+/// Draw a figure of the results at `path`, in seconds and possibly compressed, without
+/// reading the pgn again. `top_n` users per perf are drawn by `PlotKind::Top`
+pub fn run(
+    path: &str,
+    kind: PlotKind,
+    output: Option<&str>,
+    top_n: usize,
+    style: &PlotStyle,
+) -> io::Result<()> {
+    let users = results::load(path)?;
+    let default_path = kind.default_path(style.format);
+    let output = output.unwrap_or(&default_path);
+    match kind {
+        PlotKind::Histogram => write(output, &Histogram::new(&users), style),
+        PlotKind::Rating => write(output, &RatingDensity::new(&users), style),
+        PlotKind::Lorenz => write(output, &Lorenz::new(&users), style),
+        PlotKind::Top => write(output, &Top::new(&users, top_n), style),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::visitor::{Rating, TimeSpents};
+
+    pub(crate) fn svg(figure: &impl Figure) -> String {
+        register_fonts();
+        let mut svg = String::new();
+        let root = SVGBackend::with_string(&mut svg, (1024, 768)).into_drawing_area();
+        draw(root, figure, Theme::Dark).unwrap();
+        svg
+    }
+
+    #[test]
+    fn test_plot_size() {
+        assert_eq!("800x600".parse(), Ok(PlotSize(800, 600)));
+        assert!("800".parse::<PlotSize>().is_err());
+        assert!("0x600".parse::<PlotSize>().is_err());
+        assert_eq!(PlotSize::default().to_string(), "1024x768");
+    }
+
+    #[test]
+    fn test_write_png() {
+        let path = std::env::temp_dir().join(format!("plot-test-{}.png", std::process::id()));
+        let style = PlotStyle {
+            size: PlotSize(320, 240),
+            ..PlotStyle::default()
+        };
+        write(
+            path.to_str().unwrap(),
+            &Lorenz::new(&Users::default()),
+            &style,
+        )
+        .unwrap();
+        let png = std::fs::read(&path).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir();
+        let csv = dir.join(format!("plot-run-{}.csv", std::process::id()));
+        let svg = dir.join(format!("plot-run-{}.svg", std::process::id()));
+        std::fs::write(
+            &csv,
+            "username,blitz_games,blitz_avg_rating,blitz_real_time\nalice,2,1500,600\n",
+        )
+        .unwrap();
+        run(
+            csv.to_str().unwrap(),
+            PlotKind::Histogram,
+            svg.to_str(),
+            TOP_N,
+            &PlotStyle::default(),
+        )
+        .unwrap();
+        assert!(std::fs::read_to_string(&svg).unwrap().contains("blitz"));
+        std::fs::remove_file(csv).unwrap();
+        std::fs::remove_file(svg).unwrap();
+    }
+
+    #[test]
+    fn test_top() {
+        let mut users = Users::default();
+        for (username, minutes) in [("alice", 30), ("bob", 90), ("carol", 60), ("dave", 0)] {
+            let mut time_spents = TimeSpents::default();
+            let blitz = time_spents.get_mut(Perf::Blitz);
+            blitz.nb_games = 1;
+            blitz.time_spent_exact = Duration::from_secs(minutes * 60);
+            users.insert(username.to_string(), time_spents);
+        }
+        let top = Top::new(&users, 2);
+        let usernames: Vec<_> = top.perfs[2].iter().map(|(u, _)| u.as_str()).collect();
+        assert_eq!(usernames, ["bob", "carol"]);
+        assert_eq!(top.perfs[2][0].1, 1.5);
+        assert!(top.perfs[0].is_empty());
+        assert_eq!(Top::new(&users, 10).perfs[2].len(), 3);
+        let svg = svg(&top);
+        assert!(svg.contains("bob"));
+        assert!(!svg.contains("alice"));
+    }
+
+    #[test]
+    fn test_default_path() {
+        assert_eq!(PlotKind::Lorenz.default_path(None), "time-spent-lorenz.svg");
+        assert_eq!(
+            PlotKind::Rating.default_path(Some(PlotFormat::Png)),
+            "time-spent-rating.png"
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut users = Users::default();
+        for (username, minutes) in [("alice", 0), ("bob", 1), ("carol", 3), ("dave", 120)] {
+            let mut time_spents = TimeSpents::default();
+            let blitz = time_spents.get_mut(Perf::Blitz);
+            blitz.nb_games = 1;
+            blitz.time_spent_exact = Duration::from_secs(minutes * 60);
+            users.insert(username.to_string(), time_spents);
+        }
+        let histogram = Histogram::new(&users);
+        // bins of 1, 1.4, 2, 2.8 minutes, ...
+        assert_eq!(histogram.counts[2][..4], [1, 0, 0, 1]);
+        assert_eq!(histogram.counts[2].iter().sum::<u64>(), 3);
+        assert!(histogram.counts[0].is_empty());
+        assert!(svg(&histogram).contains("Users by real time played"));
+    }
+
+    #[test]
+    fn test_rating_density() {
+        let mut users = Users::default();
+        for (username, rating, minutes) in
+            [("alice", 1510, 60), ("bob", 1540, 61), ("carol", 2000, 60)]
+        {
+            let mut time_spents = TimeSpents::default();
+            let rapid = time_spents.get_mut(Perf::Rapid);
+            rapid.nb_games = 2;
+            rapid.total_rating = Rating(rating * 2);
+            rapid.time_spent_exact = Duration::from_secs(minutes * 60);
+            users.insert(username.to_string(), time_spents);
+        }
+        let density = RatingDensity::new(&users);
+        let hours = hours_bin(1.);
+        assert_eq!(density.counts[3].get(&(30, hours)), Some(&2));
+        assert_eq!(density.counts[3].get(&(40, hours)), Some(&1));
+        // only the perfs played have a panel
+        let svg = svg(&density);
+        assert!(svg.contains("rapid"));
+        assert!(!svg.contains("classical"));
+    }
+
+    #[test]
+    fn test_lorenz() {
+        let equal = Curve::new("equal", &[1., 1., 1., 1.]);
+        assert!(equal.gini.abs() < 1e-9);
+        assert_eq!(equal.points[LORENZ_POINTS / 2], (0.5, 0.5));
+        let one = Curve::new("one", &[0., 0., 0., 4.]);
+        assert!((one.gini - 0.75).abs() < 1e-9);
+        assert_eq!(one.points[LORENZ_POINTS * 3 / 4], (0.75, 0.));
+        let mut users = Users::default();
+        for (username, minutes) in [("alice", 10), ("bob", 30)] {
+            let mut time_spents = TimeSpents::default();
+            let bullet = time_spents.get_mut(Perf::Bullet);
+            bullet.nb_games = 1;
+            bullet.time_spent_exact = Duration::from_secs(minutes * 60);
+            users.insert(username.to_string(), time_spents);
+        }
+        let lorenz = Lorenz::new(&users);
+        assert_eq!(lorenz.curves.len(), 2);
+        assert!((lorenz.gini().unwrap() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tick_label() {
+        assert_eq!(tick_label(&0.1), "0.1");
+        assert_eq!(tick_label(&0.30000000000000004), "0.3");
+        assert_eq!(tick_label(&1000.), "1000");
+    }
+}
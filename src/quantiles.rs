@@ -0,0 +1,102 @@
+//! Streaming quantiles of durations, without keeping every sample
+
+use std::{collections::BTreeMap, time::Duration};
+
+/// Relative error of the quantiles
+const ACCURACY: f64 = 0.01;
+
+/// Durations counted in logarithmic bins of milliseconds, like a DDSketch: any quantile is
+/// within `ACCURACY` of the true one, in a few hundred bins whatever the number of samples
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Sketch {
+    /// shorter than a millisecond
+    zeros: u64,
+    bins: BTreeMap<i32, u64>,
+    count: u64,
+}
+
+// ratio between the bounds of a bin
+fn gamma() -> f64 {
+    (1. + ACCURACY) / (1. - ACCURACY)
+}
+
+impl Sketch {
+    pub fn add(&mut self, duration: Duration) {
+        self.count += 1;
+        let ms = duration.as_secs_f64() * 1000.;
+        if ms < 1. {
+            self.zeros += 1;
+        } else {
+            let bin = (ms.ln() / gamma().ln()).ceil() as i32;
+            *self.bins.entry(bin).or_default() += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.zeros += other.zeros;
+        self.count += other.count;
+        for (bin, count) in &other.bins {
+            *self.bins.entry(*bin).or_default() += count;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// `q` between 0 and 1, none without samples
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0., 1.) * (self.count - 1) as f64) as u64;
+        if rank < self.zeros {
+            return Some(Duration::ZERO);
+        }
+        let mut seen = self.zeros;
+        for (bin, count) in &self.bins {
+            seen += count;
+            if rank < seen {
+                // the middle of the bin, in relative terms
+                let ms = 2. * gamma().powi(*bin) / (gamma() + 1.);
+                return Some(Duration::from_secs_f64(ms / 1000.));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantiles() {
+        let mut sketch = Sketch::default();
+        assert_eq!(sketch.quantile(0.5), None);
+        for secs in 1..=1000 {
+            sketch.add(Duration::from_secs(secs));
+        }
+        for (q, expected) in [(0.5, 500.), (0.9, 900.), (0.99, 990.)] {
+            let quantile = sketch.quantile(q).unwrap().as_secs_f64();
+            assert!(
+                (quantile - expected).abs() <= expected * ACCURACY,
+                "{q}: {quantile}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = Sketch::default();
+        let mut b = Sketch::default();
+        a.add(Duration::ZERO);
+        b.add(Duration::from_secs(60));
+        b.add(Duration::from_secs(60));
+        a.merge(&b);
+        assert_eq!(a.count(), 3);
+        assert_eq!(a.quantile(0.), Some(Duration::ZERO));
+        let median = a.quantile(0.5).unwrap().as_secs_f64();
+        assert!((median - 60.).abs() <= 60. * ACCURACY);
+    }
+}
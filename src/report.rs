@@ -0,0 +1,130 @@
+//! Markdown report of the headline numbers, to paste in a forum post or an issue
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{
+    columns::TimeUnit,
+    results::{self, SortBy},
+    skips::SkipCounts,
+    summary::Summary,
+    visitor::Users,
+};
+
+// `|` would end the cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// The `top` players by real time, the totals per perf and the skipped games by reason
+pub fn write_markdown(
+    w: &mut impl Write,
+    users: &Users,
+    summary: &Summary,
+    skips: &SkipCounts,
+    top: usize,
+) -> io::Result<()> {
+    let human = |duration| TimeUnit::Human.display(duration);
+    writeln!(w, "## Top {top} players by real time\n")?;
+    writeln!(w, "| # | Player | Games | Real time | Approximate time |")?;
+    writeln!(w, "|--:|--------|------:|----------:|-----------------:|")?;
+    for (i, (username, time_spents)) in results::sort(users, SortBy::TotalTime)
+        .into_iter()
+        .take(top)
+        .enumerate()
+    {
+        writeln!(
+            w,
+            "| {} | [{}](https://lichess.org/@/{username}) | {} | {} | {} |",
+            i + 1,
+            cell(username),
+            time_spents.total_games(),
+            human(time_spents.total_exact()),
+            human(Duration::from_secs(time_spents.total_approximate() as u64))
+        )?;
+    }
+    writeln!(w, "\n## Totals per perf\n")?;
+    writeln!(
+        w,
+        "| Perf | Games | Players | Real hours | Approximate hours | Average game | Berserks |"
+    )?;
+    writeln!(
+        w,
+        "|------|------:|--------:|-----------:|------------------:|-------------:|---------:|"
+    )?;
+    for row in summary.rows(users) {
+        writeln!(
+            w,
+            "| {} | {} | {} | {:.0} | {:.0} | {} | {} |",
+            row.perf,
+            row.games,
+            row.users,
+            row.real_hours,
+            row.approximate_hours,
+            human(Duration::from_secs_f64(row.average_game_seconds)),
+            row.berserks
+        )?;
+    }
+    write!(
+        w,
+        "\n## Skipped games\n\n{} games counted, {} skipped.",
+        skips.counted(),
+        skips.total()
+    )?;
+    if skips.clock_mismatches() > 0 {
+        write!(
+            w,
+            " {} of the counted games have clocks not matching their plies.",
+            skips.clock_mismatches()
+        )?;
+    }
+    writeln!(w, "\n")?;
+    writeln!(w, "| Reason | Games |")?;
+    writeln!(w, "|--------|------:|")?;
+    for (reason, n) in skips.sorted() {
+        writeln!(w, "| {reason} | {n} |")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{Aggregator, AnalysisBuilder};
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Correspondence game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "-"]
+
+1. e4 e5 1-0
+"#;
+
+    #[test]
+    fn test_write_markdown() {
+        let mut summary = Summary::default();
+        let mut skips = SkipCounts::default();
+        let users = AnalysisBuilder::new()
+            .aggregators(vec![&mut summary as &mut dyn Aggregator, &mut skips])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let mut w = Vec::new();
+        write_markdown(&mut w, &users, &summary, &skips, 1).unwrap();
+        let report = String::from_utf8(w).unwrap();
+        assert!(report.starts_with("## Top 1 players by real time\n"));
+        assert_eq!(report.matches("](https://lichess.org/@/").count(), 1);
+        assert!(report.contains("| blitz | 1 | 2 |"));
+        assert!(report.contains("1 games counted, 1 skipped."));
+        assert!(report.ends_with("| too_few_plies | 1 |\n"));
+    }
+}
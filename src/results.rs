@@ -0,0 +1,865 @@
+//! Reading and writing the `time-spent.csv` results
+
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, BufReader, Write},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    columnar,
+    columns::{Column, JsonPerf, Ranks, TimeUnit},
+    input,
+    visitor::{Perf, TimeSpents, Users},
+    xlsx,
+};
+
+/// Field separator of the csv outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Delimiter {
+    #[default]
+    Comma,
+    Tab,
+    Semicolon,
+    Pipe,
+}
+
+impl Delimiter {
+    pub fn as_char(self) -> char {
+        match self {
+            Self::Comma => ',',
+            Self::Tab => '\t',
+            Self::Semicolon => ';',
+            Self::Pipe => '|',
+        }
+    }
+
+    fn from_char(c: char) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|d| d.as_char() == c)
+    }
+}
+
+/// How the csv outputs are formatted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: Delimiter,
+    /// emitted for each perf
+    pub columns: Vec<Column>,
+    pub time_unit: TimeUnit,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: Delimiter::default(),
+            columns: Column::ALL.to_vec(),
+            time_unit: TimeUnit::default(),
+        }
+    }
+}
+
+/// Quote `field` if it contains the delimiter, a quote or a line break
+pub fn escape(field: &str, delimiter: char) -> Cow<'_, str> {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+// inverse of `escape`, for a record whose quoted fields may contain line breaks
+fn split_fields(line: &str, delimiter: char) -> Vec<Cow<'_, str>> {
+    let mut fields = Vec::new();
+    let mut rest = line;
+    loop {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut field = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                if c != '"' {
+                    field.push(c);
+                } else if quoted[i + 1..].starts_with('"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    end = i + 1;
+                    break;
+                }
+            }
+            fields.push(Cow::Owned(field));
+            rest = &quoted[end..];
+            match rest.strip_prefix(delimiter) {
+                Some(r) => rest = r,
+                None => return fields,
+            }
+        } else {
+            match rest.split_once(delimiter) {
+                Some((field, r)) => {
+                    fields.push(Cow::Borrowed(field));
+                    rest = r;
+                }
+                None => {
+                    fields.push(Cow::Borrowed(rest));
+                    return fields;
+                }
+            }
+        }
+    }
+}
+
+/// Format of the results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// a single object keyed by username, then by perf and column
+    Json,
+    /// one object per line and user, with its `username`, like the json otherwise
+    Ndjson,
+    /// typed columns, named like the csv ones, with the exact durations in milliseconds
+    Parquet,
+    /// Arrow IPC file, also known as Feather, with the same columns as the parquet
+    Arrow,
+    /// SQLite database with a `time_spent` table of one row per user and perf played,
+    /// written with `sqlite::write` as it needs a path
+    Sqlite,
+    /// Excel workbook with a summary sheet of the totals, then one sheet per perf
+    Xlsx,
+}
+
+impl OutputFormat {
+    pub fn default_path(self) -> &'static str {
+        match self {
+            Self::Csv => "time-spent.csv",
+            Self::Json => "time-spent.json",
+            Self::Ndjson => "time-spent.ndjson",
+            Self::Parquet => "time-spent.parquet",
+            Self::Arrow => "time-spent.arrow",
+            Self::Sqlite => "time-spent.db",
+            Self::Xlsx => "time-spent.xlsx",
+        }
+    }
+}
+
+/// Order of the rows, by decreasing time and games but increasing username
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[value(name = "total_time")]
+    TotalTime,
+    Games,
+    Username,
+}
+
+pub fn sort(users: &Users, by: SortBy) -> Vec<(&String, &TimeSpents)> {
+    let mut rows: Vec<_> = users.iter().collect();
+    match by {
+        SortBy::TotalTime => {
+            rows.sort_by(|(u1, t1), (u2, t2)| (t2.total_exact(), u1).cmp(&(t1.total_exact(), u2)))
+        }
+        SortBy::Games => {
+            rows.sort_by(|(u1, t1), (u2, t2)| (t2.total_games(), u1).cmp(&(t1.total_games(), u2)))
+        }
+        SortBy::Username => rows.sort_by_key(|(username, _)| *username),
+    }
+    rows
+}
+
+pub fn write_csv<'a>(
+    w: &mut impl Write,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    let users: Vec<_> = users.into_iter().collect();
+    let ranks = options
+        .columns
+        .iter()
+        .any(|c| c.is_rank())
+        .then(|| ranks(&users));
+    write!(w, "username")?;
+    TimeSpents::write_csv_header(w, options)?;
+    writeln!(w)?;
+    for (i, (username, time_spents)) in users.into_iter().enumerate() {
+        write!(w, "{}", escape(username, options.delimiter.as_char()))?;
+        time_spents.to_csv(w, options, ranks.as_ref().map(|ranks| &ranks[i]))?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Ranks of each user in each perf played, ordered like `users` and `Perf::ALL`
+pub(crate) fn ranks(users: &[(&String, &TimeSpents)]) -> Vec<[Ranks; 6]> {
+    let mut ranks = vec![[Ranks::default(); 6]; users.len()];
+    for (p, perf) in Perf::ALL.into_iter().enumerate() {
+        let played = || {
+            users
+                .iter()
+                .enumerate()
+                .map(move |(i, (_, t))| (i, t.get(perf)))
+                .filter(|(_, t)| !t.is_empty())
+        };
+        for (i, rank) in competition_ranks(played().map(|(i, t)| (i, t.time_spent_exact))) {
+            ranks[i][p].by_time = rank;
+        }
+        for (i, rank) in competition_ranks(played().map(|(i, t)| (i, t.nb_games))) {
+            ranks[i][p].by_games = rank;
+        }
+    }
+    ranks
+}
+
+// `1224` ranking by decreasing key, of the indexed keys
+fn competition_ranks<K: Ord>(keys: impl Iterator<Item = (usize, K)>) -> Vec<(usize, usize)> {
+    let mut keys: Vec<_> = keys.collect();
+    keys.sort_by(|(_, k1), (_, k2)| k2.cmp(k1));
+    let mut ranks: Vec<(usize, usize)> = Vec::with_capacity(keys.len());
+    for (pos, (i, key)) in keys.iter().enumerate() {
+        let rank = match pos.checked_sub(1) {
+            Some(prev) if keys[prev].1 == *key => ranks[prev].1,
+            _ => pos + 1,
+        };
+        ranks.push((*i, rank));
+    }
+    ranks
+}
+
+// the perfs played by a user, keyed by their name
+struct JsonPerfs<'a> {
+    time_spents: &'a TimeSpents,
+    options: &'a CsvOptions,
+    ranks: Option<[Ranks; 6]>,
+}
+
+impl Serialize for JsonPerfs<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            Perf::ALL
+                .into_iter()
+                .enumerate()
+                .filter(|(_, perf)| !self.time_spents.get(*perf).is_empty())
+                .map(|(p, perf)| {
+                    let columns = JsonPerf {
+                        t: self.time_spents.get(perf),
+                        options: self.options,
+                        ranks: self.ranks.map(|ranks| ranks[p]),
+                    };
+                    (perf.name(), columns)
+                }),
+        )
+    }
+}
+
+// each user with its perfs, in the given order
+fn json_users<'u: 'o, 'o>(
+    users: impl IntoIterator<Item = (&'u String, &'u TimeSpents)>,
+    options: &'o CsvOptions,
+) -> Vec<(&'u String, JsonPerfs<'o>)> {
+    let users: Vec<_> = users.into_iter().collect();
+    let ranks = options
+        .columns
+        .iter()
+        .any(|c| c.is_rank())
+        .then(|| ranks(&users));
+    users
+        .into_iter()
+        .enumerate()
+        .map(|(i, (username, time_spents))| {
+            let perfs = JsonPerfs {
+                time_spents,
+                options,
+                ranks: ranks.as_ref().map(|ranks| ranks[i]),
+            };
+            (username, perfs)
+        })
+        .collect()
+}
+
+/// `{"username": {"blitz": {"games": 2, ...}}}` with the columns and time unit of `options`,
+/// users being written in the given order
+pub fn write_json<'a>(
+    w: &mut impl Write,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    serde_json::to_writer(&mut *w, &JsonUsers(json_users(users, options)))?;
+    writeln!(w)
+}
+
+struct JsonUsers<'a>(Vec<(&'a String, JsonPerfs<'a>)>);
+
+impl Serialize for JsonUsers<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(username, perfs)| (username, perfs)))
+    }
+}
+
+/// One line per user, `{"username": "foo", "blitz": {"games": 2, ...}}`
+pub fn write_ndjson<'a>(
+    w: &mut impl Write,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    for (username, perfs) in json_users(users, options) {
+        serde_json::to_writer(&mut *w, &NdjsonUser { username, perfs })?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct NdjsonUser<'a> {
+    username: &'a str,
+    #[serde(flatten)]
+    perfs: JsonPerfs<'a>,
+}
+
+/// The results in any format
+pub fn write<'a>(
+    w: &mut (impl Write + Send),
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    format: OutputFormat,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Csv => write_csv(w, users, options),
+        OutputFormat::Json => write_json(w, users, options),
+        OutputFormat::Ndjson => write_ndjson(w, users, options),
+        OutputFormat::Parquet => {
+            columnar::write_parquet(w, users, &options.columns).map_err(io::Error::other)
+        }
+        OutputFormat::Arrow => {
+            columnar::write_ipc(w, users, &options.columns).map_err(io::Error::other)
+        }
+        OutputFormat::Xlsx => xlsx::write(w, users, options),
+        OutputFormat::Sqlite => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "sqlite databases can only be written to a file",
+        )),
+    }
+}
+
+/// Human readable summary of a single user, one line per perf played
+pub fn write_profile(
+    w: &mut impl Write,
+    username: &str,
+    time_spents: &TimeSpents,
+) -> io::Result<()> {
+    let human = |duration| TimeUnit::Human.display(duration);
+    writeln!(w, "{username}")?;
+    for perf in Perf::ALL {
+        let t = time_spents.get(perf);
+        if t.is_empty() {
+            continue;
+        }
+        writeln!(
+            w,
+            "  {:<12}{:>6} games  {:>12} played  {:>10} setup  avg rating {}",
+            perf.name(),
+            t.nb_games,
+            human(t.time_spent_exact).to_string(),
+            human(t.setup_time).to_string(),
+            t.total_rating.0 / t.nb_games,
+        )?;
+    }
+    writeln!(
+        w,
+        "  {:<12}{:>6} games  {:>12} played",
+        "total",
+        time_spents.total_games(),
+        human(time_spents.total_exact()).to_string(),
+    )
+}
+
+// the lines of `r`, those ending within quotes being joined with the next ones.
+// An escaped field has an even number of quotes, so a record is complete when its count is even
+fn records(mut r: impl BufRead) -> impl Iterator<Item = io::Result<String>> {
+    std::iter::from_fn(move || {
+        let mut record = String::new();
+        loop {
+            match r.read_line(&mut record) {
+                Err(e) => return Some(Err(e)),
+                Ok(0) if record.is_empty() => return None,
+                Ok(0) => break,
+                Ok(_) if record.matches('"').count().is_multiple_of(2) => break,
+                Ok(_) => (),
+            }
+        }
+        let end = record.trim_end_matches(['\n', '\r']).len();
+        record.truncate(end);
+        Some(Ok(record))
+    })
+}
+
+/// `read_csv` of the file at `path`, possibly compressed with `--compress`
+pub fn load(path: &str) -> io::Result<Users> {
+    read_csv(BufReader::new(input::open(path, None, None)?))
+}
+
+/// Add the games and times of `other` to `users`
+pub fn merge(users: &mut Users, other: Users) {
+    for (username, time_spents) in other {
+        *users.entry(username).or_default() += time_spents;
+    }
+}
+
+/// Columns of the results written at `path`, without reading the users
+pub fn load_columns(path: &str) -> io::Result<Vec<(Perf, Column)>> {
+    let mut lines = records(BufReader::new(input::open(path, None, None)?));
+    let header = lines.next().transpose()?.unwrap_or_default();
+    Ok(parse_header(&header)?.1)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// the delimiter, and the perf and column of the fields following `username`
+fn parse_header(header: &str) -> io::Result<(char, Vec<(Perf, Column)>)> {
+    let d = header
+        .strip_prefix("username")
+        .and_then(|rest| match rest.chars().next() {
+            // no columns
+            None => Some(Delimiter::default()),
+            Some(c) => Delimiter::from_char(c),
+        })
+        .ok_or_else(|| invalid(format!("invalid header {header:?}")))?
+        .as_char();
+    let columns = split_fields(header, d)
+        .iter()
+        .skip(1)
+        .map(|name| {
+            Column::parse_header(name).ok_or_else(|| invalid(format!("unknown column {name:?}")))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok((d, columns))
+}
+
+/// The delimiter and columns are read from the header, durations must be in seconds
+pub fn read_csv(r: impl BufRead) -> io::Result<Users> {
+    let mut lines = records(r);
+    let header = lines.next().transpose()?.unwrap_or_default();
+    let (d, columns) = parse_header(&header)?;
+    let mut users = Users::default();
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        let fields = split_fields(&line, d);
+        let fields: Vec<&str> = fields.iter().map(|f| f.as_ref()).collect();
+        let time_spents = fields
+            .split_first()
+            .and_then(|(username, rest)| Some((username, TimeSpents::from_csv(&columns, rest)?)));
+        match time_spents {
+            Some((username, time_spents)) => {
+                users.insert(username.to_string(), time_spents);
+            }
+            None => return Err(invalid(format!("line {}: invalid row {line:?}", i + 2))),
+        }
+    }
+    Ok(users)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        analysis::AnalysisBuilder,
+        visitor::{ColorShare, Perf, Rating, TimeSpent},
+    };
+
+    /// `username` with a game as black in each of `perfs`, rated 2000, of 400.5s with 1.2s of
+    /// setup and approximated to 300s, for the tests of the writers
+    pub(crate) fn one_game_each(username: &str, perfs: &[Perf]) -> Users {
+        let mut time_spents = TimeSpents::default();
+        for perf in perfs {
+            time_spents.add_game(
+                *perf,
+                Duration::from_millis(400_500),
+                Duration::from_millis(1_200),
+                300,
+                Rating(2000),
+                false,
+            );
+        }
+        Users::from_iter([(username.to_string(), time_spents)])
+    }
+
+    // only values which survive the csv, ie. with a whole average rating and whole seconds
+    fn time_spent() -> impl Strategy<Value = TimeSpent> {
+        prop_oneof![
+            Just(TimeSpent::default()),
+            (
+                1..10_000usize,
+                600..3500usize,
+                1..10_000_000usize,
+                1..10_000_000u64,
+                0..100_000u64,
+                (0..10_000usize, 0..10_000usize, 0..10_000usize),
+                0..500usize,
+                (
+                    0..10_000usize,
+                    0..10_000_000u64,
+                    0..10_000usize,
+                    0..10_000_000u64
+                ),
+            )
+                .prop_map(
+                    |(
+                        nb_games,
+                        avg_rating,
+                        approximate,
+                        exact,
+                        setup,
+                        (wins, draws, losses),
+                        avg_plies,
+                        (white_games, white_exact, black_games, black_exact),
+                    )| {
+                        TimeSpent {
+                            nb_games,
+                            total_rating: Rating(avg_rating * nb_games),
+                            time_spent_approximate: approximate,
+                            time_spent_exact: Duration::from_secs(exact),
+                            setup_time: Duration::from_secs(setup),
+                            wins,
+                            draws,
+                            losses,
+                            total_plies: avg_plies * nb_games,
+                            as_white: ColorShare {
+                                nb_games: white_games,
+                                time_spent_exact: Duration::from_secs(white_exact),
+                            },
+                            as_black: ColorShare {
+                                nb_games: black_games,
+                                time_spent_exact: Duration::from_secs(black_exact),
+                            },
+                        }
+                    }
+                )
+        ]
+    }
+
+    pub(crate) fn time_spents() -> impl Strategy<Value = TimeSpents> {
+        proptest::array::uniform::<_, { Perf::ALL.len() }>(time_spent()).prop_map(|perfs| {
+            let mut time_spents = TimeSpents::default();
+            for (perf, time_spent) in Perf::ALL.into_iter().zip(perfs) {
+                *time_spents.get_mut(perf) = time_spent;
+            }
+            time_spents
+        })
+    }
+
+    pub(crate) fn users() -> impl Strategy<Value = Users> {
+        proptest::collection::hash_map("[a-zA-Z0-9_-]{2,20}", time_spents(), 0..20)
+            .prop_map(|users| users.into_iter().collect())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_csv_round_trip(users in users()) {
+            let mut w = Vec::new();
+            write_csv(&mut w, &users, &CsvOptions::default()).unwrap();
+            prop_assert_eq!(read_csv(&w[..]).unwrap(), users);
+        }
+
+        #[test]
+        fn prop_csv_round_trip_escaped(
+            usernames in proptest::collection::hash_set("[a-z,\"\r\n]{1,8}", 0..5),
+        ) {
+            let users: Users = usernames.into_iter().map(|u| (u, TimeSpents::default())).collect();
+            let mut w = Vec::new();
+            write_csv(&mut w, &users, &CsvOptions::default()).unwrap();
+            prop_assert_eq!(read_csv(&w[..]).unwrap(), users);
+        }
+
+        #[test]
+        fn prop_csv_round_trip_delimiter(
+            users in users(),
+            delimiter in proptest::sample::select(Delimiter::value_variants()),
+        ) {
+            let mut w = Vec::new();
+            let options = CsvOptions {
+                delimiter,
+                ..Default::default()
+            };
+            write_csv(&mut w, &users, &options).unwrap();
+            prop_assert_eq!(read_csv(&w[..]).unwrap(), users);
+        }
+
+        // without the games, approximate and exact time, which tell if a perf was played,
+        // the other columns cannot be read back
+        #[test]
+        fn prop_csv_round_trip_columns(
+            users in users(),
+            optional in proptest::sample::subsequence(vec![Column::Rating, Column::Setup], 0..=2),
+        ) {
+            let options = CsvOptions {
+                columns: [vec![Column::Games, Column::Approx, Column::Exact], optional].concat(),
+                ..Default::default()
+            };
+            let mut w = Vec::new();
+            write_csv(&mut w, &users, &options).unwrap();
+            let read = read_csv(&w[..]).unwrap();
+            let mut w2 = Vec::new();
+            write_csv(&mut w2, &read, &options).unwrap();
+            prop_assert_eq!(read_csv(&w2[..]).unwrap(), read);
+        }
+
+        #[test]
+        fn prop_sort_by_total_time(users in users()) {
+            let sorted = sort(&users, SortBy::TotalTime);
+            prop_assert_eq!(sorted.len(), users.len());
+            prop_assert!(sorted.windows(2).all(|w| w[0].1.total_exact() >= w[1].1.total_exact()));
+        }
+
+        #[test]
+        fn prop_escape_round_trip(
+            fields in proptest::collection::vec("[a-z,;|\t\" \r\n]{0,8}", 1..5),
+            delimiter in proptest::sample::select(Delimiter::value_variants()),
+        ) {
+            let d = delimiter.as_char();
+            let line = fields.iter().map(|f| escape(f, d)).collect::<Vec<_>>().join(&d.to_string());
+            prop_assert_eq!(split_fields(&line, d), fields);
+        }
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut time_spents = TimeSpents::default();
+        time_spents.add_game(
+            Perf::Blitz,
+            Duration::from_millis(400_500),
+            Duration::from_secs(3),
+            300,
+            Rating(2000),
+            true,
+        );
+        let users = Users::from_iter([("foo".to_string(), time_spents)]);
+        let mut w = Vec::new();
+        write_json(&mut w, &users, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            r#"{"foo":{"blitz":{"games":1,"avg_rating":2000,"approximate_time":300,"real_time":400,"setup_time":3,"wins":0,"draws":0,"losses":0,"avg_plies":0,"white_games":1,"white_real_time":400,"black_games":0,"black_real_time":0}}}
+"#
+        );
+        // the same columns and unit as the csv
+        let options = CsvOptions {
+            columns: vec![Column::Exact, Column::GamesPerHour, Column::RankByGames],
+            time_unit: TimeUnit::Minutes,
+            ..Default::default()
+        };
+        let mut w = Vec::new();
+        write_json(&mut w, &users, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            r#"{"foo":{"blitz":{"real_time":6.675,"games_per_hour":8.98876404494382,"rank_by_games":1}}}
+"#
+        );
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        let mut time_spents = TimeSpents::default();
+        time_spents.add_game(
+            Perf::Bullet,
+            Duration::from_secs(100),
+            Duration::ZERO,
+            60,
+            Rating(1500),
+            false,
+        );
+        let users = Users::from_iter([
+            ("foo".to_string(), time_spents.clone()),
+            ("bar".to_string(), time_spents),
+        ]);
+        let options = CsvOptions {
+            columns: vec![Column::Games, Column::Approx, Column::AvgGameTime],
+            ..Default::default()
+        };
+        let mut w = Vec::new();
+        write_ndjson(&mut w, sort(&users, SortBy::Username), &options).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            r#"{"username":"bar","bullet":{"games":1,"approximate_time":60,"avg_game_time":100}}
+{"username":"foo","bullet":{"games":1,"approximate_time":60,"avg_game_time":100}}
+"#
+        );
+    }
+
+    // per the pgn escaping rules, `\"` and `\\` stand for `"` and `\` in tag values
+    const ESCAPED_PGN: &str = r#"[Event "Rated \"Blitz\" game"]
+[White "the \"quoted\" one"]
+[Black "back\\slash,comma"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+"#;
+
+    #[test]
+    fn test_escaped_tag_values() {
+        let users = AnalysisBuilder::new()
+            .build()
+            .run(ESCAPED_PGN.as_bytes())
+            .unwrap();
+        let mut usernames: Vec<_> = users.keys().map(String::as_str).collect();
+        usernames.sort_unstable();
+        assert_eq!(usernames, vec![r"back\slash,comma", r#"the "quoted" one"#]);
+        let options = CsvOptions {
+            columns: vec![Column::Games],
+            ..Default::default()
+        };
+        let mut w = Vec::new();
+        write_csv(&mut w, sort(&users, SortBy::Username), &options).unwrap();
+        let csv = String::from_utf8(w).unwrap();
+        assert!(csv.contains(r#""back\slash,comma",,,1,,"#));
+        assert!(csv.contains(r#""the ""quoted"" one",,,1,,"#));
+        assert_eq!(read_csv(csv.as_bytes()).unwrap().len(), 2);
+        let mut w = Vec::new();
+        write_ndjson(&mut w, sort(&users, SortBy::Username), &options).unwrap();
+        let json = String::from_utf8(w).unwrap();
+        assert!(json.contains(r#""username":"back\\slash,comma""#));
+        assert!(json.contains(r#""username":"the \"quoted\" one""#));
+    }
+
+    #[test]
+    fn test_rank_columns() {
+        let mut users = Users::default();
+        for (username, games) in [("a", 1), ("b", 3), ("c", 1)] {
+            let mut time_spents = TimeSpents::default();
+            for _ in 0..games {
+                time_spents.add_game(
+                    Perf::Blitz,
+                    Duration::from_secs(100),
+                    Duration::ZERO,
+                    300,
+                    Rating(1500),
+                    false,
+                );
+            }
+            users.insert(username.to_string(), time_spents);
+        }
+        let options = CsvOptions {
+            columns: vec![Column::Games, Column::RankByGames],
+            ..Default::default()
+        };
+        let mut w = Vec::new();
+        write_csv(&mut w, sort(&users, SortBy::Username), &options).unwrap();
+        let rows: Vec<_> = String::from_utf8(w)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|row| {
+                row.split(',')
+                    .filter(|f| !f.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+        assert_eq!(rows, vec!["a,1,2", "b,3,1", "c,1,2"]);
+    }
+
+    #[test]
+    fn test_write_profile() {
+        let mut time_spents = TimeSpents::default();
+        let blitz = time_spents.get_mut(Perf::Blitz);
+        blitz.nb_games = 2;
+        blitz.total_rating = Rating(3000);
+        blitz.time_spent_exact = Duration::from_secs(3 * 3600 + 120);
+        blitz.time_spent_approximate = 360;
+        blitz.setup_time = Duration::from_secs(10);
+        let mut w = Vec::new();
+        write_profile(&mut w, "alice", &time_spents).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "alice
+  blitz            2 games         3h 2m played          0m setup  avg rating 1500
+  total            2 games         3h 2m played
+"
+        );
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut time_spents = TimeSpents::default();
+        time_spents.add_game(
+            Perf::Bullet,
+            Duration::from_secs(100),
+            Duration::ZERO,
+            60,
+            Rating(1500),
+            false,
+        );
+        time_spents.add_game(
+            Perf::Blitz,
+            Duration::from_secs(400),
+            Duration::ZERO,
+            300,
+            Rating(2000),
+            false,
+        );
+        let users = Users::from_iter([("foo".to_string(), time_spents)]);
+        let mut w = Vec::new();
+        write_csv(&mut w, &users, &CsvOptions::default()).unwrap();
+        let read = read_csv(&w[..]).unwrap();
+        let mut w2 = Vec::new();
+        write_csv(&mut w2, &read, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            String::from_utf8(w2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let previous =
+            read_csv(&b"username,blitz_games,blitz_real_time\nfoo,2,600\nbar,1,100\n"[..]);
+        let mut time_spents = TimeSpents::default();
+        time_spents.add_game(
+            Perf::Blitz,
+            Duration::from_secs(400),
+            Duration::ZERO,
+            300,
+            Rating(2000),
+            false,
+        );
+        let mut users = Users::from_iter([("foo".to_string(), time_spents)]);
+        merge(&mut users, previous.unwrap());
+        let foo = users["foo"].get(Perf::Blitz);
+        assert_eq!(foo.nb_games, 3);
+        assert_eq!(foo.time_spent_exact, Duration::from_secs(1000));
+        assert_eq!(users["bar"].get(Perf::Blitz).nb_games, 1);
+    }
+
+    #[test]
+    fn test_merge_colors() {
+        let previous = read_csv(
+            &b"username,blitz_games,blitz_real_time,blitz_white_games,blitz_white_real_time,\
+blitz_black_games,blitz_black_real_time\nfoo,2,600,1,200,1,400\n"[..],
+        );
+        let mut time_spents = TimeSpents::default();
+        time_spents.add_game(
+            Perf::Blitz,
+            Duration::from_secs(400),
+            Duration::ZERO,
+            300,
+            Rating(2000),
+            false,
+        );
+        let mut users = Users::from_iter([("foo".to_string(), time_spents)]);
+        merge(&mut users, previous.unwrap());
+        let foo = users["foo"].get(Perf::Blitz);
+        assert_eq!(foo.as_white.nb_games, 1);
+        assert_eq!(foo.as_white.time_spent_exact, Duration::from_secs(200));
+        assert_eq!(foo.as_black.nb_games, 2);
+        assert_eq!(foo.as_black.time_spent_exact, Duration::from_secs(800));
+    }
+}
@@ -0,0 +1,196 @@
+//! Sidecar describing the columns of the results and how they were computed,
+//! so pipelines can check what they ingest
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{
+    analysis::Buckets,
+    columns::{Column, Kind, TimeUnit},
+    results::{CsvOptions, OutputFormat},
+    skips::SkipCounts,
+    visitor::Perf,
+};
+
+#[derive(Debug, Serialize)]
+struct ColumnSchema {
+    name: String,
+    #[serde(rename = "type")]
+    data_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct Bucket {
+    perf: &'static str,
+    /// upper bound of the average game duration `base + 40 * increment`, none for the last one
+    max_average_seconds: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct Skips {
+    counted: u64,
+    /// counted games whose clocks do not match their plies
+    clock_mismatches: u64,
+    skipped: u64,
+    /// by reason, sorted by decreasing count
+    reasons: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Schema<'a> {
+    version: &'static str,
+    format: &'static str,
+    inputs: &'a [String],
+    buckets: Vec<Bucket>,
+    columns: Vec<ColumnSchema>,
+    /// none when the results were not read from a pgn
+    games: Option<Skips>,
+}
+
+/// Type and unit of a column, in the csv or columnar outputs
+fn column_type(
+    column: Column,
+    format: OutputFormat,
+    unit: TimeUnit,
+) -> (&'static str, Option<&'static str>) {
+    let columnar = matches!(format, OutputFormat::Parquet | OutputFormat::Arrow);
+    match column.kind() {
+        Kind::Count => ("integer", None),
+        Kind::Float => ("float", Some("games/h")),
+        Kind::Time if columnar => ("integer", column.integer_unit()),
+        Kind::Time => match unit {
+            TimeUnit::Seconds => ("integer", Some("s")),
+            TimeUnit::Minutes => ("float", Some("min")),
+            TimeUnit::Hours => ("float", Some("h")),
+            TimeUnit::Human => ("string", Some("human")),
+        },
+    }
+}
+
+impl<'a> Schema<'a> {
+    /// Only the csv, parquet and arrow results have columns to describe
+    pub fn new(
+        format: OutputFormat,
+        options: &CsvOptions,
+        buckets: Buckets,
+        inputs: &'a [String],
+        skips: Option<&SkipCounts>,
+    ) -> io::Result<Self> {
+        let format_name = match format {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
+            OutputFormat::Json
+            | OutputFormat::Ndjson
+            | OutputFormat::Sqlite
+            | OutputFormat::Xlsx => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "only the csv, parquet and arrow results have a schema",
+                ))
+            }
+        };
+        let mut columns = vec![ColumnSchema {
+            name: "username".to_string(),
+            data_type: "string",
+            unit: None,
+        }];
+        for perf in Perf::ALL {
+            columns.extend(options.columns.iter().map(|column| {
+                let (data_type, unit) = column_type(*column, format, options.time_unit);
+                ColumnSchema {
+                    name: format!("{}_{}", perf.name(), column.header()),
+                    data_type,
+                    unit,
+                }
+            }));
+        }
+        let bounds = buckets.bounds();
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION"),
+            format: format_name,
+            inputs,
+            buckets: Perf::REAL_TIME
+                .into_iter()
+                .enumerate()
+                .map(|(i, perf)| Bucket {
+                    perf: perf.name(),
+                    max_average_seconds: bounds.get(i).copied(),
+                })
+                .collect(),
+            columns,
+            games: skips.map(|skips| Skips {
+                counted: skips.counted(),
+                clock_mismatches: skips.clock_mismatches(),
+                skipped: skips.total(),
+                reasons: skips
+                    .sorted()
+                    .into_iter()
+                    .map(|(reason, n)| (reason.to_string(), n))
+                    .collect(),
+            }),
+        })
+    }
+
+    pub fn write_json(&self, w: &mut impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *w, self)?;
+        writeln!(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::Delimiter;
+
+    #[test]
+    fn test_schema() {
+        let options = CsvOptions {
+            delimiter: Delimiter::Comma,
+            columns: vec![Column::Games, Column::Exact],
+            time_unit: TimeUnit::Hours,
+        };
+        let inputs = ["lichess_db_standard_rated_2024-01.pgn.zst".to_string()];
+        let skips = SkipCounts::default();
+        let schema = Schema::new(
+            OutputFormat::Csv,
+            &options,
+            Buckets::default(),
+            &inputs,
+            Some(&skips),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&schema).unwrap();
+        assert_eq!(json["columns"].as_array().unwrap().len(), 1 + 2 * 6);
+        assert_eq!(
+            json["columns"][2],
+            serde_json::json!({"name": "ultrabullet_real_time", "type": "float", "unit": "h"})
+        );
+        assert_eq!(
+            json["buckets"][4],
+            serde_json::json!({"perf": "classical", "max_average_seconds": null})
+        );
+        assert_eq!(json["games"]["counted"], 0);
+        assert_eq!(json["games"]["clock_mismatches"], 0);
+        let parquet = Schema::new(
+            OutputFormat::Parquet,
+            &options,
+            Buckets::default(),
+            &inputs,
+            None,
+        )
+        .unwrap();
+        assert_eq!(parquet.columns[2].unit, Some("ms"));
+        assert!(Schema::new(
+            OutputFormat::Json,
+            &options,
+            Buckets::default(),
+            &inputs,
+            None
+        )
+        .is_err());
+    }
+}
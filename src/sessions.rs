@@ -0,0 +1,185 @@
+//! Sessions of consecutive games of the same perf, to tell apart short breaks from marathons
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, TimeDelta};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    results::{escape, Delimiter},
+    visitor::Perf,
+};
+
+#[derive(Default, Debug)]
+struct PerfSessions {
+    /// number of games of each finished session
+    sizes: Vec<u32>,
+    /// end of the last game and number of games of the current session
+    ongoing: Option<(NaiveDateTime, u32)>,
+}
+
+impl PerfSessions {
+    fn add_game(&mut self, start: NaiveDateTime, end: NaiveDateTime, gap: TimeDelta) {
+        match &mut self.ongoing {
+            // games are not strictly ordered in the dumps, overlapping ones are in the same session
+            Some((last_end, games)) if start - *last_end <= gap => {
+                *last_end = (*last_end).max(end);
+                *games += 1;
+            }
+            ongoing => {
+                if let Some((_, games)) = ongoing.replace((end, 1)) {
+                    self.sizes.push(games)
+                }
+            }
+        }
+    }
+
+    fn stats(&self) -> Option<SessionStats> {
+        let mut sizes = self.sizes.clone();
+        sizes.extend(self.ongoing.map(|(_, games)| games));
+        sizes.sort_unstable();
+        let n = sizes.len();
+        let max_games = *sizes.last()?;
+        Some(SessionStats {
+            sessions: n,
+            median_games: f64::from(sizes[(n - 1) / 2] + sizes[n / 2]) / 2.,
+            max_games,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionStats {
+    pub sessions: usize,
+    pub median_games: f64,
+    pub max_games: u32,
+}
+
+/// A new session starts when a player has not played a game of the perf for more than `gap`.
+/// Bots, excluded players and games without a start are ignored
+#[derive(Debug)]
+pub struct Sessions {
+    gap: TimeDelta,
+    users: FxHashMap<String, [PerfSessions; 6]>,
+}
+
+impl Sessions {
+    pub fn new(gap: Duration) -> Self {
+        Self {
+            gap: TimeDelta::from_std(gap).expect("session gap out of range"),
+            users: FxHashMap::default(),
+        }
+    }
+
+    pub fn get(&self, username: &str, perf: Perf) -> Option<SessionStats> {
+        self.users.get(username)?[perf_index(perf)].stats()
+    }
+
+    /// `username,perf,sessions,median_games,max_games`, sorted by username
+    pub fn write_csv(&self, w: &mut impl Write, delimiter: Delimiter) -> io::Result<()> {
+        let d = delimiter.as_char();
+        writeln!(w, "username{d}perf{d}sessions{d}median_games{d}max_games")?;
+        let mut usernames: Vec<_> = self.users.keys().collect();
+        usernames.sort_unstable();
+        for username in usernames {
+            for perf in Perf::ALL {
+                if let Some(stats) = self.get(username, perf) {
+                    writeln!(
+                        w,
+                        "{}{d}{}{d}{}{d}{}{d}{}",
+                        escape(username, d),
+                        perf.name(),
+                        stats.sessions,
+                        stats.median_games,
+                        stats.max_games
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn perf_index(perf: Perf) -> usize {
+    Perf::ALL
+        .iter()
+        .position(|p| *p == perf)
+        .expect("perf in Perf::ALL")
+}
+
+impl Aggregator for Sessions {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        let Some(start) = game.start else {
+            return;
+        };
+        let end = start
+            + TimeDelta::from_std(game.setup_duration + game.exact_duration)
+                .expect("game duration out of range");
+        for player in [game.white, game.black] {
+            if !player.is_bot && !player.is_excluded {
+                let perfs = match self.users.get_mut(&player.username) {
+                    Some(perfs) => perfs,
+                    None => self.users.entry(player.username.clone()).or_default(),
+                };
+                perfs[perf_index(game.perf)].add_game(start, end, self.gap);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    fn game(white: &str, black: &str, time: &str) -> String {
+        format!(
+            r#"[Event "Rated Blitz game"]
+[UTCDate "2023.01.31"]
+[UTCTime "{time}"]
+[White "{white}"]
+[Black "{black}"]
+[TimeControl "180+0"]
+
+1. e4 {{ [%clk 0:03:00] }} 1... e5 {{ [%clk 0:03:00] }} 2. Nf3 {{ [%clk 0:02:00] }} 2... Nc6 {{ [%clk 0:02:00] }} 1-0
+
+"#
+        )
+    }
+
+    #[test]
+    fn test_sessions() {
+        let pgn = [
+            game("alice", "bob", "12:00:00"),
+            game("alice", "bob", "12:05:00"),
+            game("alice", "carol", "12:30:00"),
+            game("alice", "bob", "18:00:00"),
+        ]
+        .concat();
+        let mut sessions = Sessions::new(Duration::from_secs(30 * 60));
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut sessions])
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        let alice = sessions.get("alice", Perf::Blitz).unwrap();
+        assert_eq!(alice.sessions, 2);
+        assert_eq!(alice.median_games, 2.);
+        assert_eq!(alice.max_games, 3);
+        assert!(sessions.get("alice", Perf::Bullet).is_none());
+        let mut w = Vec::new();
+        sessions.write_csv(&mut w, Delimiter::Comma).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,perf,sessions,median_games,max_games
+alice,blitz,2,2,3
+bob,blitz,2,1.5,2
+carol,blitz,1,1,1
+"
+        );
+    }
+}
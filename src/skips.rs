@@ -0,0 +1,294 @@
+//! Why a game is not counted, shared by every report of the skipped games
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::analysis::{Aggregator, CountedGame};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParseErrorKind {
+    TimeControl,
+    Rating,
+    Clock,
+    /// the number of plies does not match the number of clocks
+    PlyCount,
+    /// unknown `Variant` header
+    Variant,
+}
+
+/// Option of the analysis which excluded the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// not one of the sampled games
+    Sample,
+    Users,
+    Excluded,
+    Bots,
+    Rating,
+    Date,
+    Perf,
+    /// rated or casual
+    Rated,
+    MaxPlies,
+    /// a variant, without `AnalysisBuilder::variants`
+    Variant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SkipReason {
+    /// no `TimeControl` header
+    NoTimeControl,
+    TooFewPlies,
+    /// the final clocks are above the initial ones even with the time added by +15s presses
+    ClockUnderflow,
+    ParseError {
+        kind: ParseErrorKind,
+    },
+    Filtered {
+        which: Filter,
+    },
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::TimeControl => "time_control",
+            Self::Rating => "rating",
+            Self::Clock => "clock",
+            Self::PlyCount => "ply_count",
+            Self::Variant => "variant",
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sample => "sample",
+            Self::Users => "users",
+            Self::Excluded => "excluded",
+            Self::Bots => "bots",
+            Self::Rating => "rating",
+            Self::Date => "date",
+            Self::Perf => "perf",
+            Self::Rated => "rated",
+            Self::MaxPlies => "max_plies",
+            Self::Variant => "variant",
+        })
+    }
+}
+
+/// Like `parse_error:clock` or `filtered:date`
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoTimeControl => f.write_str("no_time_control"),
+            Self::TooFewPlies => f.write_str("too_few_plies"),
+            Self::ClockUnderflow => f.write_str("clock_underflow"),
+            Self::ParseError { kind } => write!(f, "parse_error:{kind}"),
+            Self::Filtered { which } => write!(f, "filtered:{which}"),
+        }
+    }
+}
+
+/// Number of skipped games by reason
+#[derive(Default, Debug)]
+pub struct SkipCounts {
+    skipped: FxHashMap<SkipReason, u64>,
+    counted: u64,
+    clock_mismatches: u64,
+}
+
+impl SkipCounts {
+    pub fn get(&self, reason: SkipReason) -> u64 {
+        self.skipped.get(&reason).copied().unwrap_or_default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.skipped.values().sum()
+    }
+
+    pub fn counted(&self) -> u64 {
+        self.counted
+    }
+
+    /// Counted games whose clocks do not match their plies
+    pub fn clock_mismatches(&self) -> u64 {
+        self.clock_mismatches
+    }
+
+    /// Sorted by decreasing count
+    pub fn sorted(&self) -> Vec<(SkipReason, u64)> {
+        let mut counts: Vec<_> = self.skipped.iter().map(|(r, n)| (*r, *n)).collect();
+        counts.sort_by(|(r1, n1), (r2, n2)| n2.cmp(n1).then(r1.to_string().cmp(&r2.to_string())));
+        counts
+    }
+
+    /// Counted and skipped games, the counted ones with a clock/ply mismatch, then the skipped ones
+    /// by reason
+    pub fn write_report(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            w,
+            "{} games counted, {} skipped",
+            self.counted,
+            self.total()
+        )?;
+        if self.clock_mismatches > 0 {
+            writeln!(
+                w,
+                "{} counted games have clocks not matching their plies",
+                self.clock_mismatches
+            )?;
+        }
+        for (reason, n) in self.sorted() {
+            writeln!(w, "  {:<24}{n:>10}", reason.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for SkipCounts {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        self.counted += 1;
+        self.clock_mismatches += u64::from(game.clock_mismatch);
+    }
+
+    fn skip_game(&mut self, _: &str, reason: SkipReason) {
+        *self.skipped.entry(reason).or_default() += 1;
+    }
+
+    fn add_variant_game(&mut self, game: &CountedGame<'_>) {
+        self.counted += 1;
+        self.clock_mismatches += u64::from(game.clock_mismatch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::AnalysisBuilder, visitor::Perf};
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+
+1. e4 e5 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[WhiteElo "2300"]
+[BlackElo "1500"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[WhiteElo "2300"]
+[BlackElo "1500"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:03:10] } 2... Nc6 { [%clk 0:03:10] } 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[WhiteElo "1000"]
+[BlackElo "1500"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[WhiteElo "2300"]
+[BlackElo "1500"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%eval 0.3] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[WhiteElo "2300"]
+[BlackElo "1500"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 2... Nc6 { [%clk 0:02:40] } 3. Bc4 { [%clk 0:02:30] } 1-0
+"#;
+
+    #[test]
+    fn test_skip_counts() {
+        let mut skips = SkipCounts::default();
+        let users = AnalysisBuilder::new()
+            .filter_rating(2000..=4000)
+            .skip_clock_mismatches()
+            .aggregators(vec![&mut skips])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        // the clocks of the game with +15s presses rose, but it is counted
+        assert_eq!(users["alice"].get(Perf::Blitz).nb_games, 1);
+        assert_eq!(skips.counted(), 1);
+        assert_eq!(skips.clock_mismatches(), 0);
+        assert_eq!(skips.total(), 5);
+        assert_eq!(skips.get(SkipReason::NoTimeControl), 1);
+        assert_eq!(skips.get(SkipReason::TooFewPlies), 1);
+        assert_eq!(skips.get(SkipReason::ClockUnderflow), 0);
+        assert_eq!(
+            skips.get(SkipReason::Filtered {
+                which: Filter::Rating
+            }),
+            1
+        );
+        assert_eq!(
+            skips.get(SkipReason::ParseError {
+                kind: ParseErrorKind::Clock
+            }),
+            1
+        );
+        assert_eq!(
+            skips.get(SkipReason::ParseError {
+                kind: ParseErrorKind::PlyCount
+            }),
+            1
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let mut skips = SkipCounts::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut skips])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let mut w = Vec::new();
+        skips.write_report(&mut w).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "3 games counted, 3 skipped
+1 counted games have clocks not matching their plies
+  no_time_control                  1
+  parse_error:clock                1
+  too_few_plies                    1
+"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(SkipReason::TooFewPlies.to_string(), "too_few_plies");
+        let reason = SkipReason::Filtered {
+            which: Filter::MaxPlies,
+        };
+        assert_eq!(reason.to_string(), "filtered:max_plies");
+    }
+}
@@ -0,0 +1,46 @@
+//! Columns of the `time_spent` table of the databases, one row per user and perf played with
+//! the columns needed to read the results back, the times being integers in the
+//! `Column::integer_unit` of their column like the parquet
+
+use crate::{
+    columns::Column,
+    visitor::{Perf, TimeSpents},
+};
+
+/// `username, perf, games, ...`
+pub fn column_names() -> String {
+    ["username", "perf"]
+        .into_iter()
+        .chain(Column::ALL.map(Column::header))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The definitions of the columns following `username` and `perf`, all of them being
+/// of the `integer` type
+pub fn column_definitions(integer: &str) -> String {
+    Column::ALL
+        .map(|column| format!("{} {integer} NOT NULL", column.header()))
+        .join(",\n    ")
+}
+
+/// The values of `Column::ALL` of each user and perf played
+pub fn rows<'a>(
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+) -> impl Iterator<Item = (&'a String, Perf, [u64; Column::ALL.len()])> {
+    users.into_iter().flat_map(|(username, time_spents)| {
+        Perf::ALL.into_iter().filter_map(move |perf| {
+            let t = time_spents.get(perf);
+            if t.is_empty() {
+                return None;
+            }
+            let values = Column::ALL.map(|column| {
+                column
+                    .value(t, None)
+                    .and_then(|cell| cell.as_u64(column))
+                    .expect("integer column of the results")
+            });
+            Some((username, perf, values))
+        })
+    })
+}
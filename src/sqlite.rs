@@ -0,0 +1,190 @@
+//! SQLite export of the results, one row per user and perf played
+
+use std::{fs, io, path::Path};
+
+use rusqlite::{params_from_iter, types::ToSql, Connection};
+
+use crate::{columns::Column, output, sql, visitor::TimeSpents};
+
+// usernames are case insensitive, as on lichess. Appended rows have a period, part of the key
+fn schema(append: bool) -> String {
+    let period = if append {
+        ",\n    period TEXT NOT NULL,\n    PRIMARY KEY (username, perf, period)"
+    } else {
+        ""
+    };
+    format!(
+        "CREATE TABLE IF NOT EXISTS time_spent (
+    username TEXT NOT NULL COLLATE NOCASE,
+    perf TEXT NOT NULL,
+    {}{period}
+);",
+        sql::column_definitions("INTEGER")
+    )
+}
+
+// created once the rows are inserted, which is faster
+const INDEXES: &str = "
+CREATE INDEX IF NOT EXISTS time_spent_username ON time_spent (username);
+CREATE INDEX IF NOT EXISTS time_spent_perf ON time_spent (perf);
+";
+
+/// Replace the database at `path` by one with a `time_spent` table, once it is complete
+pub fn write<'a>(
+    path: &Path,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+) -> io::Result<()> {
+    // left over by an interrupted run
+    let tmp = output::tmp_path(path);
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    let mut conn = Connection::open(&tmp).map_err(io::Error::other)?;
+    insert(&mut conn, users, None).map_err(io::Error::other)?;
+    conn.close().map_err(|(_, e)| io::Error::other(e))?;
+    fs::rename(tmp, path)
+}
+
+/// Add the users to the `time_spent` table of the database at `path` as the rows of `period`,
+/// creating them if needed. The rows of the same usernames and period are deleted first, so
+/// that a period can be run again. Usernames are matched with ASCII case folding, like
+/// `columnar::append_parquet`
+pub fn append<'a>(
+    path: &Path,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    period: &str,
+) -> io::Result<()> {
+    let mut conn = Connection::open(path).map_err(io::Error::other)?;
+    insert(&mut conn, users, Some(period)).map_err(io::Error::other)?;
+    conn.close().map_err(|(_, e)| io::Error::other(e))
+}
+
+fn insert<'a>(
+    conn: &mut Connection,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    period: Option<&str>,
+) -> rusqlite::Result<()> {
+    let users: Vec<_> = users.into_iter().collect();
+    let tx = conn.transaction()?;
+    tx.execute_batch(&schema(period.is_some()))?;
+    if let Some(period) = period {
+        // `NOCASE` folding the ASCII letters only
+        let mut delete =
+            tx.prepare("DELETE FROM time_spent WHERE period = ?1 AND username = ?2")?;
+        for (username, _) in &users {
+            delete.execute((period, username))?;
+        }
+    }
+    {
+        let (verb, names) = match period {
+            Some(_) => (
+                "INSERT OR REPLACE",
+                format!("{}, period", sql::column_names()),
+            ),
+            None => ("INSERT", sql::column_names()),
+        };
+        let placeholders = vec!["?"; 2 + Column::ALL.len() + usize::from(period.is_some())];
+        let mut insert = tx.prepare(&format!(
+            "{verb} INTO time_spent ({names}) VALUES ({})",
+            placeholders.join(", ")
+        ))?;
+        for (username, perf, values) in sql::rows(users) {
+            let params: [&dyn ToSql; 2] = [username, &perf.name()];
+            insert.execute(params_from_iter(
+                params
+                    .into_iter()
+                    .chain(values.iter().map(|v| v as &dyn ToSql))
+                    .chain(period.iter().map(|p| p as &dyn ToSql)),
+            ))?;
+        }
+    }
+    tx.execute_batch(INDEXES)?;
+    tx.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{results::tests::one_game_each, visitor::Perf};
+
+    #[test]
+    fn test_write() {
+        let users = one_game_each("Foo", &[Perf::Blitz, Perf::Rapid]);
+        let path = std::env::temp_dir().join(format!("sqlite-test-{}.db", std::process::id()));
+        fs::write(&path, "not a database").unwrap();
+        write(&path, &users).unwrap();
+        let conn = Connection::open(&path).unwrap();
+        let row: (String, u64, u64, u64) = conn
+            .query_row(
+                "SELECT perf, games, real_time, black_games FROM time_spent
+                WHERE username = 'foo' AND perf = 'rapid'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        let rows: u64 = conn
+            .query_row("SELECT count(*) FROM time_spent", [], |row| row.get(0))
+            .unwrap();
+        drop(conn);
+        fs::remove_file(path).unwrap();
+        assert_eq!(row, ("rapid".to_string(), 1, 400_500, 1));
+        assert_eq!(rows, 2);
+    }
+
+    #[test]
+    fn test_append() {
+        let path = std::env::temp_dir().join(format!("sqlite-append-{}.db", std::process::id()));
+        let mut users = one_game_each("foo", &[Perf::Blitz, Perf::Rapid]);
+        users.extend(one_game_each("bar", &[Perf::Bullet]));
+        users.extend(one_game_each("Élodie", &[Perf::Bullet]));
+        append(&path, &users, "2024-01").unwrap();
+        append(&path, &one_game_each("foo", &[Perf::Blitz]), "2024-02").unwrap();
+        // a re-run of January, where foo only played blitz, twice as many games, and where
+        // only the ASCII letters of usernames fold
+        let mut again = one_game_each("Foo", &[Perf::Blitz]);
+        let blitz = again.values().next().unwrap().get(Perf::Blitz).clone();
+        *again.values_mut().next().unwrap().get_mut(Perf::Blitz) += blitz;
+        again.extend(one_game_each("éLODIE", &[Perf::Blitz]));
+        append(&path, &again, "2024-01").unwrap();
+        let conn = Connection::open(&path).unwrap();
+        let mut select = conn
+            .prepare(
+                "SELECT username, perf, period, games FROM time_spent
+                ORDER BY period, perf, username",
+            )
+            .unwrap();
+        let rows: Vec<(String, String, String, u64)> = select
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        drop(select);
+        drop(conn);
+        let row = |username: &str, perf: &str, period: &str, games| {
+            (
+                username.to_string(),
+                perf.to_string(),
+                period.to_string(),
+                games,
+            )
+        };
+        assert_eq!(
+            rows,
+            [
+                row("Foo", "blitz", "2024-01", 2),
+                row("éLODIE", "blitz", "2024-01", 1),
+                row("bar", "bullet", "2024-01", 1),
+                row("Élodie", "bullet", "2024-01", 1),
+                row("foo", "blitz", "2024-02", 1),
+            ]
+        );
+        // not appended to a database without periods
+        fs::remove_file(&path).unwrap();
+        write(&path, &users).unwrap();
+        assert!(append(&path, &users, "2024-03").is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! Compact binary format of the aggregated `Users`, so partial results can be saved
+//! and loaded back without the precision loss of the csv.
+//!
+//! A state starts with `MAGIC` and the little endian `u16` version of its layout,
+//! followed by the bincode encoded payload. Released layouts are never modified:
+//! a change adds a new version, and older ones are to be upgraded when read.
+
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::visitor::{ColorShare, Perf, Rating, TimeSpent, TimeSpents, Users};
+
+const MAGIC: &[u8; 4] = b"UTSA";
+
+/// Version of the layout written by `write`
+pub const VERSION: u16 = 1;
+
+/// `TimeSpent` with fixed size integers
+#[derive(Serialize, Deserialize)]
+struct PerfState {
+    nb_games: u64,
+    total_rating: u64,
+    time_spent_exact: Duration,
+    time_spent_approximate: u64,
+    setup_time: Duration,
+    wins: u64,
+    draws: u64,
+    losses: u64,
+    total_plies: u64,
+    as_white: ColorState,
+    as_black: ColorState,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ColorState {
+    nb_games: u64,
+    time_spent_exact: Duration,
+}
+
+/// The perfs are ordered like `Perf::ALL`
+#[derive(Serialize, Deserialize)]
+struct State {
+    users: Vec<(String, [PerfState; Perf::ALL.len()])>,
+}
+
+impl From<ColorShare> for ColorState {
+    fn from(share: ColorShare) -> Self {
+        Self {
+            nb_games: share.nb_games as u64,
+            time_spent_exact: share.time_spent_exact,
+        }
+    }
+}
+
+impl From<ColorState> for ColorShare {
+    fn from(state: ColorState) -> Self {
+        Self {
+            nb_games: state.nb_games as usize,
+            time_spent_exact: state.time_spent_exact,
+        }
+    }
+}
+
+impl From<&TimeSpent> for PerfState {
+    fn from(t: &TimeSpent) -> Self {
+        Self {
+            nb_games: t.nb_games as u64,
+            total_rating: t.total_rating.0 as u64,
+            time_spent_exact: t.time_spent_exact,
+            time_spent_approximate: t.time_spent_approximate as u64,
+            setup_time: t.setup_time,
+            wins: t.wins as u64,
+            draws: t.draws as u64,
+            losses: t.losses as u64,
+            total_plies: t.total_plies as u64,
+            as_white: t.as_white.into(),
+            as_black: t.as_black.into(),
+        }
+    }
+}
+
+impl From<PerfState> for TimeSpent {
+    fn from(t: PerfState) -> Self {
+        Self {
+            nb_games: t.nb_games as usize,
+            total_rating: Rating(t.total_rating as usize),
+            time_spent_exact: t.time_spent_exact,
+            time_spent_approximate: t.time_spent_approximate as usize,
+            setup_time: t.setup_time,
+            wins: t.wins as usize,
+            draws: t.draws as usize,
+            losses: t.losses as usize,
+            total_plies: t.total_plies as usize,
+            as_white: t.as_white.into(),
+            as_black: t.as_black.into(),
+        }
+    }
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Users are written sorted by username, so a given state always has the same encoding
+pub fn write(mut w: impl Write, users: &Users) -> io::Result<()> {
+    let mut sorted: Vec<_> = users.iter().collect();
+    sorted.sort_unstable_by_key(|(username, _)| *username);
+    let state = State {
+        users: sorted
+            .into_iter()
+            .map(|(username, t)| (username.clone(), Perf::ALL.map(|perf| t.get(perf).into())))
+            .collect(),
+    };
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    bincode::serialize_into(w, &state).map_err(invalid_data)
+}
+
+/// Read a state written by `write`
+pub fn read(mut r: impl Read) -> io::Result<Users> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a time spent state"));
+    }
+    let mut version = [0; 2];
+    r.read_exact(&mut version)?;
+    let state: State = match u16::from_le_bytes(version) {
+        VERSION => bincode::deserialize_from(r).map_err(invalid_data)?,
+        v => {
+            return Err(invalid_data(format!(
+                "state version {v} is not the supported {VERSION}"
+            )))
+        }
+    };
+    Ok(state
+        .users
+        .into_iter()
+        .map(|(username, perfs)| {
+            let mut time_spents = TimeSpents::default();
+            for (perf, t) in Perf::ALL.into_iter().zip(perfs) {
+                *time_spents.get_mut(perf) = t.into();
+            }
+            (username, time_spents)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::results::tests::users;
+    use proptest::prelude::*;
+
+    // `alice` with one blitz game as white of 150.05s, rated 1500, approximated to 180s with 12s
+    // of setup, won in 4 plies. Written by version 1, must keep loading whatever the current
+    // version is
+    fn v1() -> Vec<u8> {
+        let blitz: &[&[u8]] = &[
+            &[1, 0, 0, 0, 0, 0, 0, 0],                           // nb_games
+            &[0xdc, 0x05, 0, 0, 0, 0, 0, 0],                     // total_rating
+            &[150, 0, 0, 0, 0, 0, 0, 0, 0x80, 0xf0, 0xfa, 0x02], // time_spent_exact
+            &[180, 0, 0, 0, 0, 0, 0, 0],                         // time_spent_approximate
+            &[12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],              // setup_time
+            &[1, 0, 0, 0, 0, 0, 0, 0],                           // wins
+            &[0; 16],                                            // draws and losses
+            &[4, 0, 0, 0, 0, 0, 0, 0],                           // total_plies
+            &[1, 0, 0, 0, 0, 0, 0, 0],                           // games as white
+            &[150, 0, 0, 0, 0, 0, 0, 0, 0x80, 0xf0, 0xfa, 0x02], // time as white
+            &[0; 20],                                            // games and time as black
+        ];
+        let blitz = blitz.concat();
+        let empty = vec![0; blitz.len()];
+        [
+            &[b'U', b'T', b'S', b'A', 1, 0][..], // magic and version
+            &[1, 0, 0, 0, 0, 0, 0, 0],           // number of users
+            &[5, 0, 0, 0, 0, 0, 0, 0, b'a', b'l', b'i', b'c', b'e'], // username
+            &empty,                              // ultrabullet
+            &empty,                              // bullet
+            &blitz,
+            &empty, // rapid
+            &empty, // classical
+            &empty, // correspondence
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_read_write_v1() {
+        let users = read(&v1()[..]).unwrap();
+        let blitz = users["alice"].get(Perf::Blitz);
+        assert_eq!(blitz.nb_games, 1);
+        assert_eq!(blitz.total_rating, Rating(1500));
+        assert_eq!(blitz.time_spent_exact, Duration::from_millis(150_050));
+        assert_eq!(blitz.time_spent_approximate, 180);
+        assert_eq!(blitz.setup_time, Duration::from_secs(12));
+        assert_eq!((blitz.wins, blitz.draws, blitz.losses), (1, 0, 0));
+        assert_eq!(blitz.total_plies, 4);
+        assert_eq!(users["alice"].total_games(), 1);
+        assert_eq!(blitz.as_white.nb_games, 1);
+        assert_eq!(blitz.as_black.nb_games, 0);
+        let mut w = Vec::new();
+        write(&mut w, &users).unwrap();
+        assert_eq!(w, v1());
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut state = v1();
+        state[4] = 42;
+        assert_eq!(
+            read(&state[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(read(&b"username,"[..]).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_state_round_trip(
+            mut users in users(),
+            (white_games, black_games) in (0..1000usize, 0..1000usize),
+        ) {
+            for time_spents in users.values_mut() {
+                let blitz = time_spents.get_mut(Perf::Blitz);
+                blitz.as_white.nb_games = white_games;
+                blitz.as_black.nb_games = black_games;
+            }
+            let mut w = Vec::new();
+            write(&mut w, &users).unwrap();
+            prop_assert_eq!(read(&w[..]).unwrap(), users);
+        }
+    }
+}
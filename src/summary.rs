@@ -0,0 +1,344 @@
+//! Site-wide totals per perf, of the games rather than of the players
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    quantiles::Sketch,
+    results::Delimiter,
+    visitor::{Perf, Users},
+};
+
+#[derive(Default, Debug, Clone, PartialEq)]
+struct Totals {
+    games: u64,
+    exact: Duration,
+    /// in seconds
+    approximate: u64,
+    /// players who berserked
+    berserks: u64,
+    /// sums of the squares of the per-game values above, for the variance of the sampled totals
+    exact_squares: f64,
+    approximate_squares: f64,
+    berserk_squares: u64,
+    /// real durations of the games with clocks
+    durations: Sketch,
+}
+
+#[derive(Default, Debug)]
+pub struct Summary {
+    perfs: [Totals; 6],
+    /// `--sample`, one game counted in that many
+    sample: Option<u64>,
+}
+
+/// 95% confidence intervals of the totals estimated from a sample, the low bounds being at least
+/// the sampled totals
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Intervals {
+    pub games_ci_low: u64,
+    pub games_ci_high: u64,
+    pub real_hours_ci_low: f64,
+    pub real_hours_ci_high: f64,
+    pub approximate_hours_ci_low: f64,
+    pub approximate_hours_ci_high: f64,
+    pub berserks_ci_low: u64,
+    pub berserks_ci_high: u64,
+}
+
+/// Row of the summary, `perf` being `all` for the totals over every perf
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SummaryRow {
+    pub perf: &'static str,
+    pub games: u64,
+    /// credited with at least one game
+    pub users: usize,
+    pub real_hours: f64,
+    pub approximate_hours: f64,
+    /// from the real time, 0 without games
+    pub average_game_seconds: f64,
+    /// players who berserked, counted once per game and side
+    pub berserks: u64,
+    /// median real duration of the games with clocks, none without such games
+    pub p50_game_seconds: Option<f64>,
+    pub p90_game_seconds: Option<f64>,
+    pub p99_game_seconds: Option<f64>,
+    /// with `--sample` only, around the totals scaled up by the sampling rate
+    #[serde(flatten)]
+    pub intervals: Option<Intervals>,
+}
+
+impl SummaryRow {
+    fn new(perf: &'static str, totals: &Totals, users: usize, sample: Option<u64>) -> Self {
+        let quantile = |q| totals.durations.quantile(q).map(|d| d.as_secs_f64());
+        let n = sample.unwrap_or(1);
+        Self {
+            perf,
+            games: totals.games * n,
+            users,
+            real_hours: totals.exact.as_secs_f64() * n as f64 / 3600.,
+            approximate_hours: (totals.approximate * n) as f64 / 3600.,
+            average_game_seconds: if totals.games == 0 {
+                0.
+            } else {
+                totals.exact.as_secs_f64() / totals.games as f64
+            },
+            berserks: totals.berserks * n,
+            p50_game_seconds: quantile(0.5),
+            p90_game_seconds: quantile(0.9),
+            p99_game_seconds: quantile(0.99),
+            intervals: sample.map(|n| Intervals::new(totals, n)),
+        }
+    }
+}
+
+impl Intervals {
+    /// Each game being counted independently with a probability `p` of `1 / n`, from the hash
+    /// of its link, the variance of a total scaled up by `n` is estimated by `(1 - p) / p²`
+    /// times the sum of the squares of the sampled values, under a normal approximation
+    fn new(totals: &Totals, n: u64) -> Self {
+        let n = n as f64;
+        let interval = |sum: f64, squares: f64| {
+            let margin = 1.96 * (n * (n - 1.) * squares).sqrt();
+            ((sum * n - margin).max(sum), sum * n + margin)
+        };
+        let count = |sum: u64, squares: u64| {
+            let (low, high) = interval(sum as f64, squares as f64);
+            (low.round() as u64, high.round() as u64)
+        };
+        let hours = |sum: f64, squares: f64| {
+            let (low, high) = interval(sum, squares);
+            (low / 3600., high / 3600.)
+        };
+        let games = count(totals.games, totals.games);
+        let real_hours = hours(totals.exact.as_secs_f64(), totals.exact_squares);
+        let approximate_hours = hours(totals.approximate as f64, totals.approximate_squares);
+        let berserks = count(totals.berserks, totals.berserk_squares);
+        Self {
+            games_ci_low: games.0,
+            games_ci_high: games.1,
+            real_hours_ci_low: real_hours.0,
+            real_hours_ci_high: real_hours.1,
+            approximate_hours_ci_low: approximate_hours.0,
+            approximate_hours_ci_high: approximate_hours.1,
+            berserks_ci_low: berserks.0,
+            berserks_ci_high: berserks.1,
+        }
+    }
+}
+
+impl Summary {
+    /// Of the games counted with `--sample n`, whose totals are scaled up by `n`
+    pub fn sampled(n: u64) -> Self {
+        Self {
+            sample: Some(n),
+            ..Self::default()
+        }
+    }
+
+    /// One row per perf played, then the totals. `users` are the credited players, of the
+    /// sampled games only with `--sample`
+    pub fn rows(&self, users: &Users) -> Vec<SummaryRow> {
+        let mut rows = Vec::new();
+        let mut all = Totals::default();
+        for (i, perf) in Perf::ALL.into_iter().enumerate() {
+            let totals = &self.perfs[i];
+            if totals.games == 0 {
+                continue;
+            }
+            all.games += totals.games;
+            all.exact += totals.exact;
+            all.approximate += totals.approximate;
+            all.berserks += totals.berserks;
+            all.exact_squares += totals.exact_squares;
+            all.approximate_squares += totals.approximate_squares;
+            all.berserk_squares += totals.berserk_squares;
+            all.durations.merge(&totals.durations);
+            let users = users.values().filter(|t| t.get(perf).nb_games > 0).count();
+            rows.push(SummaryRow::new(perf.name(), totals, users, self.sample));
+        }
+        rows.push(SummaryRow::new("all", &all, users.len(), self.sample));
+        rows
+    }
+
+    /// `perf,games,users,real_hours,approximate_hours,average_game_seconds,berserks`, then the
+    /// median, 90th and 99th percentiles of the game durations, and with `--sample` the low and
+    /// high bounds of the games, real and approximate hours and berserks
+    pub fn write_csv(
+        &self,
+        w: &mut impl Write,
+        users: &Users,
+        delimiter: Delimiter,
+    ) -> io::Result<()> {
+        let d = delimiter.as_char();
+        write!(
+            w,
+            "perf{d}games{d}users{d}real_hours{d}approximate_hours{d}average_game_seconds{d}berserks{d}\
+             p50_game_seconds{d}p90_game_seconds{d}p99_game_seconds"
+        )?;
+        if self.sample.is_some() {
+            write!(
+                w,
+                "{d}games_ci_low{d}games_ci_high{d}real_hours_ci_low{d}real_hours_ci_high{d}\
+                 approximate_hours_ci_low{d}approximate_hours_ci_high{d}berserks_ci_low{d}\
+                 berserks_ci_high"
+            )?;
+        }
+        writeln!(w)?;
+        let seconds = |s: Option<f64>| s.map_or(String::new(), |s| format!("{s:.1}"));
+        for row in self.rows(users) {
+            write!(
+                w,
+                "{}{d}{}{d}{}{d}{:.2}{d}{:.2}{d}{:.1}{d}{}{d}{}{d}{}{d}{}",
+                row.perf,
+                row.games,
+                row.users,
+                row.real_hours,
+                row.approximate_hours,
+                row.average_game_seconds,
+                row.berserks,
+                seconds(row.p50_game_seconds),
+                seconds(row.p90_game_seconds),
+                seconds(row.p99_game_seconds),
+            )?;
+            if let Some(ci) = row.intervals {
+                write!(
+                    w,
+                    "{d}{}{d}{}{d}{:.2}{d}{:.2}{d}{:.2}{d}{:.2}{d}{}{d}{}",
+                    ci.games_ci_low,
+                    ci.games_ci_high,
+                    ci.real_hours_ci_low,
+                    ci.real_hours_ci_high,
+                    ci.approximate_hours_ci_low,
+                    ci.approximate_hours_ci_high,
+                    ci.berserks_ci_low,
+                    ci.berserks_ci_high,
+                )?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// The rows as a json array
+    pub fn write_json(&self, w: &mut impl Write, users: &Users) -> io::Result<()> {
+        serde_json::to_writer(&mut *w, &self.rows(users))?;
+        writeln!(w)
+    }
+}
+
+impl Aggregator for Summary {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        let i = Perf::ALL
+            .iter()
+            .position(|p| *p == game.perf)
+            .expect("perf in Perf::ALL");
+        let totals = &mut self.perfs[i];
+        let berserks = game.berserks.iter().filter(|b| **b).count() as u64;
+        totals.games += 1;
+        totals.exact += game.exact_duration;
+        totals.approximate += game.approximate_duration as u64;
+        totals.berserks += berserks;
+        totals.exact_squares += game.exact_duration.as_secs_f64().powi(2);
+        totals.approximate_squares += (game.approximate_duration as f64).powi(2);
+        totals.berserk_squares += berserks * berserks;
+        if game.has_clocks {
+            totals.durations.add(game.exact_duration);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Bullet game"]
+[White "alice"]
+[Black "carol"]
+[TimeControl "60+0"]
+
+1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 { [%clk 0:00:58] } 2... Nc6 { [%clk 0:00:57] } 1-0
+
+[Event "Rated Blitz tournament https://lichess.org/tournament/abcdefgh"]
+[White "bob"]
+[Black "carol"]
+[TimeControl "180+2"]
+
+1. e4 { [%clk 0:01:30] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:01:20] } 2... Nc6 { [%clk 0:03:00] } 1-0
+"#;
+
+    #[test]
+    fn test_summary() {
+        let mut summary = Summary::default();
+        let users = AnalysisBuilder::new()
+            .aggregators(vec![&mut summary])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let rows = summary.rows(&users);
+        assert_eq!(
+            rows.iter()
+                .map(|r| (r.perf, r.games, r.users))
+                .collect::<Vec<_>>(),
+            [("bullet", 1, 2), ("blitz", 2, 3), ("all", 3, 3)]
+        );
+        // bob berserked, only counting for half of the base time
+        assert_eq!(rows[1].berserks, 1);
+        assert_eq!(rows[2].approximate_hours, (60. + 260. + 175.) / 3600.);
+        // from the sketch, within 1% of the 5, 14 and 38 seconds of the games
+        let near = |s: Option<f64>, expected: f64| (s.unwrap() - expected).abs() <= expected * 0.01;
+        assert!(near(rows[0].p50_game_seconds, 5.));
+        assert!(near(rows[2].p50_game_seconds, 14.));
+        assert!(near(rows[2].p99_game_seconds, 14.));
+        let mut w = Vec::new();
+        summary.write_json(&mut w, &users).unwrap();
+        assert!(String::from_utf8(w)
+            .unwrap()
+            .starts_with(r#"[{"perf":"bullet","games":1,"users":2,"#));
+    }
+
+    #[test]
+    fn test_sampled() {
+        let mut summary = Summary::sampled(2);
+        let users = AnalysisBuilder::new()
+            .aggregators(vec![&mut summary])
+            .sample(2)
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        let rows = summary.rows(&users);
+        let all = rows.last().unwrap();
+        let sampled = summary.perfs.iter().map(|t| t.games).sum::<u64>();
+        assert_eq!(all.games, 2 * sampled);
+        let ci = all.intervals.as_ref().unwrap();
+        assert!(ci.games_ci_low <= all.games && all.games <= ci.games_ci_high);
+        assert!(ci.games_ci_low >= sampled);
+        assert!(ci.real_hours_ci_low <= all.real_hours && all.real_hours < ci.real_hours_ci_high);
+        assert!(summary.rows(&users).iter().all(|r| r.intervals.is_some()));
+        let mut w = Vec::new();
+        summary.write_csv(&mut w, &users, Delimiter::Comma).unwrap();
+        let csv = String::from_utf8(w).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .ends_with(",berserks_ci_low,berserks_ci_high"));
+        assert!(lines.all(|line| line.split(',').count() == 18));
+        let mut w = Vec::new();
+        summary.write_json(&mut w, &users).unwrap();
+        assert!(String::from_utf8(w).unwrap().contains(r#""games_ci_low":"#));
+    }
+}
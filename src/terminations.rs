@@ -0,0 +1,189 @@
+//! How the games of each user ended, per perf
+
+use std::io::{self, Write};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    placeholders::is_placeholder,
+    results::{escape, Delimiter},
+    visitor::{Perf, Score},
+};
+
+/// `Termination` header of the lichess dumps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationHeader {
+    /// resignation, checkmate, stalemate, draw agreement...
+    Normal,
+    TimeForfeit,
+    /// left the game, and was timed out by the server
+    Abandoned,
+    /// typically a game aborted because of cheating
+    RulesInfraction,
+}
+
+impl TerminationHeader {
+    pub fn from_header(value: &[u8]) -> Option<Self> {
+        Some(match value {
+            b"Normal" => Self::Normal,
+            b"Time forfeit" => Self::TimeForfeit,
+            b"Abandoned" => Self::Abandoned,
+            b"Rules infraction" => Self::RulesInfraction,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserTerminations {
+    pub normal: u64,
+    pub time_forfeit: u64,
+    pub abandoned: u64,
+    pub rules_infraction: u64,
+    pub lost_on_time: u64,
+    pub won_on_time: u64,
+}
+
+/// Games of each termination, whatever the side, and those lost and won on time, a timeout
+/// against insufficient material being a draw
+#[derive(Default, Debug)]
+pub struct Terminations(FxHashMap<(String, Perf), UserTerminations>);
+
+impl Terminations {
+    pub fn get(&self, username: &str, perf: Perf) -> Option<&UserTerminations> {
+        self.0.get(&(username.to_string(), perf))
+    }
+
+    /// By username then perf
+    pub fn write_csv(&self, w: &mut impl Write, delimiter: Delimiter) -> io::Result<()> {
+        let d = delimiter.as_char();
+        writeln!(
+            w,
+            "username{d}perf{d}normal{d}time_forfeit{d}abandoned{d}rules_infraction{d}\
+             lost_on_time{d}won_on_time"
+        )?;
+        let mut rows: Vec<_> = self.0.iter().collect();
+        rows.sort_unstable_by_key(|((username, perf), _)| {
+            let p = Perf::ALL.iter().position(|p| p == perf);
+            (username, p)
+        });
+        for ((username, perf), t) in rows {
+            writeln!(
+                w,
+                "{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}",
+                escape(username, d),
+                perf.name(),
+                t.normal,
+                t.time_forfeit,
+                t.abandoned,
+                t.rules_infraction,
+                t.lost_on_time,
+                t.won_on_time,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for Terminations {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        let Some(termination) = game.termination else {
+            return;
+        };
+        let scores = game
+            .scores
+            .map(|scores| scores.map(Some))
+            .unwrap_or_default();
+        for (player, score) in [game.white, game.black].into_iter().zip(scores) {
+            if player.is_bot || player.is_excluded || is_placeholder(&player.username) {
+                continue;
+            }
+            let t = self
+                .0
+                .entry((player.username.clone(), game.perf))
+                .or_default();
+            *match termination {
+                TerminationHeader::Normal => &mut t.normal,
+                TerminationHeader::TimeForfeit => &mut t.time_forfeit,
+                TerminationHeader::Abandoned => &mut t.abandoned,
+                TerminationHeader::RulesInfraction => &mut t.rules_infraction,
+            } += 1;
+            if termination == TerminationHeader::TimeForfeit {
+                match score {
+                    Some(Score::Win) => t.won_on_time += 1,
+                    Some(Score::Loss) => t.lost_on_time += 1,
+                    Some(Score::Draw) | None => (),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    const PGN: &str = r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[Termination "Time forfeit"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:00:00] } 1-0
+
+[Event "Rated Blitz game"]
+[White "bob"]
+[Black "alice"]
+[Termination "Normal"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:30] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Bullet game"]
+[White "alice"]
+[Black "carol"]
+[Termination "Time forfeit"]
+[TimeControl "60+0"]
+
+1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 { [%clk 0:00:00] } 2... Nc6 { [%clk 0:00:50] } 0-1
+
+[Event "Rated Bullet game"]
+[White "alice"]
+[Black "carol"]
+[Termination "Abandoned"]
+[TimeControl "60+0"]
+
+1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } 2. Nf3 { [%clk 0:00:55] } 2... Nc6 { [%clk 0:00:50] } 1-0
+"#;
+
+    #[test]
+    fn test_terminations() {
+        let mut terminations = Terminations::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut terminations])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        assert_eq!(
+            terminations.get("bob", Perf::Blitz),
+            Some(&UserTerminations {
+                normal: 1,
+                time_forfeit: 1,
+                lost_on_time: 1,
+                ..Default::default()
+            })
+        );
+        let mut w = Vec::new();
+        terminations.write_csv(&mut w, Delimiter::Comma).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,perf,normal,time_forfeit,abandoned,rules_infraction,lost_on_time,won_on_time\n\
+             alice,bullet,0,1,1,0,1,0\n\
+             alice,blitz,1,1,0,0,0,1\n\
+             bob,blitz,1,1,0,0,1,0\n\
+             carol,bullet,0,1,1,0,0,1\n"
+        );
+    }
+}
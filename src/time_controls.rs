@@ -0,0 +1,126 @@
+//! Time spent per exact time control, to see which ones dominate each perf
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    placeholders::is_placeholder,
+    results::CsvOptions,
+    visitor::{Perf, Tc},
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct TcTime {
+    pub games: usize,
+    /// in seconds
+    pub approximate: usize,
+    pub exact: Duration,
+}
+
+/// Games and time of each time control, in the perf it was counted in. Like `--openings`, each
+/// human player is credited the game duration
+#[derive(Default, Debug)]
+pub struct TcTimes(FxHashMap<(Perf, Tc), TcTime>);
+
+impl TcTimes {
+    pub fn get(&self, perf: Perf, tc: Tc) -> Option<&TcTime> {
+        self.0.get(&(perf, tc))
+    }
+
+    /// By perf, then from the most played time control
+    pub fn write_csv(&self, w: &mut impl Write, options: &CsvOptions) -> io::Result<()> {
+        let d = options.delimiter.as_char();
+        let unit = options.time_unit;
+        writeln!(
+            w,
+            "perf{d}time_control{d}games{d}approximate_time{d}real_time"
+        )?;
+        let mut rows: Vec<_> = self.0.iter().collect();
+        rows.sort_unstable_by_key(|((perf, tc), time)| {
+            let p = Perf::ALL.iter().position(|p| p == perf);
+            (p, usize::MAX - time.games, tc.base, tc.increment)
+        });
+        for ((perf, tc), time) in rows {
+            writeln!(
+                w,
+                "{}{d}{tc}{d}{}{d}{}{d}{}",
+                perf.name(),
+                time.games,
+                unit.display(Duration::from_secs(time.approximate as u64)),
+                unit.display(time.exact),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for TcTimes {
+    fn add_game(&mut self, game: &CountedGame<'_>) {
+        let humans = [game.white, game.black]
+            .into_iter()
+            .filter(|p| !(p.is_bot || p.is_excluded || is_placeholder(&p.username)))
+            .count();
+        if humans == 0 {
+            return;
+        }
+        let time = self.0.entry((game.perf, game.tc)).or_default();
+        time.games += 1;
+        time.approximate += game.approximate_duration * humans;
+        time.exact += game.exact_duration * humans as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::AnalysisBuilder;
+
+    fn game(tc: &str, black: &str) -> String {
+        format!(
+            r#"[Event "Rated Blitz game"]
+[White "alice"]
+[Black "{black}"]
+[TimeControl "{tc}"]
+
+1. e4 {{ [%clk 0:03:00] }} 1... e5 {{ [%clk 0:03:00] }} 2. Nf3 {{ [%clk 0:02:50] }} 2... Nc6 {{ [%clk 0:02:40] }} 1-0
+
+"#
+        )
+    }
+
+    #[test]
+    fn test_tc_times() {
+        let pgn = [
+            game("180+2", "bob"),
+            game("180+0", "bob"),
+            game("180+0", "bob"),
+            game("60+0", "BOT"),
+        ]
+        .concat()
+        .replace(r#"[Black "BOT"]"#, "[Black \"bot\"]\n[BlackTitle \"BOT\"]");
+        let mut tcs = TcTimes::default();
+        AnalysisBuilder::new()
+            .aggregators(vec![&mut tcs])
+            .build()
+            .run(pgn.as_bytes())
+            .unwrap();
+        let blitz = tcs.get(Perf::Blitz, Tc::new((180, 0))).unwrap();
+        assert_eq!(blitz.games, 2);
+        assert_eq!(blitz.exact, Duration::from_secs(120));
+        let mut w = Vec::new();
+        tcs.write_csv(&mut w, &CsvOptions::default()).unwrap();
+        // only alice is credited against the bot
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "perf,time_control,games,approximate_time,real_time\n\
+             bullet,60+0,1,60,30\n\
+             blitz,180+0,2,720,120\n\
+             blitz,180+2,1,520,76\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Parsing of the `UTCDate`/`UTCTime` headers, and conversion to the timezone
+//! used by every date-based grouping
+
+use std::{fmt, str::FromStr};
+
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// Fixed offset like `+02:00`, or IANA timezone like `Europe/Paris`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Zone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Zone {
+    /// Local date and time of an UTC one
+    pub fn local(&self, utc: NaiveDateTime) -> NaiveDateTime {
+        let utc = Utc.from_utc_datetime(&utc);
+        match self {
+            Self::Fixed(offset) => utc.with_timezone(offset).naive_local(),
+            Self::Named(tz) => utc.with_timezone(tz).naive_local(),
+        }
+    }
+}
+
+impl Default for Zone {
+    fn default() -> Self {
+        Self::Fixed(FixedOffset::east_opt(0).expect("valid offset"))
+    }
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(['+', '-']) {
+            s.parse::<FixedOffset>()
+                .map(Self::Fixed)
+                .map_err(|e| format!("invalid offset {s:?}: {e}"))
+        } else {
+            s.parse::<Tz>()
+                .map(Self::Named)
+                .map_err(|e| format!("invalid timezone {s:?}: {e}"))
+        }
+    }
+}
+
+impl TryFrom<String> for Zone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(offset) => write!(f, "{offset}"),
+            Self::Named(tz) => write!(f, "{tz}"),
+        }
+    }
+}
+
+/// `UTCDate` header, like `2023.01.31`
+pub fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y.%m.%d").ok()
+}
+
+/// `UTCTime` header, like `23:59:07`
+pub fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(date: &str, time: &str) -> NaiveDateTime {
+        parse_date(date)
+            .unwrap()
+            .and_time(parse_time(time).unwrap())
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(
+            parse_date("2023.01.31"),
+            NaiveDate::from_ymd_opt(2023, 1, 31)
+        );
+        assert_eq!(parse_date("????.??.??"), None);
+        assert_eq!(parse_time("23:59:07"), NaiveTime::from_hms_opt(23, 59, 7));
+    }
+
+    #[test]
+    fn test_zone_local() {
+        let zone: Zone = "+02:00".parse().unwrap();
+        assert_eq!(
+            zone.local(utc("2023.01.31", "23:00:00")),
+            utc("2023.02.01", "01:00:00")
+        );
+        let zone: Zone = "America/New_York".parse().unwrap();
+        assert_eq!(
+            zone.local(utc("2023.07.01", "02:00:00")),
+            utc("2023.06.30", "22:00:00")
+        );
+        assert_eq!(
+            Zone::default().local(utc("2023.07.01", "02:00:00")),
+            utc("2023.07.01", "02:00:00")
+        );
+        assert!("Mars/Olympus_Mons".parse::<Zone>().is_err());
+    }
+}
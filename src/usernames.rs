@@ -0,0 +1,39 @@
+//! Sets of usernames to restrict the analysis to
+
+use std::{fs, io};
+
+use rustc_hash::FxHashSet;
+
+pub type Usernames = FxHashSet<String>;
+
+/// Load a file of one username per line, blank lines and `#` comments being ignored
+pub fn load(path: &str) -> io::Result<Usernames> {
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+fn parse(content: &str) -> Usernames {
+    content
+        .lines()
+        .map(|line| {
+            line.split_once('#')
+                .map_or(line, |(before, _)| before)
+                .trim()
+        })
+        .filter(|username| !username.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let usernames = parse("# my team\nalice\n\n  bob  # captain\n");
+        assert_eq!(
+            usernames,
+            ["alice", "bob"].map(str::to_string).into_iter().collect()
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Time spent in the variants, kept apart from the standard perfs
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    analysis::{Aggregator, CountedGame},
+    placeholders::is_placeholder,
+    results::{escape, CsvOptions},
+};
+
+/// From the `Variant` header, standard chess when missing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// standard rules from a custom position
+    FromPosition,
+    Chess960,
+    Crazyhouse,
+    Antichess,
+    Atomic,
+    Horde,
+    KingOfTheHill,
+    RacingKings,
+    ThreeCheck,
+}
+
+impl Variant {
+    /// As in the lichess dumps, like `King of the Hill`
+    pub fn from_header(header: &str) -> Option<Self> {
+        Some(match header {
+            "Standard" => Self::Standard,
+            "From Position" => Self::FromPosition,
+            "Chess960" => Self::Chess960,
+            "Crazyhouse" => Self::Crazyhouse,
+            "Antichess" => Self::Antichess,
+            "Atomic" => Self::Atomic,
+            "Horde" => Self::Horde,
+            "King of the Hill" => Self::KingOfTheHill,
+            "Racing Kings" => Self::RacingKings,
+            "Three-check" => Self::ThreeCheck,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::FromPosition => "from_position",
+            Self::Chess960 => "chess960",
+            Self::Crazyhouse => "crazyhouse",
+            Self::Antichess => "antichess",
+            Self::Atomic => "atomic",
+            Self::Horde => "horde",
+            Self::KingOfTheHill => "king_of_the_hill",
+            Self::RacingKings => "racing_kings",
+            Self::ThreeCheck => "three_check",
+        }
+    }
+
+    /// Played with the rules of standard chess, and counted in its perfs
+    pub fn is_standard(self) -> bool {
+        matches!(self, Self::Standard | Self::FromPosition)
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct VariantTime {
+    pub games: usize,
+    /// in seconds
+    pub approximate: usize,
+    pub exact: Duration,
+}
+
+/// Games and time of each user in each variant, both players being credited the game duration
+#[derive(Default, Debug)]
+pub struct VariantTimes(FxHashMap<(String, Variant), VariantTime>);
+
+impl VariantTimes {
+    pub fn get(&self, username: &str, variant: Variant) -> Option<&VariantTime> {
+        self.0.get(&(username.to_string(), variant))
+    }
+
+    /// By username then variant
+    pub fn write_csv(&self, w: &mut impl Write, options: &CsvOptions) -> io::Result<()> {
+        let d = options.delimiter.as_char();
+        let unit = options.time_unit;
+        writeln!(
+            w,
+            "username{d}variant{d}games{d}approximate_time{d}real_time"
+        )?;
+        let mut rows: Vec<_> = self.0.iter().collect();
+        rows.sort_unstable_by_key(|(key, _)| *key);
+        for ((username, variant), time) in rows {
+            writeln!(
+                w,
+                "{}{d}{}{d}{}{d}{}{d}{}",
+                escape(username, d),
+                variant.name(),
+                time.games,
+                unit.display(Duration::from_secs(time.approximate as u64)),
+                unit.display(time.exact),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Aggregator for VariantTimes {
+    fn add_game(&mut self, _: &CountedGame<'_>) {}
+
+    fn add_variant_game(&mut self, game: &CountedGame<'_>) {
+        for player in [game.white, game.black] {
+            if player.is_bot || player.is_excluded || is_placeholder(&player.username) {
+                continue;
+            }
+            let time = self
+                .0
+                .entry((player.username.clone(), game.variant))
+                .or_default();
+            time.games += 1;
+            time.approximate += game.approximate_duration;
+            time.exact += game.exact_duration;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::AnalysisBuilder, visitor::Perf};
+
+    const PGN: &str = r#"[Event "Rated Crazyhouse game"]
+[White "alice"]
+[Black "bob"]
+[Variant "Crazyhouse"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+
+[Event "Rated Blitz game"]
+[White "alice"]
+[Black "bob"]
+[Variant "From Position"]
+[TimeControl "180+0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:03:00] } 2. Nf3 { [%clk 0:02:50] } 2... Nc6 { [%clk 0:02:40] } 1-0
+"#;
+
+    #[test]
+    fn test_from_header() {
+        assert_eq!(
+            Variant::from_header("King of the Hill"),
+            Some(Variant::KingOfTheHill)
+        );
+        assert_eq!(Variant::from_header("Shogi"), None);
+        assert!(Variant::FromPosition.is_standard());
+        assert!(!Variant::Chess960.is_standard());
+    }
+
+    #[test]
+    fn test_variant_times() {
+        let mut variants = VariantTimes::default();
+        let users = AnalysisBuilder::new()
+            .variants()
+            .aggregators(vec![&mut variants])
+            .build()
+            .run(PGN.as_bytes())
+            .unwrap();
+        // only the game from position is in the standard perfs
+        assert_eq!(users["alice"].get(Perf::Blitz).nb_games, 1);
+        let crazyhouse = variants.get("bob", Variant::Crazyhouse).unwrap();
+        assert_eq!(crazyhouse.games, 1);
+        assert_eq!(crazyhouse.exact, Duration::from_secs(30));
+        assert_eq!(variants.get("bob", Variant::FromPosition), None);
+        let mut w = Vec::new();
+        variants.write_csv(&mut w, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "username,variant,games,approximate_time,real_time\n\
+             alice,crazyhouse,1,180,30\n\
+             bob,crazyhouse,1,180,30\n"
+        );
+    }
+}
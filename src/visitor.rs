@@ -1,5 +1,6 @@
 use std::{
     borrow::Cow,
+    fmt,
     io::{self, Write},
     mem,
     ops::AddAssign,
@@ -7,12 +8,30 @@ use std::{
 };
 
 use arrayvec::ArrayVec;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use clap::ValueEnum;
 use indicatif::ProgressBar;
-use pgn_reader::{RawComment, RawHeader, SanPlus, Skip, Visitor};
+use pgn_reader::{Color, Outcome, RawComment, RawHeader, SanPlus, Skip, Visitor};
 use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use shakmaty::san::Suffix;
+use tracing::{debug, warn};
 
-#[derive(Default, Debug, Clone)]
-pub struct Rating(usize);
+use crate::{
+    analysis::{Aggregator, Attribution, Buckets, Config, CountedGame},
+    columns::{Column, Ranks},
+    endings::{Ending, Termination},
+    openings,
+    placeholders::is_placeholder,
+    results::CsvOptions,
+    skips::{Filter, ParseErrorKind, SkipReason},
+    terminations::TerminationHeader,
+    timestamp::{self, Zone},
+    variants::Variant,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Rating(pub usize);
 
 impl AddAssign for Rating {
     fn add_assign(&mut self, rhs: Self) {
@@ -21,42 +40,56 @@ impl AddAssign for Rating {
 }
 
 #[derive(Default, Debug, Clone)]
-struct Player {
-    username: String,
-    rating: Rating,
-    is_bot: bool,
+pub struct Player {
+    pub username: String,
+    pub rating: Rating,
+    pub is_bot: bool,
+    /// listed in the excluded usernames
+    pub is_excluded: bool,
 }
 
 impl Player {
-    fn to_tuple(self) -> (String, Rating, bool) {
-        (self.username, self.rating, self.is_bot)
+    // whether the player is credited the game
+    fn into_tuple(self) -> (String, Rating, bool) {
+        let is_credited = !self.is_bot && !self.is_excluded && !is_placeholder(&self.username);
+        (self.username, self.rating, is_credited)
     }
 }
 
 #[derive(Default, Debug, Clone)]
-struct Players {
-    white: Player,
-    black: Player,
+pub struct Players {
+    pub white: Player,
+    pub black: Player,
 }
 
 impl Players {
-    fn add_name(&mut self, key: &[u8], value: String) {
-        if key == b"White" {
-            self.white.username = value
+    fn add_name(&mut self, key: &[u8], value: String, is_excluded: bool) {
+        let player = if key == b"White" {
+            &mut self.white
         } else {
-            self.black.username = value
-        }
+            &mut self.black
+        };
+        player.username = value;
+        player.is_excluded = is_excluded;
+    }
+
+    fn any_bot(&self) -> bool {
+        self.white.is_bot || self.black.is_bot
+    }
+
+    fn all_excluded(&self) -> bool {
+        self.white.is_excluded && self.black.is_excluded
     }
 
     fn into_iter(self) -> [(String, Rating, bool); 2] {
-        [self.white.to_tuple(), self.black.to_tuple()]
+        [self.white.into_tuple(), self.black.into_tuple()]
     }
 
-    fn add_rating(&mut self, key: &[u8], value: String) {
+    fn add_rating(&mut self, key: &[u8], rating: Rating) {
         if key == b"WhiteElo" {
-            self.white.rating = Rating(value.parse().unwrap())
+            self.white.rating = rating
         } else {
-            self.black.rating = Rating(value.parse().unwrap())
+            self.black.rating = rating
         }
     }
 
@@ -69,7 +102,7 @@ impl Players {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct TimeSpent {
     pub nb_games: usize,
     pub total_rating: Rating,
@@ -77,88 +110,308 @@ pub struct TimeSpent {
     ///  in seconds
     /// computed with formula  (clock initial time in seconds) + 40 × (clock increment)
     pub time_spent_approximate: usize,
+    /// estimated time before the first moves, not included in `time_spent_exact`
+    pub setup_time: Duration,
+    /// unfinished games count in none of the results
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    /// of all the games, written as their average
+    pub total_plies: usize,
+    pub as_white: ColorShare,
+    pub as_black: ColorShare,
 }
 
 impl TimeSpent {
     fn add_game(
         &mut self,
         game_exact_duration: Duration,
+        game_setup_duration: Duration,
         game_approximate_duration: usize,
         rating: Rating,
+        is_white: bool,
     ) {
         self.nb_games += 1;
         self.total_rating += rating;
         self.time_spent_exact += game_exact_duration;
         self.time_spent_approximate += game_approximate_duration;
+        self.setup_time += game_setup_duration;
+        let color = if is_white {
+            &mut self.as_white
+        } else {
+            &mut self.as_black
+        };
+        color.nb_games += 1;
+        color.time_spent_exact += game_exact_duration;
     }
 
-    fn to_csv(&self, w: &mut impl Write) -> io::Result<()> {
-        // nb_game, average, accurate
-        if self.nb_games > 0 && !self.time_spent_exact.is_zero() && self.time_spent_approximate > 0
-        {
-            write!(
-                w,
-                ",{},{},{},{}",
-                self.nb_games,
-                self.total_rating.0 / self.nb_games,
-                self.time_spent_approximate,
-                self.time_spent_exact.as_secs()
-            )
-        } else {
-            write!(w, ",,,,")
+    fn add_score(&mut self, score: Score) {
+        match score {
+            Score::Win => self.wins += 1,
+            Score::Draw => self.draws += 1,
+            Score::Loss => self.losses += 1,
+        }
+    }
+}
+
+impl AddAssign for TimeSpent {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nb_games += rhs.nb_games;
+        self.total_rating += rhs.total_rating;
+        self.time_spent_exact += rhs.time_spent_exact;
+        self.time_spent_approximate += rhs.time_spent_approximate;
+        self.setup_time += rhs.setup_time;
+        self.wins += rhs.wins;
+        self.draws += rhs.draws;
+        self.losses += rhs.losses;
+        self.total_plies += rhs.total_plies;
+        self.as_white += rhs.as_white;
+        self.as_black += rhs.as_black;
+    }
+}
+
+/// Result of a game for one of its players
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// https://lichess.org/faq#time-controls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Perf {
+    #[value(name = "ultrabullet")]
+    UltraBullet,
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+    /// `TimeControl` of `-`, in days per move
+    Correspondence,
+}
+
+impl Perf {
+    pub const ALL: [Self; 6] = [
+        Self::UltraBullet,
+        Self::Bullet,
+        Self::Blitz,
+        Self::Rapid,
+        Self::Classical,
+        Self::Correspondence,
+    ];
+
+    /// Told apart by the `Buckets` of their time control
+    pub const REAL_TIME: [Self; 5] = [
+        Self::UltraBullet,
+        Self::Bullet,
+        Self::Blitz,
+        Self::Rapid,
+        Self::Classical,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UltraBullet => "ultrabullet",
+            Self::Bullet => "bullet",
+            Self::Blitz => "blitz",
+            Self::Rapid => "rapid",
+            Self::Classical => "classical",
+            Self::Correspondence => "correspondence",
         }
     }
 }
 
-#[derive(Default, Debug)]
+/// Games played with one of the colors, and their real time
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorShare {
+    pub nb_games: usize,
+    pub time_spent_exact: Duration,
+}
+
+impl AddAssign for ColorShare {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nb_games += rhs.nb_games;
+        self.time_spent_exact += rhs.time_spent_exact;
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct TimeSpents {
     ultrabullet: TimeSpent,
     bullet: TimeSpent,
     blitz: TimeSpent,
     rapid: TimeSpent,
     classical: TimeSpent,
+    correspondence: TimeSpent,
 }
 
 impl TimeSpents {
-    fn add_game(&mut self, game_exact_duration: Duration, avg_time: usize, rating: Rating) {
-        // https://lichess.org/faq#time-controls
-        if avg_time <= 29 {
-            self.ultrabullet
-                .add_game(game_exact_duration, avg_time, rating)
-        } else if avg_time <= 179 {
-            self.bullet.add_game(game_exact_duration, avg_time, rating)
-        } else if avg_time <= 479 {
-            self.blitz.add_game(game_exact_duration, avg_time, rating)
-        } else if avg_time <= 1499 {
-            self.rapid.add_game(game_exact_duration, avg_time, rating)
-        } else {
-            self.classical
-                .add_game(game_exact_duration, avg_time, rating)
+    pub(crate) fn add_game(
+        &mut self,
+        perf: Perf,
+        game_exact_duration: Duration,
+        game_setup_duration: Duration,
+        avg_time: usize,
+        rating: Rating,
+        is_white: bool,
+    ) {
+        self.get_mut(perf).add_game(
+            game_exact_duration,
+            game_setup_duration,
+            avg_time,
+            rating,
+            is_white,
+        );
+    }
+
+    pub(crate) fn add_score(&mut self, perf: Perf, score: Score) {
+        self.get_mut(perf).add_score(score);
+    }
+
+    /// games and real time as white over all perfs
+    pub fn as_white(&self) -> ColorShare {
+        let mut share = ColorShare::default();
+        for perf in Perf::ALL {
+            share += self.get(perf).as_white;
         }
+        share
     }
 
-    // start with a leadinb colon, so need to be predecessed by `username`
-    pub fn to_csv(&self, w: &mut impl Write) -> io::Result<()> {
-        self.ultrabullet.to_csv(w)?;
-        self.bullet.to_csv(w)?;
-        self.blitz.to_csv(w)?;
-        self.rapid.to_csv(w)?;
-        self.classical.to_csv(w)
+    /// games and real time as black over all perfs
+    pub fn as_black(&self) -> ColorShare {
+        let mut share = ColorShare::default();
+        for perf in Perf::ALL {
+            share += self.get(perf).as_black;
+        }
+        share
+    }
+
+    pub fn get(&self, perf: Perf) -> &TimeSpent {
+        match perf {
+            Perf::UltraBullet => &self.ultrabullet,
+            Perf::Bullet => &self.bullet,
+            Perf::Blitz => &self.blitz,
+            Perf::Rapid => &self.rapid,
+            Perf::Classical => &self.classical,
+            Perf::Correspondence => &self.correspondence,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, perf: Perf) -> &mut TimeSpent {
+        match perf {
+            Perf::UltraBullet => &mut self.ultrabullet,
+            Perf::Bullet => &mut self.bullet,
+            Perf::Blitz => &mut self.blitz,
+            Perf::Rapid => &mut self.rapid,
+            Perf::Classical => &mut self.classical,
+            Perf::Correspondence => &mut self.correspondence,
+        }
+    }
+
+    /// exact time spent over all perfs
+    pub fn total_exact(&self) -> Duration {
+        Perf::ALL
+            .map(|perf| self.get(perf).time_spent_exact)
+            .iter()
+            .sum()
+    }
+
+    /// approximate time spent over all perfs, in seconds
+    pub fn total_approximate(&self) -> usize {
+        Perf::ALL
+            .map(|perf| self.get(perf).time_spent_approximate)
+            .iter()
+            .sum()
+    }
+
+    pub fn total_games(&self) -> usize {
+        Perf::ALL.map(|perf| self.get(perf).nb_games).iter().sum()
+    }
+
+    // start with a leading delimiter, so need to be predecessed by `username`
+    pub fn write_csv_header(w: &mut impl Write, options: &CsvOptions) -> io::Result<()> {
+        let delimiter = options.delimiter.as_char();
+        for perf in Perf::ALL.map(Perf::name) {
+            for column in &options.columns {
+                write!(w, "{delimiter}{perf}_{}", column.header())?;
+            }
+        }
+        Ok(())
+    }
+
+    // start with a leading delimiter, so need to be predecessed by `username`.
+    // `ranks` are ordered like `Perf::ALL`
+    pub fn to_csv(
+        &self,
+        w: &mut impl Write,
+        options: &CsvOptions,
+        ranks: Option<&[Ranks; 6]>,
+    ) -> io::Result<()> {
+        for (i, perf) in Perf::ALL.into_iter().enumerate() {
+            self.get(perf)
+                .to_csv(w, options, ranks.map(|ranks| ranks[i]))?;
+        }
+        Ok(())
+    }
+
+    /// `fields` are the csv fields following `username`, and `header` their parsed names
+    pub fn from_csv(header: &[(Perf, Column)], fields: &[&str]) -> Option<Self> {
+        let mut time_spents = Self::default();
+        if fields.len() != header.len() {
+            return None;
+        }
+        for perf in Perf::ALL {
+            let perf_fields: Vec<(Column, &str)> = header
+                .iter()
+                .zip(fields)
+                .filter(|((p, _), _)| *p == perf)
+                .map(|((_, column), field)| (*column, *field))
+                .collect();
+            *time_spents.get_mut(perf) = TimeSpent::from_csv(&perf_fields)?;
+        }
+        Some(time_spents)
+    }
+}
+
+impl AddAssign for TimeSpents {
+    fn add_assign(&mut self, rhs: Self) {
+        self.ultrabullet += rhs.ultrabullet;
+        self.bullet += rhs.bullet;
+        self.blitz += rhs.blitz;
+        self.rapid += rhs.rapid;
+        self.classical += rhs.classical;
+        self.correspondence += rhs.correspondence;
     }
 }
 
-pub struct PgnVisitor {
+pub type Users = FxHashMap<String, TimeSpents>;
+
+pub struct PgnVisitor<'a> {
     pub games: usize,
-    pub users: FxHashMap<String, TimeSpents>,
+    pub users: Users,
     pub pb: ProgressBar,
+    // otherwise the progress bar is driven by the bytes read, and only display the number of games
+    progress_by_games: bool,
+    config: Config,
+    aggregators: Vec<&'a mut dyn Aggregator>,
     game: Game, // storing temporary variable
 }
 
-impl PgnVisitor {
-    pub fn new(pb: ProgressBar) -> Self {
+impl<'a> PgnVisitor<'a> {
+    pub fn new(
+        pb: ProgressBar,
+        progress_by_games: bool,
+        config: Config,
+        aggregators: Vec<&'a mut dyn Aggregator>,
+    ) -> Self {
         Self {
             games: 0,
             pb,
+            progress_by_games,
+            config,
+            aggregators,
             users: FxHashMap::default(),
             game: Game::default(),
         }
@@ -166,25 +419,36 @@ impl PgnVisitor {
 }
 
 #[derive(Default, Debug, PartialEq, Eq, Hash, Clone, Copy)]
-struct Tc {
+pub struct Tc {
     // in seconds
-    base: u64,
+    pub base: u64,
     // in seconds
-    increment: u64,
+    pub increment: u64,
 }
 
 impl Tc {
-    fn new(tc: (u64, u64)) -> Self {
+    pub fn new(tc: (u64, u64)) -> Self {
         Self {
             base: tc.0,
             increment: tc.1,
         }
     }
-    fn average_time(&self) -> usize {
+    pub fn average_time(&self) -> usize {
         (self.base + 40 * self.increment) as usize
     }
 }
 
+/// As in the `TimeControl` header, like `180+2`, and `-` for correspondence games
+impl fmt::Display for Tc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Self::default() {
+            f.write_str("-")
+        } else {
+            write!(f, "{}+{}", self.base, self.increment)
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 struct Game {
     players: Players,
@@ -194,24 +458,70 @@ struct Game {
     first_two_clocks: ArrayVec<Duration, 2>,
     // sliding of the last two clock
     last_two_comments: ArrayVec<String, 2>,
+    // number of clocks read, which should match the plies
+    nb_clocks: u64,
+    // every clock, only with `keep_clocks`
+    clocks: Option<Vec<Duration>>,
+    // previous clock of each side, indexed by the parity of the plies
+    previous_clocks: [Option<Duration>; 2],
+    // whether the clock of each side rose by more than what increment and +15s explain
+    clock_jumps: [bool; 2],
+    // time given to each side beyond its increment, like +15s presses, indexed like the clocks
+    added_time: [Duration; 2],
     // the initial time, in seconds, with the increment, in seconds
     tc: Tc,
+    utc_date: Option<NaiveDate>,
+    utc_time: Option<NaiveTime>,
+    termination: Option<TerminationHeader>,
+    // family of the `Opening` header, else of the `ECO` code
+    opening: String,
+    // whether the last move mated
+    checkmate: bool,
+    outcome: Option<Outcome>,
+    // `TimeControl` of `-`
+    correspondence: bool,
+    variant: Variant,
+    // from the `Event` header, like `Rated Blitz game` or `Casual Blitz game`
+    rated: Option<bool>,
+    // first reason found not to count the game
+    skip: Option<SkipReason>,
 }
 
 impl Game {
-    fn should_skip(&self) -> bool {
-        // avoiding games without clocks
-        self.tc == Tc::default()
+    fn start(&self, zone: &Zone) -> Option<NaiveDateTime> {
+        Some(zone.local(self.utc_date?.and_time(self.utc_time?)))
+    }
+
+    // falling back to the UTC date if the time is missing
+    fn start_date(&self, zone: &Zone) -> Option<NaiveDate> {
+        self.start(zone).map(|start| start.date()).or(self.utc_date)
+    }
+
+    // keeping the first reason
+    fn skip(&mut self, reason: SkipReason) {
+        self.skip.get_or_insert(reason);
+    }
+
+    // avoiding games without clocks, but correspondence ones
+    fn headers_skip_reason(&self) -> Option<SkipReason> {
+        self.skip
+            .or(if self.tc == Tc::default() && !self.correspondence {
+                Some(SkipReason::NoTimeControl)
+            } else {
+                None
+            })
     }
 
     fn acc_comment(&mut self, comment: String) {
+        self.check_clock_jump(&comment);
         // first if there's still room we add to the first two clocks
         if !self.first_two_clocks.is_full() {
-            self.first_two_clocks.push(
-                comment_to_duration(&comment).unwrap_or_else(|| {
-                    panic!("could not read comment {comment:?} for game {self:?}")
-                }),
-            );
+            let Some(clock) = comment_to_duration(&comment) else {
+                return self.skip(SkipReason::ParseError {
+                    kind: ParseErrorKind::Clock,
+                });
+            };
+            self.first_two_clocks.push(clock);
         }
         // if the last two_clock is full, we need to displace the sliding-window
         if let Err(e) = self.last_two_comments.try_push(comment) {
@@ -223,27 +533,182 @@ impl Game {
         }
     }
 
+    fn check_clock_jump(&mut self, comment: &str) {
+        let side = (self.plies % 2) as usize;
+        let Some(clock) = comment_to_duration(comment) else {
+            return;
+        };
+        self.nb_clocks += 1;
+        if let Some(clocks) = &mut self.clocks {
+            clocks.push(clock);
+        }
+        if let Some(previous) = self.previous_clocks[side].replace(clock) {
+            let explained = Duration::from_secs(self.tc.increment + 15);
+            self.clock_jumps[side] |= clock > previous + explained;
+            // white moves on odd plies
+            self.added_time[side] += clock.saturating_sub(previous + self.increment(1 - side));
+        }
+    }
+
+    // a normal ending being a resignation unless the last move mated
+    fn ended_by(&self) -> Option<Termination> {
+        match self.termination? {
+            TerminationHeader::Normal if self.checkmate => Some(Termination::Checkmate),
+            TerminationHeader::Normal => Some(Termination::Resignation),
+            TerminationHeader::TimeForfeit => Some(Termination::Flag),
+            TerminationHeader::Abandoned | TerminationHeader::RulesInfraction => None,
+        }
+    }
+
+    fn ending(&self) -> Option<Ending> {
+        let Some(Outcome::Decisive { winner }) = self.outcome else {
+            return None;
+        };
+        let termination = self.ended_by()?;
+        // white moves on odd plies
+        let loser = match winner {
+            Color::White => 0,
+            Color::Black => 1,
+        };
+        Some(Ending {
+            termination,
+            loser_clock: self.previous_clocks[loser]?,
+        })
+    }
+
+    // from the result of the game, white first. None for unfinished games
+    fn scores(&self) -> Option<[Score; 2]> {
+        Some(match self.outcome? {
+            Outcome::Decisive {
+                winner: Color::White,
+            } => [Score::Win, Score::Loss],
+            Outcome::Decisive {
+                winner: Color::Black,
+            } => [Score::Loss, Score::Win],
+            Outcome::Draw => [Score::Draw; 2],
+        })
+    }
+
+    /// Both clocks jumped, which a single player giving time cannot explain
+    fn clock_anomaly(&self) -> bool {
+        self.clock_jumps == [true, true]
+    }
+
+    // plies of each side, white first
+    fn side_plies(&self) -> [u64; 2] {
+        let white = self.plies.div_ceil(2);
+        [white, self.plies - white]
+    }
+
+    // Berserked players of arenas start with half of the base time, and get no increment.
+    // Told by their first clock, white first
+    fn berserks(&self) -> [bool; 2] {
+        let base = Duration::from_secs(self.tc.base);
+        [0, 1].map(|side| {
+            self.first_two_clocks.get(side).is_some_and(|clock| {
+                let gap = base.saturating_sub(*clock);
+                !base.is_zero() && gap.abs_diff(base / 2) <= Duration::from_secs(1)
+            })
+        })
+    }
+
+    // increment received by a side after each move, white being 0
+    fn increment(&self, side: usize) -> Duration {
+        if self.berserks()[side] {
+            Duration::ZERO
+        } else {
+            Duration::from_secs(self.tc.increment)
+        }
+    }
+
+    // increments received by a side over the game, white being 0
+    fn increments(&self, side: usize) -> Duration {
+        self.increment(side) * self.side_plies()[side] as u32
+    }
+
+    // Lichess clocks only start after the first move of each player, the time
+    // taken by those moves is estimated from the gap between the base time and the first clocks.
+    // The half of the base time given up by berserking is not counted as setup
+    fn setup_duration(&self) -> Duration {
+        let base = Duration::from_secs(self.tc.base);
+        self.first_two_clocks
+            .iter()
+            .zip(self.berserks())
+            .filter(|(_, berserk)| !berserk)
+            .map(|(clock, _)| base.saturating_sub(*clock))
+            .sum()
+    }
+
+    // Like the game duration, but for each side from its own clocks, white first. A side given
+    // more time by its opponent than it spent is credited nothing
+    fn thinking_times(&self, move_time: Duration) -> [Duration; 2] {
+        if self.correspondence {
+            return self.side_plies().map(|plies| move_time * plies as u32);
+        }
+        // white moves on odd plies
+        [(0, 1), (1, 0)].map(|(side, last)| {
+            let first = self.first_two_clocks.get(side).copied().unwrap_or_default();
+            let last_clock = self.previous_clocks[last].unwrap_or_default();
+            (first + self.increments(side) + self.added_time[last]).saturating_sub(last_clock)
+        })
+    }
+
+    fn perf(&self, buckets: &Buckets) -> Perf {
+        if self.correspondence {
+            Perf::Correspondence
+        } else {
+            buckets.perf(self.tc.average_time())
+        }
+    }
+
+    // in seconds, correspondence games being approximated to 40 moves of `move_time` each.
+    // Each side counts for half of `base + 40 * increment`, berserked ones for half the base
+    fn approximate_time(&self, move_time: Duration) -> usize {
+        if self.correspondence {
+            return 80 * move_time.as_secs() as usize;
+        }
+        let sides: u64 = self
+            .berserks()
+            .map(|berserk| {
+                if berserk {
+                    self.tc.base / 2
+                } else {
+                    self.tc.base + 40 * self.tc.increment
+                }
+            })
+            .iter()
+            .sum();
+        (sides / 2) as usize
+    }
+
+    // every lichess move has a clock, a mismatch means the plies are miscounted, or a clock
+    // comment is missing. The move numbers of the movetext are not reported by the parser, so
+    // the games without clocks, like older and correspondence ones, are not checked
+    fn clock_mismatch(&self) -> bool {
+        self.nb_clocks > 0 && self.nb_clocks != self.plies
+    }
+
     // The use of the +15s button can break the game duration calculation
     // then the game is skipped
-    fn game_duration(self) -> (Players, Option<Duration>) {
-        // base time - finish time + increment * nb_plies
-        // in the implementation `+ increment * nb_plies` is done first to avoid
-        // negative time (and overflow) in 0+X type of games
-        (
-            self.players,
-            (self.first_two_clocks.into_iter().sum::<Duration>()
-                + Duration::from_secs(self.plies * self.tc.increment))
-            .checked_sub(
-                self.last_two_comments
-                    .into_iter()
-                    .map(|x| {
-                        comment_to_duration(&x).unwrap_or_else(|| {
-                            panic!("could not read comment {x:?}, game: {:?}", self.link)
-                        })
-                    })
-                    .sum(),
-            ),
-        )
+    fn game_duration(&self) -> Result<Duration, SkipReason> {
+        let last_clocks: Duration = self
+            .last_two_comments
+            .iter()
+            .map(|x| comment_to_duration(x))
+            .sum::<Option<Duration>>()
+            .ok_or(SkipReason::ParseError {
+                kind: ParseErrorKind::Clock,
+            })?;
+        // base time - finish time + increment * nb_plies + added time
+        // in the implementation the additions are done first to avoid
+        // negative time (and overflow) in 0+X type of games. Counting the time added by
+        // +15s presses amounts to summing the time spent on each move
+        (self.first_two_clocks.iter().sum::<Duration>()
+            + self.increments(0)
+            + self.increments(1)
+            + self.added_time.iter().sum::<Duration>())
+        .checked_sub(last_clocks)
+        .ok_or(SkipReason::ClockUnderflow)
     }
 }
 
@@ -267,88 +732,332 @@ fn comment_to_duration(comment: &str) -> Option<Duration> {
     Some(Duration::from_secs(h * 3600 + m * 60 + s))
 }
 
+/// Resident memory of the process, only known on linux
+fn resident_memory_mb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb / 1024)
+}
+
 fn decode<'a>(value: RawHeader<'a>, field: &str, g: &Game) -> Cow<'a, str> {
-    value
-        .decode_utf8()
-        .unwrap_or_else(|e| panic!("Error {e} decoding {field} at game: {g:?}"))
+    value.decode_utf8().unwrap_or_else(|e| {
+        warn!(field, link = %g.link, "invalid utf-8 header: {e}");
+        value.decode_utf8_lossy()
+    })
 }
 
-impl PgnVisitor {
-    fn record_game(
-        &mut self,
-        username: String,
-        rating: Rating,
-        exact_duration: Duration,
-        avg_time: usize,
-    ) {
-        let mut time_spents = self
-            .users
-            .remove(&username)
-            .unwrap_or_else(TimeSpents::default);
-        time_spents.add_game(exact_duration, avg_time, rating);
-        self.users.insert(username, time_spents);
+// Each game is kept with a probability of `1 / n`, independently of its neighbours, from a
+// seeded hash of its link, so that the same games are sampled on every run. The games without
+// a `Site` header fall back to their position in the pgn.
+fn is_sampled(link: &str, games: usize, n: usize) -> bool {
+    if link.is_empty() {
+        return games.is_multiple_of(n);
+    }
+    // FNV-1a, then the splitmix64 finalizer to spread the close links over the low bits
+    let mut hash = link.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    hash.is_multiple_of(n as u64)
+}
+
+impl PgnVisitor<'_> {
+    fn filtered(&self) -> Option<Filter> {
+        let players = &self.game.players;
+        if self
+            .config
+            .sample
+            .is_some_and(|n| !is_sampled(&self.game.link, self.games, n))
+        {
+            Some(Filter::Sample)
+        } else if !(self.config.is_tracked(&players.white.username)
+            || self.config.is_tracked(&players.black.username))
+        {
+            Some(Filter::Users)
+        } else if players.all_excluded() {
+            Some(Filter::Excluded)
+        } else if self.config.no_bots && players.any_bot() {
+            Some(Filter::Bots)
+        } else if !self.config.rating_in_range(players) {
+            Some(Filter::Rating)
+        } else if !self
+            .config
+            .date_in_range(self.game.start_date(&self.config.zone))
+        {
+            Some(Filter::Date)
+        } else if self
+            .config
+            .rated
+            .is_some_and(|rated| self.game.rated != Some(rated))
+        {
+            Some(Filter::Rated)
+        } else if !self
+            .config
+            .perf_selected(self.game.perf(&self.config.buckets))
+        {
+            Some(Filter::Perf)
+        } else if !(self.game.variant.is_standard() || self.config.variants) {
+            Some(Filter::Variant)
+        } else {
+            None
+        }
+    }
+
+    fn exact_duration(&self, game: &Game) -> Result<Duration, SkipReason> {
+        if let Some(reason) = game.skip {
+            return Err(reason);
+        }
+        if game.plies < 4 {
+            return Err(SkipReason::TooFewPlies);
+        }
+        if self.config.max_plies.is_some_and(|max| game.plies > max) {
+            return Err(SkipReason::Filtered {
+                which: Filter::MaxPlies,
+            });
+        }
+        if self.config.approx_only {
+            return Ok(Duration::ZERO);
+        }
+        // without clocks, only the number of moves tells how long they were thought about
+        if game.correspondence {
+            return Ok(self.config.correspondence_move() * game.plies as u32);
+        }
+        if self.config.skip_clock_mismatches && game.clock_mismatch() {
+            return Err(SkipReason::ParseError {
+                kind: ParseErrorKind::PlyCount,
+            });
+        }
+        game.game_duration()
+    }
+
+    // the games per second are in the template when the progress is counted in games
+    fn progress_message(&self) -> String {
+        let mut message = String::new();
+        if !self.progress_by_games {
+            let games = self.games + 1;
+            let per_sec = games as f64 / self.pb.elapsed().as_secs_f64().max(1.);
+            message = format!("{games} games, {per_sec:.0}/s, ");
+        }
+        message += &format!("{} users", self.users.len());
+        if let Some(mb) = resident_memory_mb() {
+            message += &format!(", {mb} MB");
+        }
+        message
+    }
+
+    // past the limit, comments are no longer accumulated
+    fn is_pathological(&self) -> bool {
+        self.config
+            .max_plies
+            .is_some_and(|max_plies| self.game.plies > max_plies)
     }
 }
 
-impl Visitor for PgnVisitor {
+impl Visitor for PgnVisitor<'_> {
     type Result = ();
 
     fn begin_game(&mut self) {
         self.games += 1;
+        if self.config.keep_clocks {
+            self.game.clocks = Some(Vec::new());
+        }
         if self.games % 10_000 == 9999 {
-            self.pb.inc(10_000)
+            if self.progress_by_games {
+                self.pb.inc(10_000)
+            }
+            self.pb.set_message(self.progress_message())
         }
     }
 
     fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
+        if self.config.fast && self.game.skip.is_some() {
+            return;
+        }
         if key == b"White" || key == b"Black" {
             let username = decode(value, "username", &self.game).to_string();
-            self.game.players.add_name(key, username);
+            let is_excluded = self.config.excluded.contains(&username);
+            self.game.players.add_name(key, username, is_excluded);
+            let players = &self.game.players;
+            if self.config.fast
+                && [&players.white, &players.black]
+                    .iter()
+                    .all(|p| !p.username.is_empty() && !self.config.is_tracked(&p.username))
+            {
+                self.game.skip(SkipReason::Filtered {
+                    which: Filter::Users,
+                });
+            }
         } else if key == b"WhiteElo" || key == b"BlackElo" {
-            let rating = decode(value, "rating", &self.game).to_string();
-            self.game.players.add_rating(key, rating);
+            match decode(value, "rating", &self.game).parse() {
+                Ok(rating) => self.game.players.add_rating(key, Rating(rating)),
+                Err(_) => self.game.skip(SkipReason::ParseError {
+                    kind: ParseErrorKind::Rating,
+                }),
+            }
         } else if key == b"TimeControl" {
             let tc = decode(value, "tc", &self.game);
-            if tc != "-" {
-                self.game.tc = tc_to_tuple(&tc).unwrap_or_else(|| {
-                    panic!("could not convert tc {tc:?} at game {:?}", self.game)
-                })
+            if tc == "-" {
+                self.game.correspondence = true
+            } else {
+                match tc_to_tuple(&tc) {
+                    Some(tc) => self.game.tc = tc,
+                    None => self.game.skip(SkipReason::ParseError {
+                        kind: ParseErrorKind::TimeControl,
+                    }),
+                }
             }
         } else if key == b"Site" {
             self.game.link = decode(value, "link", &self.game).to_string();
         } else if key == b"WhiteTitle" || key == b"BlackTitle" {
             let bot = decode(value, "bot", &self.game);
             self.game.players.add_bot(key, &bot);
+        } else if key == b"UTCDate" {
+            self.game.utc_date = timestamp::parse_date(&decode(value, "date", &self.game));
+        } else if key == b"Event" {
+            let event = value.as_bytes();
+            self.game.rated = if event.starts_with(b"Rated ") {
+                Some(true)
+            } else if event.starts_with(b"Casual ") {
+                Some(false)
+            } else {
+                None
+            };
+        } else if key == b"Termination" {
+            self.game.termination = TerminationHeader::from_header(value.as_bytes());
+        } else if key == b"Variant" {
+            match Variant::from_header(&decode(value, "variant", &self.game)) {
+                Some(variant) => self.game.variant = variant,
+                None => self.game.skip(SkipReason::ParseError {
+                    kind: ParseErrorKind::Variant,
+                }),
+            }
+        } else if key == b"Opening" {
+            if let Some(family) = openings::family(&decode(value, "opening", &self.game)) {
+                self.game.opening = family.to_string();
+            }
+        } else if key == b"ECO" && self.game.opening.is_empty() {
+            let eco = decode(value, "eco", &self.game);
+            if let Some(family) = self.config.eco_names.family(&eco) {
+                self.game.opening = family.to_string();
+            }
+        } else if key == b"UTCTime" {
+            self.game.utc_time = timestamp::parse_time(&decode(value, "time", &self.game));
         }
     }
-    fn san(&mut self, _: SanPlus) {
+    fn san(&mut self, san_plus: SanPlus) {
         self.game.plies += 1;
+        self.game.checkmate = san_plus.suffix == Some(Suffix::Checkmate);
+    }
+
+    fn outcome(&mut self, outcome: Option<Outcome>) {
+        self.game.outcome = outcome;
     }
 
     fn comment(&mut self, c: RawComment<'_>) {
-        self.game
-            .acc_comment(String::from_utf8_lossy(c.as_bytes()).to_string())
+        if !self.config.approx_only && !self.is_pathological() {
+            self.game
+                .acc_comment(String::from_utf8_lossy(c.as_bytes()).to_string())
+        }
     }
     fn begin_variation(&mut self) -> Skip {
         Skip(true)
     }
     fn end_headers(&mut self) -> Skip {
-        // avoiding games without clocks
-        Skip(self.game.should_skip())
+        self.game.skip = self
+            .game
+            .headers_skip_reason()
+            .or_else(|| self.filtered().map(|which| SkipReason::Filtered { which }));
+        Skip(self.game.skip.is_some())
     }
 
     fn end_game(&mut self) -> Self::Result {
         let finished_game = mem::take(&mut self.game);
-        let plies = finished_game.plies;
-        let avg_time = finished_game.tc.average_time();
-        let (players, exact_duration_opt) = finished_game.game_duration();
-        if plies >= 4 {
-            if let Some(exact_duration) = exact_duration_opt {
-                for (username, rating, is_bot) in players.into_iter() {
-                    if !is_bot {
-                        self.record_game(username, rating, exact_duration, avg_time)
-                    }
+        let exact_duration = match self.exact_duration(&finished_game) {
+            Ok(exact_duration) => exact_duration,
+            Err(reason) => {
+                if let SkipReason::Filtered {
+                    which: Filter::MaxPlies,
+                } = reason
+                {
+                    warn!(
+                        link = %finished_game.link,
+                        plies = finished_game.plies,
+                        max_plies = self.config.max_plies,
+                        "skipping pathological game"
+                    );
+                } else {
+                    debug!(link = %finished_game.link, %reason, "skipping game");
                 }
+                for aggregator in self.aggregators.iter_mut() {
+                    aggregator.skip_game(&finished_game.link, reason);
+                }
+                return;
+            }
+        };
+        let avg_time = finished_game.approximate_time(self.config.correspondence_move());
+        let perf = finished_game.perf(&self.config.buckets);
+        let counted = CountedGame {
+            white: &finished_game.players.white,
+            black: &finished_game.players.black,
+            link: &finished_game.link,
+            tc: finished_game.tc,
+            perf,
+            variant: finished_game.variant,
+            plies: finished_game.plies,
+            exact_duration,
+            approximate_duration: avg_time,
+            berserks: finished_game.berserks(),
+            start: finished_game.start(&self.config.zone),
+            setup_duration: finished_game.setup_duration(),
+            clock_anomaly: finished_game.clock_anomaly(),
+            has_clocks: finished_game.nb_clocks > 0,
+            clock_mismatch: finished_game.clock_mismatch(),
+            ending: finished_game.ending(),
+            scores: finished_game.scores(),
+            termination: finished_game.termination,
+            opening: &finished_game.opening,
+            clocks: finished_game.clocks.as_deref().unwrap_or_default(),
+        };
+        // kept out of the standard perfs
+        if !counted.variant.is_standard() {
+            for aggregator in self.aggregators.iter_mut() {
+                aggregator.add_variant_game(&counted);
+            }
+            return;
+        }
+        for aggregator in self.aggregators.iter_mut() {
+            aggregator.add_game(&counted);
+        }
+        let credited = match self.config.attribution {
+            // the clocks are not read with `approx_only`
+            Attribution::ThinkingTime if !self.config.approx_only => {
+                finished_game.thinking_times(self.config.correspondence_move())
+            }
+            _ => [exact_duration; 2],
+        };
+        let setup_duration = counted.setup_duration;
+        let scores = counted.scores;
+        let plies = counted.plies as usize;
+        let players = finished_game.players.into_iter();
+        for (i, ((username, rating, is_credited), credited)) in
+            players.into_iter().zip(credited).enumerate()
+        {
+            if is_credited && self.config.is_tracked(&username) {
+                let time_spents = self.users.entry(username).or_default();
+                time_spents.add_game(perf, credited, setup_duration, avg_time, rating, i == 0);
+                if let Some(scores) = scores {
+                    time_spents.add_score(perf, scores[i]);
+                }
+                time_spents.get_mut(perf).total_plies += plies;
             }
         }
     }
@@ -360,6 +1069,17 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_is_sampled() {
+        let sampled = (0..10_000)
+            .filter(|i| is_sampled(&format!("https://lichess.org/{i:08}"), 0, 10))
+            .count();
+        assert!((900..=1100).contains(&sampled), "{sampled}");
+        assert!(is_sampled("", 20, 10));
+        assert!(!is_sampled("", 21, 10));
+        assert!(is_sampled("https://lichess.org/aaaaaaaa", 1, 1));
+    }
+
     #[test]
     fn test_comment_to_duration() {
         assert_eq!(
@@ -377,7 +1097,9 @@ mod tests {
     }
     #[test]
     fn test_tc_to_duration() {
-        assert_eq!(tc_to_tuple("60+3"), Some(Tc::new((60, 3))))
+        assert_eq!(tc_to_tuple("60+3"), Some(Tc::new((60, 3))));
+        assert_eq!(Tc::new((60, 3)).to_string(), "60+3");
+        assert_eq!(Tc::default().to_string(), "-");
     }
 
     #[test]
@@ -389,8 +1111,107 @@ mod tests {
         g.last_two_comments.push("[%clk 0:01:00]".to_string());
         g.tc = Tc::new((60, 2));
         g.plies = 2;
-        let (_, d) = g.game_duration();
-        assert_eq!(d.unwrap(), Duration::from_secs(4))
+        assert_eq!(g.game_duration().unwrap(), Duration::from_secs(4))
+    }
+
+    #[test]
+    fn test_thinking_times() {
+        let mut game = Game {
+            tc: Tc::new((60, 1)),
+            ..Default::default()
+        };
+        for clock in ["0:01:00", "0:01:00", "0:00:50", "0:00:59", "0:00:41"] {
+            game.plies += 1;
+            game.acc_comment(format!("[%clk {clock}]"));
+        }
+        // white spent 60 + 3 - 41 seconds, black 60 + 2 - 59
+        assert_eq!(
+            game.thinking_times(Duration::ZERO),
+            [Duration::from_secs(22), Duration::from_secs(3)]
+        );
+        assert_eq!(
+            game.thinking_times(Duration::ZERO).iter().sum::<Duration>(),
+            game.game_duration().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_setup_duration() {
+        let mut g = Game {
+            tc: Tc::new((180, 0)),
+            ..Default::default()
+        };
+        g.first_two_clocks.push(Duration::from_secs(175));
+        g.first_two_clocks.push(Duration::from_secs(180));
+        assert_eq!(g.setup_duration(), Duration::from_secs(5));
+        // berserk
+        g.first_two_clocks[1] = Duration::from_secs(90);
+        assert_eq!(g.setup_duration(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_berserk() {
+        let mut game = Game {
+            tc: Tc::new((180, 2)),
+            ..Default::default()
+        };
+        for clock in ["0:03:00", "0:01:29", "0:02:50", "0:01:20"] {
+            game.plies += 1;
+            game.acc_comment(format!("[%clk {clock}]"));
+        }
+        assert_eq!(game.berserks(), [false, true]);
+        // only white gets the increments, black starting from 90 seconds
+        assert_eq!(
+            game.thinking_times(Duration::ZERO),
+            [Duration::from_secs(14), Duration::from_secs(9)]
+        );
+        assert_eq!(game.game_duration().unwrap(), Duration::from_secs(23));
+        // (180 + 40 * 2 + 180 / 2) / 2
+        assert_eq!(game.approximate_time(Duration::ZERO), 175);
+    }
+
+    #[test]
+    fn test_clock_anomaly() {
+        let mut game = Game {
+            tc: Tc::new((60, 1)),
+            ..Default::default()
+        };
+        for clock in [
+            "0:01:00", "0:01:00", "0:00:58", "0:00:55", "0:01:15", "0:00:56",
+        ] {
+            game.plies += 1;
+            game.acc_comment(format!("[%clk {clock}]"));
+        }
+        // only the clock of white rose by more than the increment and a +15s press
+        assert_eq!(game.clock_jumps, [false, true]);
+        assert!(!game.clock_anomaly());
+        game.plies += 1;
+        game.acc_comment("[%clk 0:01:14]".to_string());
+        game.plies += 1;
+        game.acc_comment("[%clk 0:01:30]".to_string());
+        assert!(game.clock_anomaly());
+    }
+
+    #[test]
+    fn test_added_time() {
+        let mut game = Game {
+            tc: Tc::new((60, 1)),
+            ..Default::default()
+        };
+        // black pressed +15s for white, who moved at once
+        for clock in [
+            "0:01:00", "0:01:00", "0:01:16", "0:00:55", "0:01:10", "0:00:50",
+        ] {
+            game.plies += 1;
+            game.acc_comment(format!("[%clk {clock}]"));
+        }
+        assert_eq!(game.added_time, [Duration::ZERO, Duration::from_secs(15)]);
+        // white spent 0 + 7, black 6 + 6, with the increments of the first moves
+        assert_eq!(
+            game.thinking_times(Duration::ZERO),
+            [Duration::from_secs(8), Duration::from_secs(13)]
+        );
+        assert_eq!(game.game_duration().unwrap(), Duration::from_secs(21));
     }
 
     #[test]
@@ -0,0 +1,77 @@
+//! Directory polled for new monthly dumps, processed once fully downloaded
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+const EXTENSION: &str = ".pgn.zst";
+
+pub struct Watcher {
+    dir: PathBuf,
+    /// size of the files not ready yet, at the previous poll
+    sizes: FxHashMap<PathBuf, u64>,
+    done: FxHashSet<PathBuf>,
+}
+
+impl Watcher {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            sizes: FxHashMap::default(),
+            done: FxHashSet::default(),
+        }
+    }
+
+    /// The `.pgn.zst` files not returned yet, whose size did not change since the previous poll
+    /// so are most likely fully downloaded. Sorted by name, which is chronological for the dumps
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let mut ready = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let is_pgn = path.to_str().is_some_and(|p| p.ends_with(EXTENSION));
+            if !is_pgn || self.done.contains(&path) {
+                continue;
+            }
+            let size = entry.metadata()?.len();
+            if self.sizes.insert(path.clone(), size) == Some(size) {
+                ready.push(path);
+            }
+        }
+        ready.sort();
+        for path in &ready {
+            self.sizes.remove(path);
+            self.done.insert(path.clone());
+        }
+        Ok(ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use super::*;
+
+    #[test]
+    fn test_poll() {
+        let dir = std::env::temp_dir().join(format!("watch-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let complete = dir.join("lichess_db_standard_rated_2013-01.pgn.zst");
+        let downloading = dir.join("lichess_db_standard_rated_2013-02.pgn.zst");
+        fs::write(&complete, b"complete").unwrap();
+        fs::write(&downloading, b"down").unwrap();
+        fs::write(dir.join("notes.txt"), b"ignored").unwrap();
+        let mut watcher = Watcher::new(&dir);
+        assert!(watcher.poll().unwrap().is_empty());
+        let mut file = OpenOptions::new().append(true).open(&downloading).unwrap();
+        file.write_all(b"loading").unwrap();
+        assert_eq!(watcher.poll().unwrap(), vec![complete]);
+        assert_eq!(watcher.poll().unwrap(), vec![downloading]);
+        assert!(watcher.poll().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
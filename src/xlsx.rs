@@ -0,0 +1,165 @@
+//! Excel workbook of the results, with a summary sheet then one sheet per perf
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+
+use crate::{
+    columns::{Cell, Column, Ranks, TimeUnit},
+    results::{self, CsvOptions},
+    visitor::{Perf, TimeSpent, TimeSpents},
+};
+
+/// Rows of a sheet, the header included
+const MAX_ROWS: usize = 1_048_576;
+
+// numbers whenever possible, so they can be summed and sorted in Excel
+fn write_duration(
+    sheet: &mut Worksheet,
+    (row, col): (u32, u16),
+    duration: Duration,
+    unit: TimeUnit,
+) -> Result<(), XlsxError> {
+    match unit {
+        TimeUnit::Seconds => sheet.write_number(row, col, duration.as_secs() as f64)?,
+        TimeUnit::Minutes => sheet.write_number(row, col, duration.as_secs_f64() / 60.)?,
+        TimeUnit::Hours => sheet.write_number(row, col, duration.as_secs_f64() / 3600.)?,
+        TimeUnit::Human => sheet.write_string(row, col, unit.display(duration).to_string())?,
+    };
+    Ok(())
+}
+
+fn write_cell(
+    sheet: &mut Worksheet,
+    cell: (u32, u16),
+    column: Column,
+    t: &TimeSpent,
+    unit: TimeUnit,
+    ranks: Option<Ranks>,
+) -> Result<(), XlsxError> {
+    let (row, col) = cell;
+    match column.value(t, ranks) {
+        Some(Cell::Count(n)) => sheet.write_number(row, col, n as f64)?,
+        Some(Cell::Float(x)) => sheet.write_number(row, col, x)?,
+        Some(Cell::Time(time)) => return write_duration(sheet, cell, time, unit),
+        None => return Ok(()),
+    };
+    Ok(())
+}
+
+fn write_header(sheet: &mut Worksheet, headers: &[&str]) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    for (col, header) in headers.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+    }
+    sheet.set_freeze_panes(1, 1)?;
+    Ok(())
+}
+
+fn workbook(users: &[(&String, &TimeSpents)], options: &CsvOptions) -> Result<Workbook, XlsxError> {
+    let ranks = options
+        .columns
+        .iter()
+        .any(|c| c.is_rank())
+        .then(|| results::ranks(users));
+    let mut workbook = Workbook::new();
+    let summary = workbook.add_worksheet().set_name("summary")?;
+    write_header(
+        summary,
+        &["username", "games", "approximate_time", "real_time"],
+    )?;
+    for (i, (username, time_spents)) in users.iter().enumerate() {
+        let row = i as u32 + 1;
+        summary.write_string(row, 0, username.as_str())?;
+        summary.write_number(row, 1, time_spents.total_games() as f64)?;
+        let approximate = Duration::from_secs(time_spents.total_approximate() as u64);
+        write_duration(summary, (row, 2), approximate, options.time_unit)?;
+        write_duration(
+            summary,
+            (row, 3),
+            time_spents.total_exact(),
+            options.time_unit,
+        )?;
+    }
+    for (p, perf) in Perf::ALL.into_iter().enumerate() {
+        let sheet = workbook.add_worksheet().set_name(perf.name())?;
+        let headers: Vec<_> = ["username"]
+            .into_iter()
+            .chain(options.columns.iter().map(|c| c.header()))
+            .collect();
+        write_header(sheet, &headers)?;
+        let played = users
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, t))| !t.get(perf).is_empty());
+        for (row, (i, (username, time_spents))) in played.enumerate() {
+            let row = row as u32 + 1;
+            sheet.write_string(row, 0, username.as_str())?;
+            for (col, column) in options.columns.iter().enumerate() {
+                let ranks = ranks.as_ref().map(|ranks| ranks[i][p]);
+                let cell = (row, col as u16 + 1);
+                write_cell(
+                    sheet,
+                    cell,
+                    *column,
+                    time_spents.get(perf),
+                    options.time_unit,
+                    ranks,
+                )?;
+            }
+        }
+    }
+    Ok(workbook)
+}
+
+/// The users who played a perf are in its sheet, in the given order.
+/// The durations are in `options.time_unit`, unrounded
+pub fn write<'a>(
+    w: &mut impl Write,
+    users: impl IntoIterator<Item = (&'a String, &'a TimeSpents)>,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    let users: Vec<_> = users.into_iter().collect();
+    if users.len() >= MAX_ROWS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "xlsx sheets are limited to {} users, keep fewer with --min-games",
+                MAX_ROWS - 1
+            ),
+        ));
+    }
+    let buffer = workbook(&users, options)
+        .and_then(|mut workbook| workbook.save_to_buffer())
+        .map_err(io::Error::other)?;
+    w.write_all(&buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{results::Delimiter, visitor::Users};
+
+    #[test]
+    fn test_write() {
+        let mut users = Users::default();
+        let mut time_spents = TimeSpents::default();
+        let blitz = time_spents.get_mut(Perf::Blitz);
+        blitz.nb_games = 2;
+        blitz.time_spent_exact = Duration::from_secs(600);
+        blitz.time_spent_approximate = 520;
+        users.insert("alice".to_string(), time_spents);
+        let options = CsvOptions {
+            delimiter: Delimiter::Comma,
+            columns: Column::ALL.to_vec(),
+            time_unit: TimeUnit::Minutes,
+        };
+        let mut w = Vec::new();
+        write(&mut w, &users, &options).unwrap();
+        // a zip archive
+        assert!(w.starts_with(b"PK\x03\x04"));
+    }
+}